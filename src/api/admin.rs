@@ -0,0 +1,401 @@
+use std::str::FromStr;
+
+use actix_web::{delete, get, post, put, web, Either, HttpRequest, HttpResponse};
+use mongodb::bson::oid::ObjectId;
+use serde::Deserialize;
+
+use crate::{
+    models::{
+        init::Tweetbook,
+        messages::Message,
+        moderation::{ModerationAction, ModerationLog},
+        reports::{Report, ReportKind, ReportStatus},
+        stats::PlatformStats,
+        users::{Role, User},
+    },
+    utils::{
+        auth::Authorization,
+        error::UserError,
+        pagination::{Paginated, Pagination},
+    },
+};
+
+#[derive(Deserialize)]
+struct QueueFilter {
+    status: Option<ReportStatus>,
+}
+
+#[derive(Deserialize)]
+struct AuditFilter {
+    target: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ActionInput {
+    action: ModerationAction,
+    note: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct NoteInput {
+    note: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct UserFilter {
+    search: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RoleInput {
+    role: Role,
+    note: Option<String>,
+}
+
+pub fn admin(cfg: &mut web::ServiceConfig) {
+    cfg.service(review_queue)
+        .service(act_on_report)
+        .service(suspend_user)
+        .service(unsuspend_user)
+        .service(audit_log)
+        .service(platform_stats)
+        .service(list_users)
+        .service(change_role)
+        .service(reset_password)
+        .service(delete_tweet);
+}
+
+/// Parses the id of a user an admin acts on, who has to exist and can't
+/// be the admin themselves.
+async fn other_user(
+    db: web::Data<Tweetbook>,
+    admin: ObjectId,
+    user_id: &str,
+) -> Result<ObjectId, UserError> {
+    let user_id = match ObjectId::from_str(user_id) {
+        Ok(user_id) => user_id,
+        Err(_) => return Err(UserError::UserNotExists),
+    };
+    if user_id == admin {
+        return Err(UserError::CannotTargetSelf);
+    }
+
+    match User::exists(db, user_id).await {
+        Ok(true) => Ok(user_id),
+        Ok(false) => Err(UserError::UserNotExists),
+        Err(_) => Err(UserError::InternalServerError),
+    }
+}
+
+#[get("/api/admin/reports")]
+async fn review_queue(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    filter: web::Query<QueueFilter>,
+    page: web::Query<Pagination>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_role(req, Role::Moderator).await;
+    match id_res {
+        Ok(id) => {
+            let status = filter.status.unwrap_or(ReportStatus::Open);
+
+            match Report::get_queue(db, status, id, &page).await {
+                Ok(reports) => Either::Left(HttpResponse::Ok().json(reports)),
+                Err(_) => Either::Right(Err(UserError::InternalServerError)),
+            }
+        }
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[post("/api/admin/reports/{report_id}/action")]
+async fn act_on_report(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<String>,
+    body: web::Json<ActionInput>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_role(req, Role::Moderator).await;
+    match id_res {
+        Ok(id) => match ObjectId::from_str(&path.into_inner()) {
+            Ok(report_id) => match Report::get_report(db.clone(), report_id).await {
+                Ok(Some(report)) => {
+                    let body = body.into_inner();
+
+                    match ModerationLog::act_on_report(db, id, &report, body.action, body.note)
+                        .await
+                    {
+                        Ok(_) => Either::Right(Ok("Report resolved!")),
+                        Err(error) => Either::Right(Err(error)),
+                    }
+                }
+                Ok(None) => Either::Right(Err(UserError::ReportNotExists)),
+                Err(_) => Either::Right(Err(UserError::InternalServerError)),
+            },
+            Err(_) => Either::Right(Err(UserError::ReportNotExists)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+/// Suspends or reinstates a user outside of any report, recording it in
+/// the audit trail when it changed anything. Only users of a lower role
+/// than the moderator can be acted on.
+async fn set_suspended(
+    db: web::Data<Tweetbook>,
+    moderator: ObjectId,
+    user_id: String,
+    suspended: bool,
+    note: Option<String>,
+) -> Result<(), UserError> {
+    let user_id = match other_user(db.clone(), moderator, &user_id).await {
+        Ok(user_id) => user_id,
+        Err(error) => return Err(error),
+    };
+    match User::outranks(db.clone(), moderator, user_id).await {
+        Ok(true) => {}
+        Ok(false) => return Err(UserError::Forbidden),
+        Err(_) => return Err(UserError::InternalServerError),
+    }
+
+    let action = if suspended {
+        ModerationAction::SuspendUser
+    } else {
+        ModerationAction::UnsuspendUser
+    };
+
+    match User::set_suspended(db.clone(), user_id, suspended).await {
+        Ok(true) => {
+            match ModerationLog::record(
+                db,
+                moderator,
+                action,
+                ReportKind::User,
+                user_id,
+                None,
+                note,
+            )
+            .await
+            {
+                Ok(_) => Ok(()),
+                Err(_) => Err(UserError::InternalServerError),
+            }
+        }
+        Ok(false) => Ok(()),
+        Err(_) => Err(UserError::InternalServerError),
+    }
+}
+
+#[post("/api/admin/users/{user_id}/suspend")]
+async fn suspend_user(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<String>,
+    body: Option<web::Json<NoteInput>>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_role(req, Role::Moderator).await;
+    match id_res {
+        Ok(id) => {
+            let note = body.and_then(|body| body.into_inner().note);
+
+            match set_suspended(db, id, path.into_inner(), true, note).await {
+                Ok(_) => Either::Right(Ok("User suspended!")),
+                Err(error) => Either::Right(Err(error)),
+            }
+        }
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[delete("/api/admin/users/{user_id}/suspend")]
+async fn unsuspend_user(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<String>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_role(req, Role::Moderator).await;
+    match id_res {
+        Ok(id) => match set_suspended(db, id, path.into_inner(), false, None).await {
+            Ok(_) => Either::Right(Ok("User reinstated!")),
+            Err(error) => Either::Right(Err(error)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[get("/api/admin/audit")]
+async fn audit_log(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    filter: web::Query<AuditFilter>,
+    page: web::Query<Pagination>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_role(req, Role::Moderator).await;
+    match id_res {
+        Ok(_) => {
+            let target = match &filter.target {
+                Some(target) => match ObjectId::from_str(target) {
+                    Ok(target) => Some(target),
+                    Err(_) => {
+                        let empty = Paginated::<ModerationLog>::new(vec![], 0, &page);
+                        return Either::Left(HttpResponse::Ok().json(empty));
+                    }
+                },
+                None => None,
+            };
+
+            match ModerationLog::get_log(db, target, &page).await {
+                Ok(entries) => Either::Left(HttpResponse::Ok().json(entries)),
+                Err(_) => Either::Right(Err(UserError::InternalServerError)),
+            }
+        }
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[get("/api/admin/stats")]
+async fn platform_stats(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_role(req, Role::Admin).await;
+    match id_res {
+        Ok(_) => match PlatformStats::collect(db).await {
+            Ok(stats) => Either::Left(HttpResponse::Ok().json(stats)),
+            Err(_) => Either::Right(Err(UserError::InternalServerError)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[get("/api/admin/users")]
+async fn list_users(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    filter: web::Query<UserFilter>,
+    page: web::Query<Pagination>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_role(req, Role::Admin).await;
+    match id_res {
+        Ok(_) => match User::admin_search(db, filter.search.as_deref(), &page).await {
+            Ok(users) => Either::Left(HttpResponse::Ok().json(users)),
+            Err(_) => Either::Right(Err(UserError::InternalServerError)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[put("/api/admin/users/{user_id}/role")]
+async fn change_role(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<String>,
+    body: web::Json<RoleInput>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_role(req, Role::Admin).await;
+    match id_res {
+        Ok(id) => match other_user(db.clone(), id, &path.into_inner()).await {
+            Ok(user_id) => match User::set_role(db.clone(), user_id, body.role).await {
+                Ok(_) => {
+                    let note = match &body.note {
+                        Some(note) => format!("{:?}: {}", body.role, note),
+                        None => format!("{:?}", body.role),
+                    };
+                    let recorded = ModerationLog::record(
+                        db,
+                        id,
+                        ModerationAction::ChangeRole,
+                        ReportKind::User,
+                        user_id,
+                        None,
+                        Some(note),
+                    )
+                    .await;
+
+                    match recorded {
+                        Ok(_) => Either::Right(Ok("Role updated!")),
+                        Err(_) => Either::Right(Err(UserError::InternalServerError)),
+                    }
+                }
+                Err(_) => Either::Right(Err(UserError::InternalServerError)),
+            },
+            Err(error) => Either::Right(Err(error)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[post("/api/admin/users/{user_id}/password-reset")]
+async fn reset_password(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<String>,
+    body: Option<web::Json<NoteInput>>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_role(req, Role::Admin).await;
+    match id_res {
+        Ok(id) => match other_user(db.clone(), id, &path.into_inner()).await {
+            Ok(user_id) => match User::require_password_reset(db.clone(), user_id).await {
+                Ok(_) => {
+                    let recorded = ModerationLog::record(
+                        db,
+                        id,
+                        ModerationAction::ResetPassword,
+                        ReportKind::User,
+                        user_id,
+                        None,
+                        body.and_then(|body| body.into_inner().note),
+                    )
+                    .await;
+
+                    match recorded {
+                        Ok(_) => Either::Right(Ok("Password reset required!")),
+                        Err(_) => Either::Right(Err(UserError::InternalServerError)),
+                    }
+                }
+                Err(_) => Either::Right(Err(UserError::InternalServerError)),
+            },
+            Err(error) => Either::Right(Err(error)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+/// Deletes any user's tweet. Like a moderator hiding it, its author can't
+/// restore it.
+#[delete("/api/admin/tweets/{tweet_id}")]
+async fn delete_tweet(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<String>,
+    body: Option<web::Json<NoteInput>>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_role(req, Role::Admin).await;
+    match id_res {
+        Ok(id) => match ObjectId::from_str(&path.into_inner()) {
+            Ok(tweet_id) => match Message::hide(db.clone(), tweet_id).await {
+                Ok(Some(_)) => {
+                    let recorded = ModerationLog::record(
+                        db,
+                        id,
+                        ModerationAction::DeleteTweet,
+                        ReportKind::Tweet,
+                        tweet_id,
+                        None,
+                        body.and_then(|body| body.into_inner().note),
+                    )
+                    .await;
+
+                    match recorded {
+                        Ok(_) => Either::Right(Ok("Tweet deleted!")),
+                        Err(_) => Either::Right(Err(UserError::InternalServerError)),
+                    }
+                }
+                Ok(None) => Either::Right(Err(UserError::TweetNotExists)),
+                Err(_) => Either::Right(Err(UserError::InternalServerError)),
+            },
+            Err(_) => Either::Right(Err(UserError::TweetNotExists)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
@@ -6,16 +6,21 @@ use serde::{Deserialize, Serialize};
 use crate::{
     models::{
         init::Tweetbook,
-        users::{MinUser, User},
+        users::{MinUser, Role, User},
     },
     utils::{auth::Authorization, error::UserError},
 };
 
+const MIN_PASSWORD_CHARS: usize = 8;
+
 #[derive(Clone, Deserialize)]
 pub struct AuthCredentials {
     pub username: Option<String>,
     pub email: String,
     pub password: String,
+    /// Required to sign in after an admin forced a password reset.
+    #[serde(rename = "newPassword")]
+    pub new_password: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -47,7 +52,7 @@ async fn signup(
 
     match user_data {
         Ok(old_users) => {
-            if old_users.len() > 0 {
+            if !old_users.is_empty() {
                 Either::Right(Err(UserError::UserAlreadyExists))
             } else {
                 let inserted = User::add_user(
@@ -56,13 +61,14 @@ async fn signup(
                         username: body.username.clone(),
                         email: body.email.clone(),
                         password: body.password.clone(),
+                        new_password: None,
                     },
                 )
                 .await;
 
                 match inserted {
                     Ok(new_user) => {
-                        let token = Authorization::get_token(new_user.clone());
+                        let token = Authorization::get_token(new_user.clone(), Role::User);
 
                         Either::Left(HttpResponse::Ok().json(AuthResponse {
                             id: new_user.id,
@@ -79,6 +85,28 @@ async fn signup(
     }
 }
 
+/// Sets the `newPassword` a user whose password was reset by an admin has
+/// to sign in with. It can't be the password being replaced.
+async fn reset_password(
+    db: web::Data<Tweetbook>,
+    user: ObjectId,
+    body: &AuthCredentials,
+) -> Result<(), UserError> {
+    match &body.new_password {
+        Some(new_password)
+            if new_password.chars().count() >= MIN_PASSWORD_CHARS
+                && *new_password != body.password =>
+        {
+            match User::set_password(db, user, new_password).await {
+                Ok(_) => Ok(()),
+                Err(_) => Err(UserError::InternalServerError),
+            }
+        }
+        Some(_) => Err(UserError::InvalidPassword),
+        None => Err(UserError::PasswordResetRequired),
+    }
+}
+
 #[post("/api/auth/signin")]
 async fn signin(
     db: web::Data<Tweetbook>,
@@ -94,22 +122,32 @@ async fn signin(
 
     match user_data {
         Ok(mut old_users) => {
-            if old_users.len() > 0 {
+            if !old_users.is_empty() {
                 let user = old_users.remove(0);
                 let matched = verify(body.password.as_str(), user.password.unwrap().as_str());
 
                 match matched {
                     Ok(password_match) => {
-                        if password_match == true {
+                        if password_match && user.suspended_at.is_some() {
+                            Either::Right(Err(UserError::AccountSuspended))
+                        } else if password_match {
+                            if user.password_reset_required {
+                                match reset_password(db, user.id, &body).await {
+                                    Ok(_) => {}
+                                    Err(error) => return Either::Right(Err(error)),
+                                }
+                            }
+
                             let claims = MinUser {
                                 id: user.id,
                                 username: user.username,
-                                email: user.email,
+                                display_name: user.display_name,
                                 bio: user.bio,
                                 profile_img_url: user.profile_img_url,
+                                followed_by_me: None,
                             };
 
-                            let token = Authorization::get_token(claims.clone());
+                            let token = Authorization::get_token(claims.clone(), user.role);
 
                             Either::Left(HttpResponse::Ok().json(AuthResponse {
                                 id: claims.id,
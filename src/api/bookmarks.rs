@@ -0,0 +1,219 @@
+use std::str::FromStr;
+
+use actix_web::{delete, get, patch, post, web, Either, HttpRequest, HttpResponse};
+use mongodb::bson::oid::ObjectId;
+use serde::Deserialize;
+
+use crate::{
+    models::{bookmarks::Bookmark, init::Tweetbook, messages::Message},
+    utils::{auth::Authorization, error::UserError, pagination::Pagination},
+};
+
+#[derive(Deserialize)]
+struct BookmarkInput {
+    folder: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct BookmarkFilter {
+    folder: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct FolderInput {
+    name: String,
+}
+
+pub fn bookmarks(cfg: &mut web::ServiceConfig) {
+    cfg.service(bookmark_tweet)
+        .service(unbookmark_tweet)
+        .service(list_bookmarks)
+        .service(list_folders)
+        .service(create_folder)
+        .service(rename_folder)
+        .service(delete_folder);
+}
+
+/// Parses a folder id and checks it belongs to `user`.
+async fn own_folder(
+    db: web::Data<Tweetbook>,
+    user: ObjectId,
+    folder: &str,
+) -> Result<ObjectId, UserError> {
+    let folder = match ObjectId::from_str(folder) {
+        Ok(folder) => folder,
+        Err(_) => return Err(UserError::BookmarkFolderNotExists),
+    };
+
+    match Bookmark::owns_folder(db, user, folder).await {
+        Ok(true) => Ok(folder),
+        Ok(false) => Err(UserError::BookmarkFolderNotExists),
+        Err(_) => Err(UserError::InternalServerError),
+    }
+}
+
+#[post("/api/user/tweet/{tweet_id}/bookmark")]
+async fn bookmark_tweet(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<String>,
+    body: Option<web::Json<BookmarkInput>>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => match ObjectId::from_str(&path.into_inner()) {
+            Ok(tweet_id) => match Message::get_visible_message(db.clone(), tweet_id, id).await {
+                Ok(Some(_)) => {
+                    let folder = match body.and_then(|body| body.into_inner().folder) {
+                        Some(folder) => match own_folder(db.clone(), id, &folder).await {
+                            Ok(folder) => Some(folder),
+                            Err(error) => return Either::Right(Err(error)),
+                        },
+                        None => None,
+                    };
+
+                    match Bookmark::add(db.clone(), id, tweet_id, folder).await {
+                        Ok(_) => match Message::get_visible_message(db, tweet_id, id).await {
+                            Ok(Some(msg)) => Either::Left(HttpResponse::Ok().json(msg)),
+                            Ok(None) => Either::Right(Err(UserError::TweetNotExists)),
+                            Err(_) => Either::Right(Err(UserError::InternalServerError)),
+                        },
+                        Err(_) => Either::Right(Err(UserError::InternalServerError)),
+                    }
+                }
+                Ok(None) => Either::Right(Err(UserError::TweetNotExists)),
+                Err(_) => Either::Right(Err(UserError::InternalServerError)),
+            },
+            Err(_) => Either::Right(Err(UserError::TweetNotExists)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[delete("/api/user/tweet/{tweet_id}/bookmark")]
+async fn unbookmark_tweet(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<String>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => match ObjectId::from_str(&path.into_inner()) {
+            Ok(tweet_id) => match Bookmark::remove(db.clone(), id, tweet_id).await {
+                Ok(_) => match Message::get_visible_message(db, tweet_id, id).await {
+                    Ok(Some(msg)) => Either::Left(HttpResponse::Ok().json(msg)),
+                    Ok(None) => Either::Right(Ok("Bookmark removed!")),
+                    Err(_) => Either::Right(Err(UserError::InternalServerError)),
+                },
+                Err(_) => Either::Right(Err(UserError::InternalServerError)),
+            },
+            Err(_) => Either::Right(Err(UserError::TweetNotExists)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[get("/api/user/bookmarks")]
+async fn list_bookmarks(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    filter: web::Query<BookmarkFilter>,
+    page: web::Query<Pagination>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => {
+            let folder = match &filter.folder {
+                Some(folder) => match own_folder(db.clone(), id, folder).await {
+                    Ok(folder) => Some(folder),
+                    Err(error) => return Either::Right(Err(error)),
+                },
+                None => None,
+            };
+
+            match Bookmark::get_bookmarks(db, id, folder, &page).await {
+                Ok(bookmarks) => Either::Left(HttpResponse::Ok().json(bookmarks)),
+                Err(_) => Either::Right(Err(UserError::InternalServerError)),
+            }
+        }
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[get("/api/user/bookmarks/folders")]
+async fn list_folders(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => match Bookmark::get_folders(db, id).await {
+            Ok(folders) => Either::Left(HttpResponse::Ok().json(folders)),
+            Err(_) => Either::Right(Err(UserError::InternalServerError)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[post("/api/user/bookmarks/folders")]
+async fn create_folder(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    body: web::Json<FolderInput>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => match Bookmark::parse_folder_name(&body.name) {
+            Some(name) => match Bookmark::create_folder(db, id, name).await {
+                Ok(folder) => Either::Left(HttpResponse::Ok().json(folder)),
+                Err(_) => Either::Right(Err(UserError::InternalServerError)),
+            },
+            None => Either::Right(Err(UserError::InvalidFolderName)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[patch("/api/user/bookmarks/folders/{folder_id}")]
+async fn rename_folder(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<String>,
+    body: web::Json<FolderInput>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => match ObjectId::from_str(&path.into_inner()) {
+            Ok(folder_id) => match Bookmark::parse_folder_name(&body.name) {
+                Some(name) => match Bookmark::rename_folder(db, id, folder_id, name).await {
+                    Ok(Some(folder)) => Either::Left(HttpResponse::Ok().json(folder)),
+                    Ok(None) => Either::Right(Err(UserError::BookmarkFolderNotExists)),
+                    Err(_) => Either::Right(Err(UserError::InternalServerError)),
+                },
+                None => Either::Right(Err(UserError::InvalidFolderName)),
+            },
+            Err(_) => Either::Right(Err(UserError::BookmarkFolderNotExists)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[delete("/api/user/bookmarks/folders/{folder_id}")]
+async fn delete_folder(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<String>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => match ObjectId::from_str(&path.into_inner()) {
+            Ok(folder_id) => match Bookmark::delete_folder(db, id, folder_id).await {
+                Ok(true) => Either::Right(Ok("Folder deleted!")),
+                Ok(false) => Either::Right(Err(UserError::BookmarkFolderNotExists)),
+                Err(_) => Either::Right(Err(UserError::InternalServerError)),
+            },
+            Err(_) => Either::Right(Err(UserError::BookmarkFolderNotExists)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
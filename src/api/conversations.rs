@@ -0,0 +1,271 @@
+use std::str::FromStr;
+
+use actix_web::{delete, get, post, web, Either, HttpRequest, HttpResponse};
+use mongodb::bson::oid::ObjectId;
+use serde::Deserialize;
+
+use crate::{
+    models::{
+        conversations::{Conversation, StoredConversation},
+        direct_messages::{DirectMessage, MAX_DIRECT_MESSAGE_CHARS},
+        init::Tweetbook,
+        users::User,
+    },
+    utils::{auth::Authorization, error::UserError, pagination::Pagination},
+};
+
+#[derive(Deserialize)]
+struct ConversationInput {
+    /// Everyone to talk to, not including the caller.
+    participants: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct DirectMessageInput {
+    text: String,
+}
+
+pub fn conversations(cfg: &mut web::ServiceConfig) {
+    cfg.service(start_conversation)
+        .service(list_conversations)
+        .service(get_conversation)
+        .service(send_message)
+        .service(list_messages)
+        .service(read_conversation)
+        .service(delete_message);
+}
+
+/// Loads the conversation `id` if `user` takes part in it.
+async fn find_conversation(
+    db: web::Data<Tweetbook>,
+    id: String,
+    user: ObjectId,
+) -> Result<StoredConversation, UserError> {
+    match ObjectId::from_str(&id) {
+        Ok(id) => match Conversation::get_for_participant(db, id, user).await {
+            Ok(Some(conversation)) => Ok(conversation),
+            Ok(None) => Err(UserError::ConversationNotExists),
+            Err(_) => Err(UserError::InternalServerError),
+        },
+        Err(_) => Err(UserError::ConversationNotExists),
+    }
+}
+
+#[post("/api/conversations")]
+async fn start_conversation(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    body: web::Json<ConversationInput>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => {
+            let mut participants = vec![id];
+            for participant in &body.participants {
+                match ObjectId::from_str(participant) {
+                    Ok(participant) => {
+                        if !participants.contains(&participant) {
+                            participants.push(participant);
+                        }
+                    }
+                    Err(_) => return Either::Right(Err(UserError::UserNotExists)),
+                }
+            }
+
+            if participants.len() < 2 || participants.len() > Conversation::max_participants() {
+                return Either::Right(Err(UserError::InvalidParticipants));
+            }
+
+            for participant in &participants[1..] {
+                match User::exists(db.clone(), *participant).await {
+                    Ok(true) => {}
+                    Ok(false) => return Either::Right(Err(UserError::UserNotExists)),
+                    Err(_) => return Either::Right(Err(UserError::InternalServerError)),
+                }
+                match User::is_blocked_between(db.clone(), id, *participant).await {
+                    Ok(false) => {}
+                    Ok(true) => return Either::Right(Err(UserError::Blocked)),
+                    Err(_) => return Either::Right(Err(UserError::InternalServerError)),
+                }
+            }
+
+            match Conversation::start(db.clone(), participants).await {
+                Ok(conversation_id) => {
+                    match Conversation::get_conversation(db, conversation_id, id).await {
+                        Ok(Some(conversation)) => {
+                            Either::Left(HttpResponse::Ok().json(conversation))
+                        }
+                        Ok(None) => Either::Right(Err(UserError::ConversationNotExists)),
+                        Err(_) => Either::Right(Err(UserError::InternalServerError)),
+                    }
+                }
+                Err(_) => Either::Right(Err(UserError::InternalServerError)),
+            }
+        }
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[get("/api/conversations")]
+async fn list_conversations(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    page: web::Query<Pagination>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => match Conversation::get_conversations(db, id, &page).await {
+            Ok(conversations) => Either::Left(HttpResponse::Ok().json(conversations)),
+            Err(_) => Either::Right(Err(UserError::InternalServerError)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[get("/api/conversations/{conversation_id}")]
+async fn get_conversation(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<String>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => match find_conversation(db.clone(), path.into_inner(), id).await {
+            Ok(conversation) => {
+                match Conversation::get_conversation(db, conversation.id, id).await {
+                    Ok(Some(conversation)) => Either::Left(HttpResponse::Ok().json(conversation)),
+                    Ok(None) => Either::Right(Err(UserError::ConversationNotExists)),
+                    Err(_) => Either::Right(Err(UserError::InternalServerError)),
+                }
+            }
+            Err(error) => Either::Right(Err(error)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[post("/api/conversations/{conversation_id}/messages")]
+async fn send_message(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<String>,
+    body: web::Json<DirectMessageInput>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => {
+            let text = body.text.trim();
+            if text.is_empty() || text.chars().count() > MAX_DIRECT_MESSAGE_CHARS {
+                return Either::Right(Err(UserError::InvalidDirectMessage));
+            }
+
+            match find_conversation(db.clone(), path.into_inner(), id).await {
+                Ok(conversation) => {
+                    // A block ends a one-to-one conversation; in a group it only
+                    // hides the blocked member's messages from the blocker.
+                    if conversation.participants.len() == 2 {
+                        let other = conversation
+                            .participants
+                            .iter()
+                            .find(|participant| **participant != id)
+                            .copied()
+                            .unwrap_or(id);
+
+                        match User::is_blocked_between(db.clone(), id, other).await {
+                            Ok(false) => {}
+                            Ok(true) => return Either::Right(Err(UserError::Blocked)),
+                            Err(_) => return Either::Right(Err(UserError::InternalServerError)),
+                        }
+                    }
+
+                    match DirectMessage::send(db, &conversation, id, text.to_string()).await {
+                        Ok(Some(message)) => Either::Left(HttpResponse::Ok().json(message)),
+                        Ok(None) => Either::Right(Err(UserError::DirectMessageNotExists)),
+                        Err(_) => Either::Right(Err(UserError::InternalServerError)),
+                    }
+                }
+                Err(error) => Either::Right(Err(error)),
+            }
+        }
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[get("/api/conversations/{conversation_id}/messages")]
+async fn list_messages(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<String>,
+    page: web::Query<Pagination>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => match find_conversation(db.clone(), path.into_inner(), id).await {
+            Ok(conversation) => {
+                match DirectMessage::get_messages(db, conversation.id, id, &page).await {
+                    Ok(messages) => Either::Left(HttpResponse::Ok().json(messages)),
+                    Err(_) => Either::Right(Err(UserError::InternalServerError)),
+                }
+            }
+            Err(error) => Either::Right(Err(error)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[post("/api/conversations/{conversation_id}/read")]
+async fn read_conversation(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<String>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => match find_conversation(db.clone(), path.into_inner(), id).await {
+            Ok(conversation) => {
+                match Conversation::mark_read(db.clone(), conversation.id, id).await {
+                    Ok(_) => match Conversation::get_conversation(db, conversation.id, id).await {
+                        Ok(Some(conversation)) => {
+                            Either::Left(HttpResponse::Ok().json(conversation))
+                        }
+                        Ok(None) => Either::Right(Err(UserError::ConversationNotExists)),
+                        Err(_) => Either::Right(Err(UserError::InternalServerError)),
+                    },
+                    Err(_) => Either::Right(Err(UserError::InternalServerError)),
+                }
+            }
+            Err(error) => Either::Right(Err(error)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+/// Deletes a message for the caller only; other participants still see it.
+#[delete("/api/conversations/{conversation_id}/messages/{message_id}")]
+async fn delete_message(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<(String, String)>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => {
+            let (conversation_id, message_id) = path.into_inner();
+
+            match find_conversation(db.clone(), conversation_id, id).await {
+                Ok(conversation) => match ObjectId::from_str(&message_id) {
+                    Ok(message_id) => {
+                        match DirectMessage::delete_for(db, conversation.id, message_id, id).await {
+                            Ok(true) => Either::Left(HttpResponse::Ok().body("Message deleted!")),
+                            Ok(false) => Either::Right(Err(UserError::DirectMessageNotExists)),
+                            Err(_) => Either::Right(Err(UserError::InternalServerError)),
+                        }
+                    }
+                    Err(_) => Either::Right(Err(UserError::DirectMessageNotExists)),
+                },
+                Err(error) => Either::Right(Err(error)),
+            }
+        }
+        Err(error) => Either::Right(Err(error)),
+    }
+}
@@ -0,0 +1,306 @@
+use std::str::FromStr;
+
+use actix_web::{delete, get, post, put, web, Either, HttpRequest, HttpResponse};
+use chrono::Utc;
+use mongodb::bson::{doc, oid::ObjectId, DateTime, Document};
+use serde::Deserialize;
+
+use crate::{
+    models::{
+        drafts::Draft,
+        init::Tweetbook,
+        media::Media,
+        polls::{NewPoll, PollRequest},
+    },
+    utils::{auth::Authorization, config::env_or, error::UserError, pagination::Pagination},
+};
+
+#[derive(Deserialize)]
+struct DraftInput {
+    text: String,
+    #[serde(rename = "replyTo")]
+    reply_to: Option<String>,
+    /// Ids of images uploaded through `/api/media`.
+    media: Option<Vec<String>>,
+    poll: Option<PollRequest>,
+    /// RFC 3339 time to post at; without it the draft is just saved.
+    #[serde(rename = "publishAt")]
+    publish_at: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ScheduleInput {
+    #[serde(rename = "publishAt")]
+    publish_at: String,
+}
+
+pub fn drafts(cfg: &mut web::ServiceConfig) {
+    cfg.service(create_draft)
+        .service(list_drafts)
+        .service(list_scheduled)
+        .service(get_draft)
+        .service(update_draft)
+        .service(delete_draft)
+        .service(schedule_draft)
+        .service(unschedule_draft)
+        .service(publish_draft);
+}
+
+/// Checks what can be checked before posting time and returns the content
+/// as stored. Whether the replied-to tweet and uploads are still usable is
+/// checked again when the draft is published.
+fn parse_content(body: &DraftInput) -> Result<Document, UserError> {
+    let reply_to = match &body.reply_to {
+        Some(parent_id) => match ObjectId::from_str(parent_id) {
+            Ok(parent_id) => Some(parent_id),
+            Err(_) => return Err(UserError::TweetNotExists),
+        },
+        None => None,
+    };
+    let media = Media::parse_ids(body.media.as_deref().unwrap_or_default())?;
+
+    match &body.poll {
+        Some(_) if !media.is_empty() => return Err(UserError::InvalidPoll),
+        Some(poll) if NewPoll::parse(poll).is_none() => return Err(UserError::InvalidPoll),
+        _ => {}
+    }
+
+    Ok(Draft::content(
+        &body.text,
+        reply_to,
+        &media,
+        body.poll.as_ref(),
+    ))
+}
+
+/// Parses a publish time, which has to be in the future and no more than
+/// `SCHEDULE_MAX_DAYS` (a year by default) away.
+fn parse_publish_at(publish_at: &str) -> Result<DateTime, UserError> {
+    let publish_at = match chrono::DateTime::parse_from_rfc3339(publish_at) {
+        Ok(publish_at) => publish_at.with_timezone(&Utc),
+        Err(_) => return Err(UserError::InvalidSchedule),
+    };
+
+    let now = Utc::now();
+    let latest = now + chrono::Duration::days(env_or("SCHEDULE_MAX_DAYS", 365));
+    if publish_at <= now || publish_at > latest {
+        return Err(UserError::InvalidSchedule);
+    }
+
+    Ok(DateTime::from_millis(publish_at.timestamp_millis()))
+}
+
+fn parse_draft_id(id: &str) -> Result<ObjectId, UserError> {
+    match ObjectId::from_str(id) {
+        Ok(id) => Ok(id),
+        Err(_) => Err(UserError::DraftNotExists),
+    }
+}
+
+#[post("/api/drafts")]
+async fn create_draft(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    body: web::Json<DraftInput>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => {
+            let content = match parse_content(&body) {
+                Ok(content) => content,
+                Err(error) => return Either::Right(Err(error)),
+            };
+            let publish_at = match &body.publish_at {
+                Some(publish_at) => match parse_publish_at(publish_at) {
+                    Ok(publish_at) => Some(publish_at),
+                    Err(error) => return Either::Right(Err(error)),
+                },
+                None => None,
+            };
+
+            match Draft::create(db, id, content, publish_at).await {
+                Ok(draft) => Either::Left(HttpResponse::Ok().json(draft)),
+                Err(_) => Either::Right(Err(UserError::InternalServerError)),
+            }
+        }
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[get("/api/drafts")]
+async fn list_drafts(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    page: web::Query<Pagination>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => match Draft::get_drafts(db, id, false, &page).await {
+            Ok(drafts) => Either::Left(HttpResponse::Ok().json(drafts)),
+            Err(_) => Either::Right(Err(UserError::InternalServerError)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[get("/api/drafts/scheduled")]
+async fn list_scheduled(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    page: web::Query<Pagination>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => match Draft::get_drafts(db, id, true, &page).await {
+            Ok(drafts) => Either::Left(HttpResponse::Ok().json(drafts)),
+            Err(_) => Either::Right(Err(UserError::InternalServerError)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[get("/api/drafts/{draft_id}")]
+async fn get_draft(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<String>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => match parse_draft_id(&path.into_inner()) {
+            Ok(draft_id) => match Draft::get_draft(db, draft_id, id).await {
+                Ok(Some(draft)) => Either::Left(HttpResponse::Ok().json(draft)),
+                Ok(None) => Either::Right(Err(UserError::DraftNotExists)),
+                Err(_) => Either::Right(Err(UserError::InternalServerError)),
+            },
+            Err(error) => Either::Right(Err(error)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[put("/api/drafts/{draft_id}")]
+async fn update_draft(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<String>,
+    body: web::Json<DraftInput>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => match parse_draft_id(&path.into_inner()) {
+            Ok(draft_id) => {
+                let mut set = match parse_content(&body) {
+                    Ok(content) => content,
+                    Err(error) => return Either::Right(Err(error)),
+                };
+                if let Some(publish_at) = &body.publish_at {
+                    match parse_publish_at(publish_at) {
+                        Ok(publish_at) => set.insert("publishAt", publish_at),
+                        Err(error) => return Either::Right(Err(error)),
+                    };
+                }
+
+                match Draft::update(db, draft_id, id, set).await {
+                    Ok(Some(draft)) => Either::Left(HttpResponse::Ok().json(draft)),
+                    Ok(None) => Either::Right(Err(UserError::DraftNotExists)),
+                    Err(_) => Either::Right(Err(UserError::InternalServerError)),
+                }
+            }
+            Err(error) => Either::Right(Err(error)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[delete("/api/drafts/{draft_id}")]
+async fn delete_draft(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<String>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => match parse_draft_id(&path.into_inner()) {
+            Ok(draft_id) => match Draft::delete(db, draft_id, id).await {
+                Ok(true) => Either::Right(Ok("Draft deleted!")),
+                Ok(false) => Either::Right(Err(UserError::DraftNotExists)),
+                Err(_) => Either::Right(Err(UserError::InternalServerError)),
+            },
+            Err(error) => Either::Right(Err(error)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[put("/api/drafts/{draft_id}/schedule")]
+async fn schedule_draft(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<String>,
+    body: web::Json<ScheduleInput>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => match parse_draft_id(&path.into_inner()) {
+            Ok(draft_id) => match parse_publish_at(&body.publish_at) {
+                Ok(publish_at) => {
+                    let set = doc! { "publishAt": publish_at, "failure": null };
+
+                    match Draft::update(db, draft_id, id, set).await {
+                        Ok(Some(draft)) => Either::Left(HttpResponse::Ok().json(draft)),
+                        Ok(None) => Either::Right(Err(UserError::DraftNotExists)),
+                        Err(_) => Either::Right(Err(UserError::InternalServerError)),
+                    }
+                }
+                Err(error) => Either::Right(Err(error)),
+            },
+            Err(error) => Either::Right(Err(error)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[delete("/api/drafts/{draft_id}/schedule")]
+async fn unschedule_draft(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<String>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => match parse_draft_id(&path.into_inner()) {
+            Ok(draft_id) => match Draft::update(db, draft_id, id, doc! { "publishAt": null }).await
+            {
+                Ok(Some(draft)) => Either::Left(HttpResponse::Ok().json(draft)),
+                Ok(None) => Either::Right(Err(UserError::DraftNotExists)),
+                Err(_) => Either::Right(Err(UserError::InternalServerError)),
+            },
+            Err(error) => Either::Right(Err(error)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[post("/api/drafts/{draft_id}/publish")]
+async fn publish_draft(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<String>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => match parse_draft_id(&path.into_inner()) {
+            Ok(draft_id) => match Draft::claim(db.clone(), draft_id, id).await {
+                Ok(Some(draft)) => match Draft::publish(db, draft).await {
+                    Ok(msg) => Either::Left(HttpResponse::Ok().json(msg)),
+                    Err(error) => Either::Right(Err(error)),
+                },
+                Ok(None) => Either::Right(Err(UserError::DraftNotExists)),
+                Err(_) => Either::Right(Err(UserError::InternalServerError)),
+            },
+            Err(error) => Either::Right(Err(error)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
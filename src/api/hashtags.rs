@@ -0,0 +1,31 @@
+use actix_web::{get, web, Either, HttpRequest, HttpResponse};
+
+use crate::{
+    models::{init::Tweetbook, messages::Message},
+    utils::{auth::Authorization, error::UserError, pagination::Pagination},
+};
+
+pub fn hashtags(cfg: &mut web::ServiceConfig) {
+    cfg.service(hashtag_tweets);
+}
+
+#[get("/api/hashtags/{tag}/tweets")]
+async fn hashtag_tweets(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<String>,
+    page: web::Query<Pagination>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => {
+            let messages = Message::get_hashtag_messages(db, path.into_inner(), id, &page).await;
+
+            match messages {
+                Ok(msgs) => Either::Left(HttpResponse::Ok().json(msgs)),
+                Err(_) => Either::Right(Err(UserError::InternalServerError)),
+            }
+        }
+        Err(error) => Either::Right(Err(error)),
+    }
+}
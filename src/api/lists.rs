@@ -0,0 +1,343 @@
+use std::str::FromStr;
+
+use actix_web::{delete, get, patch, post, web, Either, HttpRequest, HttpResponse};
+use mongodb::bson::{doc, oid::ObjectId, Document};
+use serde::Deserialize;
+
+use crate::{
+    models::{
+        init::Tweetbook,
+        lists::{StoredList, UserList},
+        users::User,
+    },
+    utils::{auth::Authorization, error::UserError, pagination::Pagination},
+};
+
+#[derive(Deserialize)]
+struct ListInput {
+    name: String,
+    description: Option<String>,
+    private: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct ListUpdate {
+    name: Option<String>,
+    description: Option<String>,
+    private: Option<bool>,
+}
+
+pub fn lists(cfg: &mut web::ServiceConfig) {
+    cfg.service(create_list)
+        .service(my_lists)
+        .service(get_list)
+        .service(update_list)
+        .service(delete_list)
+        .service(list_members)
+        .service(add_member)
+        .service(remove_member)
+        .service(subscribe_list)
+        .service(unsubscribe_list)
+        .service(list_timeline);
+}
+
+/// Loads the list `id` if `user` may see it.
+async fn find_list(
+    db: web::Data<Tweetbook>,
+    id: String,
+    user: ObjectId,
+) -> Result<StoredList, UserError> {
+    match ObjectId::from_str(&id) {
+        Ok(id) => match UserList::get_visible(db, id, user).await {
+            Ok(Some(list)) => Ok(list),
+            Ok(None) => Err(UserError::ListNotExists),
+            Err(_) => Err(UserError::InternalServerError),
+        },
+        Err(_) => Err(UserError::ListNotExists),
+    }
+}
+
+/// Loads the list `id` if `user` owns it.
+async fn find_own_list(
+    db: web::Data<Tweetbook>,
+    id: String,
+    user: ObjectId,
+) -> Result<StoredList, UserError> {
+    match find_list(db, id, user).await {
+        Ok(list) if list.owner == user => Ok(list),
+        Ok(_) => Err(UserError::NotListOwner),
+        Err(error) => Err(error),
+    }
+}
+
+async fn list_response(
+    db: web::Data<Tweetbook>,
+    id: ObjectId,
+    viewer: ObjectId,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    match UserList::get_list(db, id, viewer).await {
+        Ok(Some(list)) => Either::Left(HttpResponse::Ok().json(list)),
+        Ok(None) => Either::Right(Err(UserError::ListNotExists)),
+        Err(_) => Either::Right(Err(UserError::InternalServerError)),
+    }
+}
+
+#[post("/api/lists")]
+async fn create_list(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    body: web::Json<ListInput>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => {
+            let name = match UserList::parse_name(&body.name) {
+                Some(name) => name,
+                None => return Either::Right(Err(UserError::InvalidList)),
+            };
+            let description =
+                match UserList::parse_description(body.description.as_deref().unwrap_or("")) {
+                    Some(description) => description,
+                    None => return Either::Right(Err(UserError::InvalidList)),
+                };
+
+            let private = body.private.unwrap_or(false);
+            match UserList::create(db.clone(), id, name, description, private).await {
+                Ok(list_id) => list_response(db, list_id, id).await,
+                Err(_) => Either::Right(Err(UserError::InternalServerError)),
+            }
+        }
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[get("/api/lists")]
+async fn my_lists(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    page: web::Query<Pagination>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => match UserList::get_lists(db, id, &page).await {
+            Ok(lists) => Either::Left(HttpResponse::Ok().json(lists)),
+            Err(_) => Either::Right(Err(UserError::InternalServerError)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[get("/api/lists/{list_id}")]
+async fn get_list(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<String>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => match find_list(db.clone(), path.into_inner(), id).await {
+            Ok(list) => list_response(db, list.id, id).await,
+            Err(error) => Either::Right(Err(error)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[patch("/api/lists/{list_id}")]
+async fn update_list(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<String>,
+    body: web::Json<ListUpdate>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => match find_own_list(db.clone(), path.into_inner(), id).await {
+            Ok(list) => {
+                let mut set = Document::new();
+                if let Some(name) = &body.name {
+                    match UserList::parse_name(name) {
+                        Some(name) => set.insert("name", name),
+                        None => return Either::Right(Err(UserError::InvalidList)),
+                    };
+                }
+                if let Some(description) = &body.description {
+                    match UserList::parse_description(description) {
+                        Some(description) => set.insert("description", description),
+                        None => return Either::Right(Err(UserError::InvalidList)),
+                    };
+                }
+                if let Some(private) = body.private {
+                    set.insert("private", private);
+                }
+
+                match UserList::update(db.clone(), list.id, id, set).await {
+                    Ok(true) => list_response(db, list.id, id).await,
+                    Ok(false) => Either::Right(Err(UserError::ListNotExists)),
+                    Err(_) => Either::Right(Err(UserError::InternalServerError)),
+                }
+            }
+            Err(error) => Either::Right(Err(error)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[delete("/api/lists/{list_id}")]
+async fn delete_list(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<String>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => match find_own_list(db.clone(), path.into_inner(), id).await {
+            Ok(list) => match UserList::delete(db, list.id, id).await {
+                Ok(true) => Either::Right(Ok("List deleted!")),
+                Ok(false) => Either::Right(Err(UserError::ListNotExists)),
+                Err(_) => Either::Right(Err(UserError::InternalServerError)),
+            },
+            Err(error) => Either::Right(Err(error)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[get("/api/lists/{list_id}/members")]
+async fn list_members(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<String>,
+    page: web::Query<Pagination>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => match find_list(db.clone(), path.into_inner(), id).await {
+            Ok(list) => match UserList::get_members(db, list.id, id, &page).await {
+                Ok(members) => Either::Left(HttpResponse::Ok().json(members)),
+                Err(_) => Either::Right(Err(UserError::InternalServerError)),
+            },
+            Err(error) => Either::Right(Err(error)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[post("/api/lists/{list_id}/members/{user_id}")]
+async fn add_member(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<(String, String)>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    let (list_id, user_id) = path.into_inner();
+    match id_res {
+        Ok(id) => match find_own_list(db.clone(), list_id, id).await {
+            Ok(list) => match ObjectId::from_str(&user_id) {
+                Ok(member) => {
+                    match User::exists(db.clone(), member).await {
+                        Ok(true) => {}
+                        Ok(false) => return Either::Right(Err(UserError::UserNotExists)),
+                        Err(_) => return Either::Right(Err(UserError::InternalServerError)),
+                    }
+                    match User::is_blocked_between(db.clone(), id, member).await {
+                        Ok(false) => {}
+                        Ok(true) => return Either::Right(Err(UserError::Blocked)),
+                        Err(_) => return Either::Right(Err(UserError::InternalServerError)),
+                    }
+
+                    match UserList::add_member(db.clone(), list.id, member).await {
+                        Ok(true) => list_response(db, list.id, id).await,
+                        Ok(false) => Either::Right(Err(UserError::ListFull)),
+                        Err(_) => Either::Right(Err(UserError::InternalServerError)),
+                    }
+                }
+                Err(_) => Either::Right(Err(UserError::UserNotExists)),
+            },
+            Err(error) => Either::Right(Err(error)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[delete("/api/lists/{list_id}/members/{user_id}")]
+async fn remove_member(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<(String, String)>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    let (list_id, user_id) = path.into_inner();
+    match id_res {
+        Ok(id) => match find_own_list(db.clone(), list_id, id).await {
+            Ok(list) => match ObjectId::from_str(&user_id) {
+                Ok(member) => match UserList::remove_member(db.clone(), list.id, member).await {
+                    Ok(_) => list_response(db, list.id, id).await,
+                    Err(_) => Either::Right(Err(UserError::InternalServerError)),
+                },
+                Err(_) => Either::Right(Err(UserError::UserNotExists)),
+            },
+            Err(error) => Either::Right(Err(error)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[post("/api/lists/{list_id}/subscribe")]
+async fn subscribe_list(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<String>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => match find_list(db.clone(), path.into_inner(), id).await {
+            Ok(list) if list.owner == id => Either::Right(Err(UserError::CannotTargetSelf)),
+            Ok(list) => match UserList::subscribe(db.clone(), list.id, id).await {
+                Ok(_) => list_response(db, list.id, id).await,
+                Err(_) => Either::Right(Err(UserError::InternalServerError)),
+            },
+            Err(error) => Either::Right(Err(error)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[delete("/api/lists/{list_id}/subscribe")]
+async fn unsubscribe_list(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<String>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => match ObjectId::from_str(&path.into_inner()) {
+            Ok(list_id) => match UserList::unsubscribe(db, list_id, id).await {
+                Ok(_) => Either::Right(Ok("Unsubscribed!")),
+                Err(_) => Either::Right(Err(UserError::InternalServerError)),
+            },
+            Err(_) => Either::Right(Err(UserError::ListNotExists)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[get("/api/lists/{list_id}/timeline")]
+async fn list_timeline(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<String>,
+    page: web::Query<Pagination>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => match find_list(db.clone(), path.into_inner(), id).await {
+            Ok(list) => match UserList::get_timeline(db, &list, id, &page).await {
+                Ok(tweets) => Either::Left(HttpResponse::Ok().json(tweets)),
+                Err(_) => Either::Right(Err(UserError::InternalServerError)),
+            },
+            Err(error) => Either::Right(Err(error)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
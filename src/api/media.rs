@@ -0,0 +1,119 @@
+use actix_files::Files;
+use actix_multipart::Multipart;
+use actix_web::{post, web, Either, HttpRequest, HttpResponse};
+use futures::StreamExt;
+use mongodb::bson::{doc, oid::ObjectId};
+
+use crate::{
+    models::{
+        init::Tweetbook,
+        media::{Media, MediaPurpose},
+        users::User,
+    },
+    storage::{
+        local::{LocalStorage, MEDIA_ROUTE},
+        Storage,
+    },
+    utils::{
+        auth::Authorization,
+        error::UserError,
+        images::{self, max_upload_bytes},
+    },
+};
+
+pub fn media(cfg: &mut web::ServiceConfig) {
+    cfg.service(upload_media).service(upload_avatar);
+
+    if Storage::is_local() {
+        cfg.service(Files::new(MEDIA_ROUTE, LocalStorage::dir()));
+    }
+}
+
+/// Reads the `file` field of a multipart upload, giving up as soon as it
+/// grows past `MEDIA_MAX_BYTES` instead of buffering the whole body.
+async fn read_upload(mut payload: Multipart) -> Result<Vec<u8>, UserError> {
+    let limit = max_upload_bytes();
+
+    while let Some(field) = payload.next().await {
+        let mut field = match field {
+            Ok(field) => field,
+            Err(_) => return Err(UserError::InvalidMedia),
+        };
+        if field.name() != "file" {
+            continue;
+        }
+
+        let mut bytes = vec![];
+        while let Some(chunk) = field.next().await {
+            match chunk {
+                Ok(chunk) if bytes.len() + chunk.len() <= limit => bytes.extend_from_slice(&chunk),
+                Ok(_) => return Err(UserError::MediaTooLarge),
+                Err(_) => return Err(UserError::InvalidMedia),
+            }
+        }
+        return Ok(bytes);
+    }
+
+    Err(UserError::InvalidMedia)
+}
+
+/// Decodes, re-encodes and stores an upload, off the async workers.
+async fn store_upload(
+    db: web::Data<Tweetbook>,
+    owner: ObjectId,
+    payload: Multipart,
+    purpose: MediaPurpose,
+) -> Result<Media, UserError> {
+    let bytes = read_upload(payload).await?;
+
+    let image = match web::block(move || images::process(&bytes)).await {
+        Ok(processed) => processed?,
+        Err(_) => return Err(UserError::InternalServerError),
+    };
+
+    Media::store(db, owner, image, purpose).await
+}
+
+#[post("/api/media")]
+async fn upload_media(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    payload: Multipart,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => match store_upload(db, id, payload, MediaPurpose::Tweet).await {
+            Ok(media) => Either::Left(HttpResponse::Ok().json(media)),
+            Err(error) => Either::Right(Err(error)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[post("/api/user/avatar")]
+async fn upload_avatar(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    payload: Multipart,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => match store_upload(db.clone(), id, payload, MediaPurpose::Avatar).await {
+            Ok(media) => {
+                let user = User::update_user(
+                    db,
+                    id.to_string(),
+                    doc! { "$set": { "profileImgUrl": media.thumbnail_url } },
+                )
+                .await;
+
+                match user {
+                    Ok(user) => Either::Left(HttpResponse::Ok().json(user)),
+                    Err(_) => Either::Right(Err(UserError::InternalServerError)),
+                }
+            }
+            Err(error) => Either::Right(Err(error)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
@@ -1,17 +1,43 @@
 use std::str::FromStr;
 
-use actix_web::{delete, get, post, web, Either, HttpRequest, HttpResponse};
-use mongodb::bson::{doc, oid::ObjectId};
+use actix_web::{delete, get, patch, post, web, Either, HttpRequest, HttpResponse};
+use mongodb::bson::{doc, oid::ObjectId, DateTime};
 use serde::Deserialize;
 
 use crate::{
-    models::{init::Tweetbook, messages::Message},
-    utils::{auth::Authorization, error::UserError},
+    filters::Submission,
+    models::{
+        init::Tweetbook,
+        media::Media,
+        messages::Message,
+        notifications::{Notification, NotificationKind},
+        polls::{Poll, PollRequest},
+        spam::{Activity, ActivityKind},
+        users::User,
+    },
+    utils::{auth::Authorization, error::UserError, pagination::Pagination},
 };
 
 #[derive(Clone, Deserialize)]
 struct MessageInput {
     text: String,
+    #[serde(rename = "replyTo")]
+    reply_to: Option<String>,
+    /// Ids of images uploaded through `/api/media`.
+    media: Option<Vec<String>>,
+    poll: Option<PollRequest>,
+}
+
+/// Only the text of a tweet can be edited.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EditInput {
+    text: String,
+}
+
+#[derive(Deserialize)]
+struct VoteInput {
+    option: usize,
 }
 
 pub fn messages(cfg: &mut web::ServiceConfig) {
@@ -20,7 +46,15 @@ pub fn messages(cfg: &mut web::ServiceConfig) {
         .service(like_tweet)
         .service(unlike_tweet)
         .service(get_tweet)
-        .service(delete_tweet);
+        .service(tweet_likes)
+        .service(edit_tweet)
+        .service(tweet_history)
+        .service(delete_tweet)
+        .service(restore_tweet)
+        .service(vote_poll)
+        .service(pin_tweet)
+        .service(unpin_tweet)
+        .service(mentions);
 }
 
 #[get("/api/tweets")]
@@ -30,15 +64,15 @@ async fn all_tweets(
 ) -> Either<HttpResponse, Result<&'static str, UserError>> {
     let id_res = Authorization::verify_request(req).await;
     match id_res {
-        Ok(_) => {
-            let messages = Message::get_message_by_query::<Message>(db, None).await;
+        Ok(id) => {
+            let messages = Message::get_timeline_messages(db, id).await;
 
             match messages {
                 Ok(msgs) => Either::Left(HttpResponse::Ok().json(msgs)),
                 Err(_) => Either::Right(Err(UserError::InternalServerError)),
             }
         }
-        Err(_) => Either::Right(Err(UserError::Unauthorised)),
+        Err(error) => Either::Right(Err(error)),
     }
 }
 
@@ -51,14 +85,34 @@ async fn post_tweet(
     let id_res = Authorization::verify_request(req).await;
     match id_res {
         Ok(id) => {
-            let message = Message::insert_message(db, body.text.to_owned(), id.to_string()).await;
+            let reply_to = match &body.reply_to {
+                Some(parent_id) => match ObjectId::from_str(parent_id) {
+                    Ok(parent_id) => Some(parent_id),
+                    Err(_) => return Either::Right(Err(UserError::TweetNotExists)),
+                },
+                None => None,
+            };
+            let media = match Media::parse_ids(body.media.as_deref().unwrap_or_default()) {
+                Ok(media) => media,
+                Err(error) => return Either::Right(Err(error)),
+            };
+
+            let message = Message::publish(
+                db,
+                id,
+                body.text.to_owned(),
+                reply_to,
+                &media,
+                body.poll.as_ref(),
+            )
+            .await;
 
             match message {
                 Ok(msg) => Either::Left(HttpResponse::Ok().json(msg)),
-                Err(_) => Either::Right(Err(UserError::InternalServerError)),
+                Err(error) => Either::Right(Err(error)),
             }
         }
-        Err(_) => Either::Right(Err(UserError::Unauthorised)),
+        Err(error) => Either::Right(Err(error)),
     }
 }
 
@@ -70,21 +124,45 @@ async fn like_tweet(
 ) -> Either<HttpResponse, Result<&'static str, UserError>> {
     let id_res = Authorization::verify_request(req).await;
     match id_res {
-        Ok(id) => {
-            let tweet_id = path.into_inner();
-            let message = Message::update_message(
-                db,
-                tweet_id,
-                doc! { "$addToSet": { "likes": { "$each": vec![id]}  }},
-            )
-            .await;
+        Ok(id) => match ObjectId::from_str(&path.into_inner()) {
+            Ok(tweet_id) => match Message::get_visible_message(db.clone(), tweet_id, id).await {
+                Ok(Some(_)) => {
+                    match Activity::track(db.clone(), id, ActivityKind::Like, None).await {
+                        Ok(_) => {}
+                        Err(error) => return Either::Right(Err(error)),
+                    }
 
-            match message {
-                Ok(msg) => Either::Left(HttpResponse::Ok().json(msg)),
+                    let message = Message::update_message(
+                        db.clone(),
+                        tweet_id.to_string(),
+                        doc! { "$addToSet": { "likes": { "$each": vec![id]}  }},
+                        id,
+                    )
+                    .await;
+
+                    match message {
+                        Ok(msg) => {
+                            if let Some(author) = &msg.user {
+                                let _ = Notification::notify(
+                                    db,
+                                    author.id,
+                                    id,
+                                    NotificationKind::Like,
+                                    Some(msg.id),
+                                )
+                                .await;
+                            }
+                            Either::Left(HttpResponse::Ok().json(msg))
+                        }
+                        Err(_) => Either::Right(Err(UserError::InternalServerError)),
+                    }
+                }
+                Ok(None) => Either::Right(Err(UserError::TweetNotExists)),
                 Err(_) => Either::Right(Err(UserError::InternalServerError)),
-            }
-        }
-        Err(_) => Either::Right(Err(UserError::Unauthorised)),
+            },
+            Err(_) => Either::Right(Err(UserError::TweetNotExists)),
+        },
+        Err(error) => Either::Right(Err(error)),
     }
 }
 
@@ -96,21 +174,117 @@ async fn unlike_tweet(
 ) -> Either<HttpResponse, Result<&'static str, UserError>> {
     let id_res = Authorization::verify_request(req).await;
     match id_res {
-        Ok(id) => {
-            let tweet_id = path.into_inner();
-            let message = Message::update_message(
-                db,
-                tweet_id,
-                doc! { "$pull": { "likes": { "$in": vec![id]} }},
-            )
-            .await;
+        Ok(id) => match ObjectId::from_str(&path.into_inner()) {
+            Ok(tweet_id) => match Message::get_visible_message(db.clone(), tweet_id, id).await {
+                Ok(Some(_)) => {
+                    let message = Message::update_message(
+                        db,
+                        tweet_id.to_string(),
+                        doc! { "$pull": { "likes": { "$in": vec![id]} }},
+                        id,
+                    )
+                    .await;
 
-            match message {
-                Ok(msg) => Either::Left(HttpResponse::Ok().json(msg)),
+                    match message {
+                        Ok(msg) => Either::Left(HttpResponse::Ok().json(msg)),
+                        Err(_) => Either::Right(Err(UserError::InternalServerError)),
+                    }
+                }
+                Ok(None) => Either::Right(Err(UserError::TweetNotExists)),
                 Err(_) => Either::Right(Err(UserError::InternalServerError)),
-            }
-        }
-        Err(_) => Either::Right(Err(UserError::Unauthorised)),
+            },
+            Err(_) => Either::Right(Err(UserError::TweetNotExists)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[post("/api/user/tweet/{tweet_id}/vote")]
+async fn vote_poll(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<String>,
+    body: web::Json<VoteInput>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => match ObjectId::from_str(&path.into_inner()) {
+            Ok(tweet_id) => match Message::get_visible_message(db.clone(), tweet_id, id).await {
+                Ok(Some(msg)) => {
+                    match &msg.poll {
+                        None => return Either::Right(Err(UserError::PollNotExists)),
+                        Some(poll) if poll.closed => {
+                            return Either::Right(Err(UserError::PollClosed))
+                        }
+                        Some(poll) if poll.my_vote.is_some() => {
+                            return Either::Right(Err(UserError::AlreadyVoted))
+                        }
+                        Some(poll) if body.option >= poll.options.len() => {
+                            return Either::Right(Err(UserError::InvalidPollOption))
+                        }
+                        Some(_) => {}
+                    }
+
+                    match Poll::vote(db.clone(), tweet_id, id, body.option).await {
+                        Ok(true) => match Message::get_visible_message(db, tweet_id, id).await {
+                            Ok(Some(msg)) => Either::Left(HttpResponse::Ok().json(msg)),
+                            Ok(None) => Either::Right(Err(UserError::TweetNotExists)),
+                            Err(_) => Either::Right(Err(UserError::InternalServerError)),
+                        },
+                        // Lost a race with another vote or the poll closing.
+                        Ok(false) => Either::Right(Err(UserError::AlreadyVoted)),
+                        Err(_) => Either::Right(Err(UserError::InternalServerError)),
+                    }
+                }
+                Ok(None) => Either::Right(Err(UserError::TweetNotExists)),
+                Err(_) => Either::Right(Err(UserError::InternalServerError)),
+            },
+            Err(_) => Either::Right(Err(UserError::TweetNotExists)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[post("/api/user/tweet/{tweet_id}/pin")]
+async fn pin_tweet(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<String>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => match ObjectId::from_str(&path.into_inner()) {
+            Ok(tweet_id) => match Message::get_visible_message(db.clone(), tweet_id, id).await {
+                Ok(Some(msg)) if !msg.is_mine => Either::Right(Err(UserError::NotTweetOwner)),
+                Ok(Some(msg)) => match User::pin_tweet(db, id, msg.id).await {
+                    Ok(_) => Either::Left(HttpResponse::Ok().json(msg)),
+                    Err(_) => Either::Right(Err(UserError::InternalServerError)),
+                },
+                Ok(None) => Either::Right(Err(UserError::TweetNotExists)),
+                Err(_) => Either::Right(Err(UserError::InternalServerError)),
+            },
+            Err(_) => Either::Right(Err(UserError::TweetNotExists)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[delete("/api/user/tweet/{tweet_id}/pin")]
+async fn unpin_tweet(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<String>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => match ObjectId::from_str(&path.into_inner()) {
+            Ok(tweet_id) => match User::unpin_tweet(db, id, tweet_id).await {
+                Ok(_) => Either::Right(Ok("Tweet unpinned!")),
+                Err(_) => Either::Right(Err(UserError::InternalServerError)),
+            },
+            Err(_) => Either::Right(Err(UserError::TweetNotExists)),
+        },
+        Err(error) => Either::Right(Err(error)),
     }
 }
 
@@ -122,26 +296,128 @@ async fn get_tweet(
 ) -> Either<HttpResponse, Result<&'static str, UserError>> {
     let id_res = Authorization::verify_request(req).await;
     match id_res {
-        Ok(_id) => {
-            let tweet_id = path.into_inner();
-            let message = Message::get_message_by_query::<Message>(
-                db,
-                Some(doc! {
-                    "$match": {
-                        "_id": ObjectId::from_str(&tweet_id).unwrap()
+        Ok(id) => match ObjectId::from_str(&path.into_inner()) {
+            Ok(tweet_id) => {
+                let message = Message::get_visible_message(db, tweet_id, id).await;
+
+                match message {
+                    Ok(Some(msg)) => Either::Left(HttpResponse::Ok().json(msg)),
+                    Ok(None) => Either::Right(Err(UserError::TweetNotExists)),
+                    Err(_) => Either::Right(Err(UserError::InternalServerError)),
+                }
+            }
+            Err(_) => Either::Right(Err(UserError::TweetNotExists)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[get("/api/user/tweet/{tweet_id}/likes")]
+async fn tweet_likes(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<String>,
+    page: web::Query<Pagination>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => match ObjectId::from_str(&path.into_inner()) {
+            Ok(tweet_id) => match Message::get_visible_message(db.clone(), tweet_id, id).await {
+                Ok(Some(_)) => {
+                    let likes = Message::get_message_likes(db, tweet_id, id, &page).await;
+
+                    match likes {
+                        Ok(users) => Either::Left(HttpResponse::Ok().json(users)),
+                        Err(_) => Either::Right(Err(UserError::InternalServerError)),
                     }
-                }),
-            )
-            .await;
+                }
+                Ok(None) => Either::Right(Err(UserError::TweetNotExists)),
+                Err(_) => Either::Right(Err(UserError::InternalServerError)),
+            },
+            Err(_) => Either::Right(Err(UserError::TweetNotExists)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
 
-            println!("{:?}", message);
+#[patch("/api/user/tweet/{tweet_id}")]
+async fn edit_tweet(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<String>,
+    body: web::Json<EditInput>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => match ObjectId::from_str(&path.into_inner()) {
+            Ok(tweet_id) => match Message::get_visible_message(db.clone(), tweet_id, id).await {
+                Ok(Some(msg)) if !msg.is_mine => Either::Right(Err(UserError::NotTweetOwner)),
+                Ok(Some(msg))
+                    if msg.created_at.timestamp_millis() + Message::edit_window_millis()
+                        < DateTime::now().timestamp_millis() =>
+                {
+                    Either::Right(Err(UserError::EditWindowExpired))
+                }
+                Ok(Some(msg)) => {
+                    let submission = Submission {
+                        author: id,
+                        text: &body.text,
+                        tweet: Some(tweet_id),
+                        reply_to: msg.reply_to.map(|parent| parent.id),
+                    };
+                    let screening = match db.filters.screen(db.clone(), &submission).await {
+                        Ok(screening) => screening,
+                        Err(error) => return Either::Right(Err(error)),
+                    };
 
-            match message {
-                Ok(mut msg) => Either::Left(HttpResponse::Ok().json(msg.remove(0))),
+                    let message = Message::edit_message(
+                        db,
+                        tweet_id,
+                        body.text.to_owned(),
+                        id,
+                        &screening,
+                        &msg.entities.mentions,
+                    )
+                    .await;
+
+                    match message {
+                        Ok(msg) => Either::Left(HttpResponse::Ok().json(msg)),
+                        Err(_) => Either::Right(Err(UserError::InternalServerError)),
+                    }
+                }
+                Ok(None) => Either::Right(Err(UserError::TweetNotExists)),
                 Err(_) => Either::Right(Err(UserError::InternalServerError)),
-            }
-        }
-        Err(_) => Either::Right(Err(UserError::Unauthorised)),
+            },
+            Err(_) => Either::Right(Err(UserError::TweetNotExists)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[get("/api/user/tweet/{tweet_id}/history")]
+async fn tweet_history(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<String>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => match ObjectId::from_str(&path.into_inner()) {
+            Ok(tweet_id) => match Message::get_visible_message(db.clone(), tweet_id, id).await {
+                Ok(Some(_)) => {
+                    let history = Message::get_message_history(db, tweet_id).await;
+
+                    match history {
+                        Ok(versions) => Either::Left(HttpResponse::Ok().json(versions)),
+                        Err(_) => Either::Right(Err(UserError::InternalServerError)),
+                    }
+                }
+                Ok(None) => Either::Right(Err(UserError::TweetNotExists)),
+                Err(_) => Either::Right(Err(UserError::InternalServerError)),
+            },
+            Err(_) => Either::Right(Err(UserError::TweetNotExists)),
+        },
+        Err(error) => Either::Right(Err(error)),
     }
 }
 
@@ -160,10 +436,55 @@ async fn delete_tweet(
             println!("{:?}", message);
 
             match message {
-                Ok(_) => Either::Left(HttpResponse::Ok().body(format!("Tweet deleted!"))),
+                Ok(true) => Either::Left(HttpResponse::Ok().body("Tweet deleted!")),
+                Ok(false) => Either::Right(Err(UserError::TweetNotExists)),
+                Err(_) => Either::Right(Err(UserError::InternalServerError)),
+            }
+        }
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[post("/api/user/tweet/{tweet_id}/restore")]
+async fn restore_tweet(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<String>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => match ObjectId::from_str(&path.into_inner()) {
+            Ok(tweet_id) => {
+                let message = Message::restore_message(db, tweet_id, id).await;
+
+                match message {
+                    Ok(true) => Either::Left(HttpResponse::Ok().body("Tweet restored!")),
+                    Ok(false) => Either::Right(Err(UserError::UndoWindowExpired)),
+                    Err(_) => Either::Right(Err(UserError::InternalServerError)),
+                }
+            }
+            Err(_) => Either::Right(Err(UserError::TweetNotExists)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[get("/api/user/mentions")]
+async fn mentions(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    page: web::Query<Pagination>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => {
+            let messages = Message::get_mention_messages(db, id, &page).await;
+
+            match messages {
+                Ok(msgs) => Either::Left(HttpResponse::Ok().json(msgs)),
                 Err(_) => Either::Right(Err(UserError::InternalServerError)),
             }
         }
-        Err(_) => Either::Right(Err(UserError::Unauthorised)),
+        Err(error) => Either::Right(Err(error)),
     }
 }
@@ -1,3 +1,15 @@
+pub mod admin;
 pub mod auth;
+pub mod bookmarks;
+pub mod conversations;
+pub mod drafts;
+pub mod hashtags;
+pub mod lists;
+pub mod media;
 pub mod messages;
+pub mod notifications;
+pub mod reports;
+pub mod search;
+pub mod stream;
+pub mod trends;
 pub mod user;
@@ -0,0 +1,129 @@
+use std::str::FromStr;
+
+use actix_web::{get, post, put, web, Either, HttpRequest, HttpResponse};
+use mongodb::bson::{doc, oid::ObjectId};
+
+use crate::{
+    models::{
+        init::Tweetbook,
+        notifications::{Notification, NotificationPrefs},
+    },
+    utils::{auth::Authorization, error::UserError, pagination::Pagination},
+};
+
+pub fn notifications(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_notifications)
+        .service(unread_notifications)
+        .service(read_all_notifications)
+        .service(read_notification)
+        .service(notification_prefs)
+        .service(update_notification_prefs);
+}
+
+#[get("/api/notifications")]
+async fn list_notifications(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    page: web::Query<Pagination>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => {
+            let notifications = Notification::get_notifications(db, id, &page).await;
+
+            match notifications {
+                Ok(notifications) => Either::Left(HttpResponse::Ok().json(notifications)),
+                Err(_) => Either::Right(Err(UserError::InternalServerError)),
+            }
+        }
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[get("/api/notifications/unread")]
+async fn unread_notifications(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => match Notification::unread_count(db, id).await {
+            Ok(unread) => Either::Left(HttpResponse::Ok().json(doc! { "unread": unread as i64 })),
+            Err(_) => Either::Right(Err(UserError::InternalServerError)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[post("/api/notifications/read")]
+async fn read_all_notifications(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => match Notification::mark_all_read(db, id).await {
+            Ok(_) => Either::Left(HttpResponse::Ok().json(doc! { "unread": 0 })),
+            Err(_) => Either::Right(Err(UserError::InternalServerError)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[post("/api/notifications/{notification_id}/read")]
+async fn read_notification(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<String>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => match ObjectId::from_str(&path.into_inner()) {
+            Ok(notification_id) => {
+                match Notification::mark_read(db.clone(), id, notification_id).await {
+                    Ok(true) => match Notification::unread_count(db, id).await {
+                        Ok(unread) => {
+                            Either::Left(HttpResponse::Ok().json(doc! { "unread": unread as i64 }))
+                        }
+                        Err(_) => Either::Right(Err(UserError::InternalServerError)),
+                    },
+                    Ok(false) => Either::Right(Err(UserError::NotificationNotExists)),
+                    Err(_) => Either::Right(Err(UserError::InternalServerError)),
+                }
+            }
+            Err(_) => Either::Right(Err(UserError::NotificationNotExists)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[get("/api/notifications/preferences")]
+async fn notification_prefs(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => match Notification::get_prefs(db, id).await {
+            Ok(prefs) => Either::Left(HttpResponse::Ok().json(prefs)),
+            Err(_) => Either::Right(Err(UserError::InternalServerError)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[put("/api/notifications/preferences")]
+async fn update_notification_prefs(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    body: web::Json<NotificationPrefs>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => match Notification::set_prefs(db, id, &body).await {
+            Ok(_) => Either::Left(HttpResponse::Ok().json(body.into_inner())),
+            Err(_) => Either::Right(Err(UserError::InternalServerError)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
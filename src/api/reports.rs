@@ -0,0 +1,114 @@
+use std::str::FromStr;
+
+use actix_web::{post, web, Either, HttpRequest, HttpResponse};
+use mongodb::bson::oid::ObjectId;
+use serde::Deserialize;
+
+use crate::{
+    models::{
+        init::Tweetbook,
+        messages::Message,
+        reports::{Report, ReportKind, ReportReason, MAX_REPORT_DETAILS_CHARS},
+        users::User,
+    },
+    utils::{auth::Authorization, error::UserError},
+};
+
+#[derive(Deserialize)]
+struct ReportInput {
+    kind: ReportKind,
+    /// The reported tweet or user.
+    id: String,
+    reason: ReportReason,
+    details: Option<String>,
+}
+
+pub fn reports(cfg: &mut web::ServiceConfig) {
+    cfg.service(report);
+}
+
+/// Resolves what is being reported to `(target, reported user)`. Tweets
+/// have to be visible to the reporter, and nobody can report themselves.
+async fn resolve_target(
+    db: web::Data<Tweetbook>,
+    reporter: ObjectId,
+    kind: ReportKind,
+    id: &str,
+) -> Result<(ObjectId, ObjectId), UserError> {
+    match kind {
+        ReportKind::Tweet => {
+            let tweet_id = match ObjectId::from_str(id) {
+                Ok(tweet_id) => tweet_id,
+                Err(_) => return Err(UserError::TweetNotExists),
+            };
+
+            match Message::get_visible_message(db, tweet_id, reporter).await {
+                Ok(Some(msg)) => match msg.user {
+                    Some(author) if author.id == reporter => Err(UserError::CannotTargetSelf),
+                    Some(author) => Ok((tweet_id, author.id)),
+                    None => Err(UserError::TweetNotExists),
+                },
+                Ok(None) => Err(UserError::TweetNotExists),
+                Err(_) => Err(UserError::InternalServerError),
+            }
+        }
+        ReportKind::User => {
+            let user_id = match ObjectId::from_str(id) {
+                Ok(user_id) => user_id,
+                Err(_) => return Err(UserError::UserNotExists),
+            };
+            if user_id == reporter {
+                return Err(UserError::CannotTargetSelf);
+            }
+
+            match User::exists(db, user_id).await {
+                Ok(true) => Ok((user_id, user_id)),
+                Ok(false) => Err(UserError::UserNotExists),
+                Err(_) => Err(UserError::InternalServerError),
+            }
+        }
+    }
+}
+
+#[post("/api/report")]
+async fn report(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    body: web::Json<ReportInput>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => {
+            let details = body
+                .details
+                .as_deref()
+                .map(str::trim)
+                .filter(|details| !details.is_empty());
+            if details.is_some_and(|details| details.chars().count() > MAX_REPORT_DETAILS_CHARS) {
+                return Either::Right(Err(UserError::InvalidReport));
+            }
+
+            match resolve_target(db.clone(), id, body.kind, &body.id).await {
+                Ok((target, target_user)) => {
+                    let filed = Report::file(
+                        db,
+                        id,
+                        body.kind,
+                        target,
+                        target_user,
+                        body.reason,
+                        details.map(str::to_string),
+                    )
+                    .await;
+
+                    match filed {
+                        Ok(_) => Either::Right(Ok("Report submitted!")),
+                        Err(_) => Either::Right(Err(UserError::InternalServerError)),
+                    }
+                }
+                Err(error) => Either::Right(Err(error)),
+            }
+        }
+        Err(error) => Either::Right(Err(error)),
+    }
+}
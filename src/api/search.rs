@@ -0,0 +1,78 @@
+use actix_web::{get, web, Either, HttpRequest, HttpResponse};
+use mongodb::bson::doc;
+use serde::Deserialize;
+
+use crate::{
+    models::{
+        init::Tweetbook,
+        messages::Message,
+        users::{MinUser, User},
+    },
+    search::{SearchQuery, TweetSearch},
+    utils::{
+        auth::Authorization,
+        config::env_or,
+        error::UserError,
+        pagination::{Paginated, Pagination},
+    },
+};
+
+#[derive(Deserialize)]
+struct TweetSearchInput {
+    q: String,
+}
+
+pub fn search(cfg: &mut web::ServiceConfig) {
+    cfg.service(search_tweets);
+}
+
+#[get("/api/search/tweets")]
+async fn search_tweets(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    info: web::Query<TweetSearchInput>,
+    page: web::Query<Pagination>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(id) => {
+            let mut query = match SearchQuery::parse(&info.q) {
+                Ok(query) => query,
+                Err(error) => return Either::Right(Err(error)),
+            };
+
+            if let Some(username) = &query.from {
+                let users = User::get_user_by_query::<MinUser>(
+                    db.clone(),
+                    doc! {
+                        "$match": { "username": username }
+                    },
+                )
+                .await;
+
+                match users {
+                    Ok(mut users) if !users.is_empty() => {
+                        query.from_user = Some(users.remove(0).id);
+                    }
+                    Ok(_) => {
+                        let empty: Paginated<Message> = Paginated::new(vec![], 0, &page);
+                        return Either::Left(HttpResponse::Ok().json(empty));
+                    }
+                    Err(_) => return Either::Right(Err(UserError::InternalServerError)),
+                }
+            }
+
+            let max_hits = env_or("SEARCH_MAX_RESULTS", 1000_usize);
+            let hits = db.search.search(db.clone(), &query, max_hits).await;
+
+            match hits {
+                Ok(ids) => match Message::get_ranked_messages(db, ids, id, &page).await {
+                    Ok(msgs) => Either::Left(HttpResponse::Ok().json(msgs)),
+                    Err(_) => Either::Right(Err(UserError::InternalServerError)),
+                },
+                Err(_) => Either::Right(Err(UserError::InternalServerError)),
+            }
+        }
+        Err(error) => Either::Right(Err(error)),
+    }
+}
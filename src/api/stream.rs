@@ -0,0 +1,163 @@
+use std::{pin::pin, time::Duration};
+
+use actix_web::{get, rt, web, Either, HttpRequest, HttpResponse};
+use actix_web_lab::sse::{self, ChannelStream, Sse};
+use actix_ws::{Message as WsMessage, ProtocolError};
+use futures::{future, StreamExt};
+
+use crate::{
+    events::{BusEvent, Subscription},
+    models::init::Tweetbook,
+    utils::{auth::Authorization, config::env_interval, error::UserError},
+};
+
+/// Events queued for an SSE response before the sender waits on the client.
+const SSE_BUFFER: usize = 16;
+
+pub fn stream(cfg: &mut web::ServiceConfig) {
+    cfg.service(event_stream).service(event_socket);
+}
+
+fn keep_alive() -> Duration {
+    env_interval("STREAM_KEEP_ALIVE_SECONDS", 15)
+}
+
+enum Next {
+    Client(Option<Result<WsMessage, ProtocolError>>),
+    Bus(Option<BusEvent>),
+    Heartbeat,
+}
+
+/// Server-Sent Events fallback for clients that can't open a WebSocket.
+#[get("/api/stream")]
+async fn event_stream(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+) -> Either<Sse<ChannelStream>, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_stream_request(req).await;
+    match id_res {
+        Ok(id) => match Subscription::open(db.clone(), id).await {
+            Ok(mut subscription) => {
+                let (sender, events) = sse::channel(SSE_BUFFER);
+
+                // The keep-alive comments double as a disconnect check, so the
+                // task ends with the client even when no events come through.
+                rt::spawn(async move {
+                    let mut heartbeat = rt::time::interval(keep_alive());
+
+                    loop {
+                        let next = {
+                            let event = pin!(subscription.recv());
+                            let tick = pin!(heartbeat.tick());
+
+                            match future::select(event, tick).await {
+                                future::Either::Left((event, _)) => Next::Bus(event),
+                                future::Either::Right(_) => Next::Heartbeat,
+                            }
+                        };
+
+                        let sent = match next {
+                            Next::Bus(Some(event)) => {
+                                match subscription.resolve(db.clone(), event).await {
+                                    Some(event) => {
+                                        let data = sse::Data::new_json(&event)
+                                            .unwrap()
+                                            .event(event.name());
+                                        sender.send(data).await
+                                    }
+                                    None => Ok(()),
+                                }
+                            }
+                            Next::Heartbeat => {
+                                sender.send(sse::Event::Comment("keep-alive".into())).await
+                            }
+                            Next::Bus(None) | Next::Client(_) => break,
+                        };
+
+                        if sent.is_err() {
+                            break;
+                        }
+                    }
+                });
+
+                Either::Left(events)
+            }
+            Err(_) => Either::Right(Err(UserError::InternalServerError)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[get("/api/stream/ws")]
+async fn event_socket(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    body: web::Payload,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_stream_request(req.clone()).await;
+    match id_res {
+        Ok(id) => match Subscription::open(db.clone(), id).await {
+            Ok(mut subscription) => match actix_ws::handle(&req, body) {
+                Ok((response, mut session, mut messages)) => {
+                    rt::spawn(async move {
+                        let mut heartbeat = rt::time::interval(keep_alive());
+
+                        loop {
+                            let next = {
+                                let event = pin!(subscription.recv());
+                                let tick = pin!(heartbeat.tick());
+
+                                match future::select(future::select(messages.next(), event), tick)
+                                    .await
+                                {
+                                    future::Either::Left((future::Either::Left((msg, _)), _)) => {
+                                        Next::Client(msg)
+                                    }
+                                    future::Either::Left((
+                                        future::Either::Right((event, _)),
+                                        _,
+                                    )) => Next::Bus(event),
+                                    future::Either::Right(_) => Next::Heartbeat,
+                                }
+                            };
+
+                            let sent = match next {
+                                Next::Client(Some(Ok(WsMessage::Ping(bytes)))) => {
+                                    session.pong(&bytes).await
+                                }
+                                Next::Client(Some(Ok(WsMessage::Close(reason)))) => {
+                                    let _ = session.close(reason).await;
+                                    return;
+                                }
+                                Next::Client(Some(Ok(_))) => Ok(()),
+                                Next::Client(_) | Next::Bus(None) => break,
+                                Next::Bus(Some(event)) => {
+                                    match subscription.resolve(db.clone(), event).await {
+                                        Some(event) => {
+                                            session
+                                                .text(serde_json::to_string(&event).unwrap())
+                                                .await
+                                        }
+                                        None => Ok(()),
+                                    }
+                                }
+                                Next::Heartbeat => session.ping(b"").await,
+                            };
+
+                            if sent.is_err() {
+                                return;
+                            }
+                        }
+
+                        let _ = session.close(None).await;
+                    });
+
+                    Either::Left(response)
+                }
+                Err(error) => Either::Left(error.error_response()),
+            },
+            Err(_) => Either::Right(Err(UserError::InternalServerError)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
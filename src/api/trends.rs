@@ -0,0 +1,29 @@
+use actix_web::{get, web, Either, HttpRequest, HttpResponse};
+
+use crate::{
+    models::{init::Tweetbook, trends::Trends},
+    utils::{auth::Authorization, error::UserError},
+};
+
+pub fn trends(cfg: &mut web::ServiceConfig) {
+    cfg.service(current_trends);
+}
+
+#[get("/api/trends")]
+async fn current_trends(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+    match id_res {
+        Ok(_) => {
+            let trends = Trends::get_current(db).await;
+
+            match trends {
+                Ok(trends) => Either::Left(HttpResponse::Ok().json(trends)),
+                Err(_) => Either::Right(Err(UserError::InternalServerError)),
+            }
+        }
+        Err(error) => Either::Right(Err(error)),
+    }
+}
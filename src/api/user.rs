@@ -1,13 +1,19 @@
+use std::str::FromStr;
+
 use actix_web::{delete, get, post, put, web, Either, HttpRequest, HttpResponse};
-use mongodb::bson::doc;
+use mongodb::bson::{doc, oid::ObjectId};
 use serde::Deserialize;
 
 use crate::{
     models::{
         init::Tweetbook,
-        users::{MinUser, User},
+        lists::UserList,
+        messages::Message,
+        notifications::{Notification, NotificationKind},
+        spam::{Activity, ActivityKind},
+        users::{Access, MinUser, User},
     },
-    utils::{auth::Authorization, error::UserError},
+    utils::{auth::Authorization, challenge, error::UserError, pagination::Pagination},
 };
 
 pub fn user(cfg: &mut web::ServiceConfig) {
@@ -15,7 +21,22 @@ pub fn user(cfg: &mut web::ServiceConfig) {
         .service(update_profile)
         .service(follow_user)
         .service(unfollow_user)
-        .service(user_search);
+        .service(user_search)
+        .service(user_typeahead)
+        .service(user_followers)
+        .service(user_following)
+        .service(user_tweets)
+        .service(follow_requests)
+        .service(approve_follow_request)
+        .service(reject_follow_request)
+        .service(block_user)
+        .service(unblock_user)
+        .service(blocked_users)
+        .service(mute_user)
+        .service(unmute_user)
+        .service(muted_users)
+        .service(get_challenge)
+        .service(solve_challenge);
 }
 
 #[derive(Deserialize)]
@@ -23,11 +44,19 @@ struct UserSearch {
     search: String,
 }
 
+#[derive(Deserialize)]
+struct ChallengeInput {
+    token: String,
+}
+
 #[derive(Deserialize)]
 struct UserUpdate {
     bio: String,
     #[serde(rename = "profileImgUrl")]
     profile_img_url: String,
+    private: Option<bool>,
+    #[serde(rename = "displayName")]
+    display_name: Option<String>,
 }
 
 #[get("/api/user/profile/{user_id}")]
@@ -39,16 +68,22 @@ async fn user_profile(
     let id_res = Authorization::verify_request(req).await;
 
     match id_res {
-        Ok(_) => {
-            let user_id = path.into_inner();
-            let users_response = User::get_user_details(db, user_id).await;
+        Ok(id) => match ObjectId::from_str(&path.into_inner()) {
+            Ok(user_id) => match User::is_blocked_between(db.clone(), user_id, id).await {
+                Ok(false) => {
+                    let users_response = User::get_user_details(db, user_id.to_string(), id).await;
 
-            match users_response {
-                Ok(users) => Either::Left(HttpResponse::Ok().json(users)),
-                Err(_) => Either::Right(Err(UserError::UserNotExists)),
-            }
-        }
-        Err(_) => Either::Right(Err(UserError::Unauthorised)),
+                    match users_response {
+                        Ok(users) => Either::Left(HttpResponse::Ok().json(users)),
+                        Err(_) => Either::Right(Err(UserError::UserNotExists)),
+                    }
+                }
+                Ok(true) => Either::Right(Err(UserError::Blocked)),
+                Err(_) => Either::Right(Err(UserError::InternalServerError)),
+            },
+            Err(_) => Either::Right(Err(UserError::UserNotExists)),
+        },
+        Err(error) => Either::Right(Err(error)),
     }
 }
 
@@ -62,19 +97,24 @@ async fn update_profile(
 
     match id_res {
         Ok(id) => {
-            let users_response = User::update_user(
-                db,
-                id.to_string(),
-                doc! {"$set": {"bio": body.bio.to_owned(), "profileImgUrl": body.profile_img_url.to_owned()}},
-            )
-            .await;
+            let mut update =
+                doc! {"bio": body.bio.to_owned(), "profileImgUrl": body.profile_img_url.to_owned()};
+            if let Some(private) = body.private {
+                update.insert("private", private);
+            }
+            if let Some(display_name) = &body.display_name {
+                update.insert("displayName", display_name);
+                update.insert("displayNameLower", display_name.to_lowercase());
+            }
+
+            let users_response = User::update_user(db, id.to_string(), doc! {"$set": update}).await;
 
             match users_response {
                 Ok(users) => Either::Left(HttpResponse::Ok().json(users)),
                 Err(_) => Either::Right(Err(UserError::UserNotExists)),
             }
         }
-        Err(_) => Either::Right(Err(UserError::Unauthorised)),
+        Err(error) => Either::Right(Err(error)),
     }
 }
 
@@ -89,7 +129,7 @@ async fn follow_user(
     match id_res {
         Ok(id) => {
             let user_id = path.into_inner();
-            let user_res = User::get_user_by_query::<MinUser>(
+            let user_res = User::get_user_by_query::<User>(
                 db.clone(),
                 doc! {
                     "$match": {
@@ -103,26 +143,39 @@ async fn follow_user(
 
             match user_res {
                 Ok(mut users) => {
-                    if users.len() > 0 {
+                    if !users.is_empty() {
                         let user = users.remove(0);
+                        if user.id == id {
+                            return Either::Right(Err(UserError::CannotTargetSelf));
+                        }
 
-                        let user_follower = User::update_user(
-                            db.clone(),
-                            id.to_string(),
-                            doc! { "$addToSet": { "following": { "$each": vec![user.id]} }},
-                        )
-                        .await
-                        .unwrap();
+                        let access = User::access(db.clone(), user.id, id).await.unwrap();
+                        if access == Access::Blocked {
+                            return Either::Right(Err(UserError::Blocked));
+                        }
 
-                        User::update_user(
-                            db.clone(),
-                            user.id.to_string(),
-                            doc! { "$addToSet": { "followers": { "$each": vec![user_follower.id]}}},
-                        )
-                        .await
-                        .unwrap();
+                        // Only attempts that could go through count towards the
+                        // rate limit and spam score.
+                        match Activity::track(db.clone(), id, ActivityKind::Follow, None).await {
+                            Ok(_) => {}
+                            Err(error) => return Either::Right(Err(error)),
+                        }
+
+                        if access == Access::Allowed {
+                            User::follow(db.clone(), id, user.id).await.unwrap();
+                            let _ = Notification::notify(
+                                db.clone(),
+                                user.id,
+                                id,
+                                NotificationKind::Follow,
+                                None,
+                            )
+                            .await;
+                        } else {
+                            User::request_follow(db.clone(), id, user.id).await.unwrap()
+                        }
 
-                        let mut user_updated = User::get_user_details(db, user.id.to_string())
+                        let mut user_updated = User::get_user_details(db, user.id.to_string(), id)
                             .await
                             .unwrap();
 
@@ -134,7 +187,7 @@ async fn follow_user(
                 Err(_) => Either::Right(Err(UserError::UserNotExists)),
             }
         }
-        Err(_) => Either::Right(Err(UserError::Unauthorised)),
+        Err(error) => Either::Right(Err(error)),
     }
 }
 
@@ -163,26 +216,20 @@ async fn unfollow_user(
 
             match user_res {
                 Ok(mut users) => {
-                    if users.len() > 0 {
+                    if !users.is_empty() {
                         let user = users.remove(0);
+                        if user.id == id {
+                            return Either::Right(Err(UserError::CannotTargetSelf));
+                        }
 
-                        let user_follower = User::update_user(
-                            db.clone(),
-                            id.to_string(),
-                            doc! { "$pull": { "following": { "$in": vec![user.id]} }},
-                        )
-                        .await
-                        .unwrap();
+                        match Activity::track(db.clone(), id, ActivityKind::Unfollow, None).await {
+                            Ok(_) => {}
+                            Err(error) => return Either::Right(Err(error)),
+                        }
 
-                        User::update_user(
-                            db.clone(),
-                            user.id.to_string(),
-                            doc! { "$pull": { "followers": { "$in": vec![user_follower.id]}}},
-                        )
-                        .await
-                        .unwrap();
+                        User::unfollow(db.clone(), id, user.id).await.unwrap();
 
-                        let mut user_updated = User::get_user_details(db, user.id.to_string())
+                        let mut user_updated = User::get_user_details(db, user.id.to_string(), id)
                             .await
                             .unwrap();
 
@@ -194,7 +241,7 @@ async fn unfollow_user(
                 Err(_) => Either::Right(Err(UserError::UserNotExists)),
             }
         }
-        Err(_) => Either::Right(Err(UserError::Unauthorised)),
+        Err(error) => Either::Right(Err(error)),
     }
 }
 
@@ -203,29 +250,431 @@ async fn user_search(
     req: HttpRequest,
     db: web::Data<Tweetbook>,
     info: web::Query<UserSearch>,
+    page: web::Query<Pagination>,
 ) -> Either<HttpResponse, Result<&'static str, UserError>> {
     let id_res = Authorization::verify_request(req).await;
 
     match id_res {
-        Ok(_) => {
-            let users_response = User::get_user_by_query::<MinUser>(
-                db,
-                doc! {
-                    "$match": {
-                        "username": {
-                            "$regex": info.search.to_string(),
-                            "$options": "i"
+        Ok(id) => {
+            let users_response = User::search_users(db, &info.search, id, &page).await;
+
+            match users_response {
+                Ok(users) => Either::Left(HttpResponse::Ok().json(users)),
+                Err(_) => Either::Right(Err(UserError::UserNotExists)),
+            }
+        }
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[get("/api/users/typeahead")]
+async fn user_typeahead(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    info: web::Query<UserSearch>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+
+    match id_res {
+        Ok(id) => {
+            let users_response = User::typeahead(db, &info.search, id).await;
+
+            match users_response {
+                Ok(users) => Either::Left(HttpResponse::Ok().json(users)),
+                Err(_) => Either::Right(Err(UserError::InternalServerError)),
+            }
+        }
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[get("/api/user/{user_id}/followers")]
+async fn user_followers(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<String>,
+    page: web::Query<Pagination>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+
+    match id_res {
+        Ok(id) => match ObjectId::from_str(&path.into_inner()) {
+            Ok(user_id) => match User::access(db.clone(), user_id, id).await {
+                Ok(Access::Allowed) => {
+                    let users_response =
+                        User::get_user_connections(db, user_id.to_string(), "followers", id, &page)
+                            .await;
+
+                    match users_response {
+                        Ok(users) => Either::Left(HttpResponse::Ok().json(users)),
+                        Err(_) => Either::Right(Err(UserError::UserNotExists)),
+                    }
+                }
+                Ok(Access::Private) => Either::Right(Err(UserError::PrivateAccount)),
+                Ok(Access::Blocked) => Either::Right(Err(UserError::Blocked)),
+                Err(_) => Either::Right(Err(UserError::InternalServerError)),
+            },
+            Err(_) => Either::Right(Err(UserError::UserNotExists)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[get("/api/user/{user_id}/following")]
+async fn user_following(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<String>,
+    page: web::Query<Pagination>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+
+    match id_res {
+        Ok(id) => match ObjectId::from_str(&path.into_inner()) {
+            Ok(user_id) => match User::access(db.clone(), user_id, id).await {
+                Ok(Access::Allowed) => {
+                    let users_response =
+                        User::get_user_connections(db, user_id.to_string(), "following", id, &page)
+                            .await;
+
+                    match users_response {
+                        Ok(users) => Either::Left(HttpResponse::Ok().json(users)),
+                        Err(_) => Either::Right(Err(UserError::UserNotExists)),
+                    }
+                }
+                Ok(Access::Private) => Either::Right(Err(UserError::PrivateAccount)),
+                Ok(Access::Blocked) => Either::Right(Err(UserError::Blocked)),
+                Err(_) => Either::Right(Err(UserError::InternalServerError)),
+            },
+            Err(_) => Either::Right(Err(UserError::UserNotExists)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[get("/api/user/{user_id}/tweets")]
+async fn user_tweets(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<String>,
+    page: web::Query<Pagination>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+
+    match id_res {
+        Ok(id) => match ObjectId::from_str(&path.into_inner()) {
+            Ok(user_id) => match User::access(db.clone(), user_id, id).await {
+                Ok(Access::Allowed) => {
+                    let messages = Message::get_user_messages(db, user_id, id, &page).await;
+
+                    match messages {
+                        Ok(msgs) => Either::Left(HttpResponse::Ok().json(msgs)),
+                        Err(_) => Either::Right(Err(UserError::InternalServerError)),
+                    }
+                }
+                Ok(Access::Private) => Either::Right(Err(UserError::PrivateAccount)),
+                Ok(Access::Blocked) => Either::Right(Err(UserError::Blocked)),
+                Err(_) => Either::Right(Err(UserError::InternalServerError)),
+            },
+            Err(_) => Either::Right(Err(UserError::UserNotExists)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[get("/api/user/follow-requests")]
+async fn follow_requests(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    page: web::Query<Pagination>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+
+    match id_res {
+        Ok(id) => {
+            let users_response =
+                User::get_user_connections(db, id.to_string(), "followRequests", id, &page).await;
+
+            match users_response {
+                Ok(users) => Either::Left(HttpResponse::Ok().json(users)),
+                Err(_) => Either::Right(Err(UserError::InternalServerError)),
+            }
+        }
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[post("/api/user/follow-requests/{user_id}")]
+async fn approve_follow_request(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<String>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+
+    match id_res {
+        Ok(id) => match ObjectId::from_str(&path.into_inner()) {
+            Ok(user_id) => match User::has_follow_request(db.clone(), user_id, id).await {
+                Ok(true) => {
+                    User::follow(db.clone(), user_id, id).await.unwrap();
+                    let _ = Notification::notify(
+                        db.clone(),
+                        id,
+                        user_id,
+                        NotificationKind::Follow,
+                        None,
+                    )
+                    .await;
+
+                    let mut user_updated = User::get_user_details(db, user_id.to_string(), id)
+                        .await
+                        .unwrap();
+
+                    Either::Left(HttpResponse::Ok().json(user_updated.remove(0)))
+                }
+                Ok(false) => Either::Right(Err(UserError::FollowRequestNotExists)),
+                Err(_) => Either::Right(Err(UserError::InternalServerError)),
+            },
+            Err(_) => Either::Right(Err(UserError::UserNotExists)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[delete("/api/user/follow-requests/{user_id}")]
+async fn reject_follow_request(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<String>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+
+    match id_res {
+        Ok(id) => match ObjectId::from_str(&path.into_inner()) {
+            Ok(user_id) => match User::has_follow_request(db.clone(), user_id, id).await {
+                Ok(true) => {
+                    User::update_user(
+                        db,
+                        id.to_string(),
+                        doc! { "$pull": { "followRequests": user_id }},
+                    )
+                    .await
+                    .unwrap();
+
+                    Either::Left(HttpResponse::Ok().body("Follow request rejected!"))
+                }
+                Ok(false) => Either::Right(Err(UserError::FollowRequestNotExists)),
+                Err(_) => Either::Right(Err(UserError::InternalServerError)),
+            },
+            Err(_) => Either::Right(Err(UserError::UserNotExists)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[post("/api/user/block/{user_id}")]
+async fn block_user(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<String>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+
+    match id_res {
+        Ok(id) => match ObjectId::from_str(&path.into_inner()) {
+            Ok(user_id) if user_id == id => Either::Right(Err(UserError::CannotTargetSelf)),
+            Ok(user_id) => match User::exists(db.clone(), user_id).await {
+                Ok(true) => {
+                    let response = User::block(db.clone(), id, user_id).await;
+
+                    match response {
+                        Ok(_) => {
+                            let _ = UserList::remove_from_lists_of(db.clone(), id, user_id).await;
+                            let _ = UserList::remove_from_lists_of(db, user_id, id).await;
+                            Either::Left(HttpResponse::Ok().body("User blocked!"))
                         }
+                        Err(_) => Either::Right(Err(UserError::UserNotExists)),
                     }
-                },
-            )
-            .await;
+                }
+                Ok(false) => Either::Right(Err(UserError::UserNotExists)),
+                Err(_) => Either::Right(Err(UserError::InternalServerError)),
+            },
+            Err(_) => Either::Right(Err(UserError::UserNotExists)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[delete("/api/user/block/{user_id}")]
+async fn unblock_user(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<String>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+
+    match id_res {
+        Ok(id) => match ObjectId::from_str(&path.into_inner()) {
+            Ok(user_id) if user_id == id => Either::Right(Err(UserError::CannotTargetSelf)),
+            Ok(user_id) => {
+                let response =
+                    User::update_user(db, id.to_string(), doc! { "$pull": { "blocked": user_id }})
+                        .await;
+
+                match response {
+                    Ok(_) => Either::Left(HttpResponse::Ok().body("User unblocked!")),
+                    Err(_) => Either::Right(Err(UserError::UserNotExists)),
+                }
+            }
+            Err(_) => Either::Right(Err(UserError::UserNotExists)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[get("/api/user/blocks")]
+async fn blocked_users(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    page: web::Query<Pagination>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+
+    match id_res {
+        Ok(id) => {
+            let users_response =
+                User::get_user_connections(db, id.to_string(), "blocked", id, &page).await;
 
             match users_response {
                 Ok(users) => Either::Left(HttpResponse::Ok().json(users)),
-                Err(_) => Either::Right(Err(UserError::UserNotExists)),
+                Err(_) => Either::Right(Err(UserError::InternalServerError)),
             }
         }
-        Err(_) => Either::Right(Err(UserError::Unauthorised)),
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[post("/api/user/mute/{user_id}")]
+async fn mute_user(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<String>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+
+    match id_res {
+        Ok(id) => match ObjectId::from_str(&path.into_inner()) {
+            Ok(user_id) if user_id == id => Either::Right(Err(UserError::CannotTargetSelf)),
+            Ok(user_id) => match User::exists(db.clone(), user_id).await {
+                Ok(true) => {
+                    let response = User::update_user(
+                        db,
+                        id.to_string(),
+                        doc! { "$addToSet": { "muted": user_id }},
+                    )
+                    .await;
+
+                    match response {
+                        Ok(_) => Either::Left(HttpResponse::Ok().body("User muted!")),
+                        Err(_) => Either::Right(Err(UserError::UserNotExists)),
+                    }
+                }
+                Ok(false) => Either::Right(Err(UserError::UserNotExists)),
+                Err(_) => Either::Right(Err(UserError::InternalServerError)),
+            },
+            Err(_) => Either::Right(Err(UserError::UserNotExists)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[delete("/api/user/mute/{user_id}")]
+async fn unmute_user(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    path: web::Path<String>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+
+    match id_res {
+        Ok(id) => match ObjectId::from_str(&path.into_inner()) {
+            Ok(user_id) if user_id == id => Either::Right(Err(UserError::CannotTargetSelf)),
+            Ok(user_id) => {
+                let response =
+                    User::update_user(db, id.to_string(), doc! { "$pull": { "muted": user_id }})
+                        .await;
+
+                match response {
+                    Ok(_) => Either::Left(HttpResponse::Ok().body("User unmuted!")),
+                    Err(_) => Either::Right(Err(UserError::UserNotExists)),
+                }
+            }
+            Err(_) => Either::Right(Err(UserError::UserNotExists)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[get("/api/user/mutes")]
+async fn muted_users(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    page: web::Query<Pagination>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+
+    match id_res {
+        Ok(id) => {
+            let users_response =
+                User::get_user_connections(db, id.to_string(), "muted", id, &page).await;
+
+            match users_response {
+                Ok(users) => Either::Left(HttpResponse::Ok().json(users)),
+                Err(_) => Either::Right(Err(UserError::InternalServerError)),
+            }
+        }
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+/// Whether the user has to solve a challenge before following, liking or
+/// tweeting again, and the site key to render it with.
+#[get("/api/user/challenge")]
+async fn get_challenge(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+
+    match id_res {
+        Ok(id) => match Activity::challenge_required(db, id).await {
+            Ok(required) => Either::Left(HttpResponse::Ok().json(doc! {
+                "required": required,
+                "siteKey": challenge::site_key(),
+            })),
+            Err(_) => Either::Right(Err(UserError::InternalServerError)),
+        },
+        Err(error) => Either::Right(Err(error)),
+    }
+}
+
+#[post("/api/user/challenge")]
+async fn solve_challenge(
+    req: HttpRequest,
+    db: web::Data<Tweetbook>,
+    body: web::Json<ChallengeInput>,
+) -> Either<HttpResponse, Result<&'static str, UserError>> {
+    let id_res = Authorization::verify_request(req).await;
+
+    match id_res {
+        Ok(id) => match challenge::verify(&body.token).await {
+            Ok(true) => match Activity::pass_challenge(db, id).await {
+                Ok(_) => Either::Right(Ok("Challenge passed!")),
+                Err(_) => Either::Right(Err(UserError::InternalServerError)),
+            },
+            Ok(false) => Either::Right(Err(UserError::InvalidChallenge)),
+            Err(error) => Either::Right(Err(error)),
+        },
+        Err(error) => Either::Right(Err(error)),
     }
 }
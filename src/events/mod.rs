@@ -0,0 +1,230 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use actix_web::web;
+use futures::{
+    channel::mpsc::{self, Receiver, Sender},
+    StreamExt,
+};
+use mongodb::{bson::oid::ObjectId, error::Error};
+use serde::Serialize;
+
+use crate::models::{
+    direct_messages::DirectMessage, init::Tweetbook, messages::Message,
+    notifications::NotificationKind, users::User,
+};
+
+/// Events a single connection may buffer before further ones are dropped
+/// for it, so a slow client can't hold up publishers.
+const SUBSCRIBER_BUFFER: usize = 64;
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum Channel {
+    /// Tweets posted by a user, listened to by their followers.
+    Tweets(ObjectId),
+    /// Events addressed to a single user.
+    Inbox(ObjectId),
+}
+
+/// What gets published on the bus. Kept to ids so that each subscriber can
+/// load what it is allowed to see.
+#[derive(Clone, Debug)]
+pub enum BusEvent {
+    NewTweet {
+        tweet: ObjectId,
+    },
+    TweetUpdated {
+        tweet: ObjectId,
+        like_count: u64,
+    },
+    Followed {
+        followee: ObjectId,
+    },
+    Unfollowed {
+        followee: ObjectId,
+    },
+    Notification {
+        kind: NotificationKind,
+        tweet: Option<ObjectId>,
+    },
+    DirectMessage {
+        message: ObjectId,
+    },
+}
+
+/// What a client receives on `/api/stream`.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", content = "data", rename_all = "lowercase")]
+pub enum StreamEvent {
+    Tweet(Box<Message>),
+    Likes {
+        tweet: ObjectId,
+        #[serde(rename = "likeCount")]
+        like_count: u64,
+    },
+    Notification {
+        kind: NotificationKind,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        tweet: Option<ObjectId>,
+    },
+    #[serde(rename = "directMessage")]
+    DirectMessage(Box<DirectMessage>),
+}
+
+impl StreamEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            StreamEvent::Tweet(_) => "tweet",
+            StreamEvent::Likes { .. } => "likes",
+            StreamEvent::Notification { .. } => "notification",
+            StreamEvent::DirectMessage(_) => "directMessage",
+        }
+    }
+}
+
+/// In-process pub/sub connecting the write paths to open streams.
+#[derive(Default)]
+pub struct EventBus {
+    next_id: AtomicU64,
+    channels: Mutex<HashMap<Channel, HashMap<u64, Sender<BusEvent>>>>,
+}
+
+impl EventBus {
+    pub fn publish(&self, channel: Channel, event: BusEvent) {
+        let mut channels = self.channels.lock().unwrap();
+
+        if let Some(subscribers) = channels.get_mut(&channel) {
+            subscribers.retain(|_, sender| match sender.try_send(event.clone()) {
+                Ok(_) => true,
+                Err(error) => !error.is_disconnected(),
+            });
+            if subscribers.is_empty() {
+                channels.remove(&channel);
+            }
+        }
+    }
+
+    pub fn subscribe(bus: &Arc<EventBus>, user: ObjectId) -> Subscription {
+        let (sender, receiver) = mpsc::channel(SUBSCRIBER_BUFFER);
+
+        Subscription {
+            bus: bus.clone(),
+            id: bus.next_id.fetch_add(1, Ordering::Relaxed),
+            user,
+            sender,
+            receiver,
+            channels: HashSet::new(),
+        }
+    }
+}
+
+/// One open stream's membership on the bus, left when dropped.
+pub struct Subscription {
+    bus: Arc<EventBus>,
+    id: u64,
+    user: ObjectId,
+    sender: Sender<BusEvent>,
+    receiver: Receiver<BusEvent>,
+    channels: HashSet<Channel>,
+}
+
+impl Subscription {
+    /// Subscribes `user` to their inbox and to tweets from themselves and
+    /// everyone they follow.
+    pub async fn open(data: web::Data<Tweetbook>, user: ObjectId) -> Result<Self, Error> {
+        match User::get_relation_ids(data.clone(), user, "following").await {
+            Ok(following) => {
+                let mut subscription = EventBus::subscribe(&data.events, user);
+
+                subscription.join(Channel::Inbox(user));
+                subscription.join(Channel::Tweets(user));
+                for followee in following {
+                    subscription.join(Channel::Tweets(followee));
+                }
+
+                Ok(subscription)
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    pub fn join(&mut self, channel: Channel) {
+        if self.channels.insert(channel) {
+            self.bus
+                .channels
+                .lock()
+                .unwrap()
+                .entry(channel)
+                .or_default()
+                .insert(self.id, self.sender.clone());
+        }
+    }
+
+    pub fn leave(&mut self, channel: Channel) {
+        if self.channels.remove(&channel) {
+            let mut channels = self.bus.channels.lock().unwrap();
+
+            if let Some(subscribers) = channels.get_mut(&channel) {
+                subscribers.remove(&self.id);
+                if subscribers.is_empty() {
+                    channels.remove(&channel);
+                }
+            }
+        }
+    }
+
+    /// Waits for the next bus event. Safe to cancel.
+    pub async fn recv(&mut self) -> Option<BusEvent> {
+        self.receiver.next().await
+    }
+
+    /// Turns a bus event into what this subscriber should be sent, if
+    /// anything. Follow changes update which authors' tweets are received.
+    pub async fn resolve(
+        &mut self,
+        data: web::Data<Tweetbook>,
+        event: BusEvent,
+    ) -> Option<StreamEvent> {
+        match event {
+            BusEvent::NewTweet { tweet } => {
+                match Message::get_visible_message(data, tweet, self.user).await {
+                    Ok(Some(message)) => Some(StreamEvent::Tweet(Box::new(message))),
+                    _ => None,
+                }
+            }
+            BusEvent::TweetUpdated { tweet, like_count } => {
+                Some(StreamEvent::Likes { tweet, like_count })
+            }
+            BusEvent::Followed { followee } => {
+                self.join(Channel::Tweets(followee));
+                None
+            }
+            BusEvent::Unfollowed { followee } => {
+                self.leave(Channel::Tweets(followee));
+                None
+            }
+            BusEvent::Notification { kind, tweet } => {
+                Some(StreamEvent::Notification { kind, tweet })
+            }
+            BusEvent::DirectMessage { message } => {
+                match DirectMessage::get_visible(data, message, self.user).await {
+                    Ok(Some(message)) => Some(StreamEvent::DirectMessage(Box::new(message))),
+                    _ => None,
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        for channel in self.channels.clone() {
+            self.leave(channel);
+        }
+    }
+}
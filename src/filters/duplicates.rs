@@ -0,0 +1,61 @@
+use actix_web::web;
+use mongodb::{
+    bson::{doc, DateTime, Document},
+    error::Error,
+};
+
+use crate::models::{init::Tweetbook, messages::Message};
+
+use super::{normalize::normalize, FilterAction, FilterReason, Submission, Verdict};
+
+/// Flags a tweet whose normalized text matches one of the author's own
+/// live tweets from the last `window_millis`. Replies are left alone, since
+/// short answers like "thanks!" are naturally repeated across threads.
+pub struct DuplicateFilter {
+    action: FilterAction,
+    window_millis: i64,
+}
+
+impl DuplicateFilter {
+    pub fn new(action: FilterAction, window_millis: i64) -> Self {
+        DuplicateFilter {
+            action,
+            window_millis,
+        }
+    }
+
+    pub async fn check(
+        &self,
+        data: web::Data<Tweetbook>,
+        submission: &Submission<'_>,
+        fingerprint: &str,
+    ) -> Result<Option<Verdict>, Error> {
+        if submission.reply_to.is_some() || normalize(submission.text).is_empty() {
+            return Ok(None);
+        }
+
+        let since = DateTime::from_millis(DateTime::now().timestamp_millis() - self.window_millis);
+        let mut filter = doc! {
+            "user": submission.author,
+            "fingerprint": fingerprint,
+            "deletedAt": null,
+            "createdAt": { "$gte": since },
+        };
+        if let Some(tweet) = submission.tweet {
+            filter.insert("_id", doc! { "$ne": tweet });
+        }
+
+        let duplicates = Message::get_collection::<Document>(data)
+            .count_documents(filter, None)
+            .await;
+
+        match duplicates {
+            Ok(0) => Ok(None),
+            Ok(_) => Ok(Some(Verdict {
+                action: self.action,
+                reason: FilterReason::Duplicate,
+            })),
+            Err(error) => Err(error),
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use awc::http::Uri;
+
+use crate::utils::entities::extract_urls;
+
+use super::{FilterAction, FilterReason, Submission, Verdict};
+
+/// Flags tweets linking to a blocked domain or any of its subdomains.
+pub struct LinkFilter {
+    action: FilterAction,
+    domains: Vec<String>,
+}
+
+fn normalize_domain(domain: &str) -> String {
+    domain
+        .trim()
+        .trim_start_matches("*.")
+        .trim_matches('.')
+        .to_lowercase()
+}
+
+impl LinkFilter {
+    pub fn new(action: FilterAction, domains: Vec<String>) -> Self {
+        let domains = domains
+            .iter()
+            .map(|domain| normalize_domain(domain))
+            .filter(|domain| !domain.is_empty())
+            .collect();
+
+        LinkFilter { action, domains }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.domains.is_empty()
+    }
+
+    fn is_blocked(&self, host: &str) -> bool {
+        let host = normalize_domain(host);
+
+        self.domains.iter().any(|domain| {
+            host == *domain
+                || host
+                    .strip_suffix(domain.as_str())
+                    .is_some_and(|sub| sub.ends_with('.'))
+        })
+    }
+
+    pub fn check(&self, submission: &Submission<'_>) -> Option<Verdict> {
+        let blocked = extract_urls(submission.text).iter().any(|link| {
+            link.value
+                .parse::<Uri>()
+                .ok()
+                .and_then(|uri| uri.host().map(|host| self.is_blocked(host)))
+                .unwrap_or(false)
+        });
+
+        if blocked {
+            Some(Verdict {
+                action: self.action,
+                reason: FilterReason::BlockedLink,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::oid::ObjectId;
+
+    use super::*;
+
+    fn flags(filter: &LinkFilter, text: &str) -> bool {
+        filter
+            .check(&Submission {
+                author: ObjectId::new(),
+                text,
+                tweet: None,
+                reply_to: None,
+            })
+            .is_some()
+    }
+
+    fn filter(domains: &[&str]) -> LinkFilter {
+        LinkFilter::new(
+            FilterAction::Reject,
+            domains.iter().map(|domain| domain.to_string()).collect(),
+        )
+    }
+
+    #[test]
+    fn blocks_domains_and_subdomains() {
+        let filter = filter(&["spam.example"]);
+        assert!(flags(&filter, "see https://spam.example/offer"));
+        assert!(flags(&filter, "see http://www.SPAM.example."));
+        assert!(flags(&filter, "https://a.b.spam.example:8080/x"));
+        assert!(!flags(&filter, "https://notspam.example/"));
+        assert!(!flags(&filter, "https://spam.example.org/"));
+    }
+
+    #[test]
+    fn only_looks_at_links() {
+        let filter = filter(&["spam.example"]);
+        assert!(!flags(&filter, "spam.example is down"));
+        assert!(!flags(&filter, "https://fine.example/spam.example"));
+    }
+
+    #[test]
+    fn normalizes_listed_domains() {
+        let filter = filter(&[" *.Spam.Example. ", "", "."]);
+        assert!(flags(&filter, "https://spam.example"));
+        assert!(flags(&filter, "https://cdn.spam.example"));
+        assert_eq!(filter.domains, ["spam.example"]);
+    }
+
+    #[test]
+    fn reports_its_action() {
+        let verdict = filter(&["spam.example"])
+            .check(&Submission {
+                author: ObjectId::new(),
+                text: "https://spam.example",
+                tweet: None,
+                reply_to: None,
+            })
+            .unwrap();
+        assert_eq!(verdict.action, FilterAction::Reject);
+        assert_eq!(verdict.reason, FilterReason::BlockedLink);
+    }
+}
@@ -0,0 +1,240 @@
+pub mod duplicates;
+pub mod links;
+pub mod normalize;
+pub mod words;
+
+use std::{env, fs, str::FromStr};
+
+use actix_web::web;
+use mongodb::{bson::oid::ObjectId, error::Error};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    models::init::Tweetbook,
+    utils::{config::env_or, error::UserError},
+};
+
+use self::{
+    duplicates::DuplicateFilter, links::LinkFilter, normalize::fingerprint, words::WordFilter,
+};
+
+/// What happens to a tweet a filter flags, from mildest to harshest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FilterAction {
+    /// Posted, marked `sensitive` for clients to blur.
+    Label,
+    /// Posted, but only its author sees it until a moderator approves it.
+    Hold,
+    /// Not posted; the author gets the reason.
+    Reject,
+}
+
+impl FromStr for FilterAction {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_lowercase().as_str() {
+            "label" => Ok(FilterAction::Label),
+            "hold" => Ok(FilterAction::Hold),
+            "reject" => Ok(FilterAction::Reject),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterReason {
+    BannedWord,
+    BlockedLink,
+    Duplicate,
+}
+
+impl FilterReason {
+    /// The error a rejected tweet's author gets.
+    pub fn error(self) -> UserError {
+        match self {
+            FilterReason::BannedWord => UserError::BannedContent,
+            FilterReason::BlockedLink => UserError::BlockedLink,
+            FilterReason::Duplicate => UserError::DuplicateTweet,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Verdict {
+    pub action: FilterAction,
+    pub reason: FilterReason,
+}
+
+/// A tweet about to be posted, or edited when `tweet` is set.
+pub struct Submission<'a> {
+    pub author: ObjectId,
+    pub text: &'a str,
+    pub tweet: Option<ObjectId>,
+    /// The tweet it replies to, if it is a reply.
+    pub reply_to: Option<ObjectId>,
+}
+
+/// The outcome of a tweet that passed the filters.
+#[derive(Debug, Default)]
+pub struct Screening {
+    pub hold: bool,
+    pub sensitive: bool,
+    pub reasons: Vec<FilterReason>,
+    /// Stored with the tweet for duplicate detection.
+    pub fingerprint: String,
+}
+
+/// One step of the content filter pipeline.
+pub trait ContentFilter {
+    async fn check(
+        &self,
+        data: web::Data<Tweetbook>,
+        submission: &Submission<'_>,
+        fingerprint: &str,
+    ) -> Result<Option<Verdict>, Error>;
+}
+
+pub enum Filter {
+    Words(WordFilter),
+    Links(LinkFilter),
+    Duplicates(DuplicateFilter),
+}
+
+impl ContentFilter for Filter {
+    async fn check(
+        &self,
+        data: web::Data<Tweetbook>,
+        submission: &Submission<'_>,
+        fingerprint: &str,
+    ) -> Result<Option<Verdict>, Error> {
+        match self {
+            Filter::Words(filter) => Ok(filter.check(submission)),
+            Filter::Links(filter) => Ok(filter.check(submission)),
+            Filter::Duplicates(filter) => filter.check(data, submission, fingerprint).await,
+        }
+    }
+}
+
+/// Entries of the comma separated list in `key` and of the file named by
+/// `{key}_FILE`, one entry per line with `#` starting a comment.
+fn read_list(key: &str) -> Vec<String> {
+    let mut list: Vec<String> = env::var(key)
+        .unwrap_or_default()
+        .split(',')
+        .map(|entry| entry.trim().to_string())
+        .collect();
+
+    if let Ok(path) = env::var(format!("{}_FILE", key)) {
+        match fs::read_to_string(&path) {
+            Ok(contents) => list.extend(
+                contents
+                    .lines()
+                    .map(|line| line.split('#').next().unwrap_or("").trim().to_string()),
+            ),
+            Err(error) => println!("Failed to read {}: {:?}", path, error),
+        }
+    }
+
+    list.retain(|entry| !entry.is_empty());
+    list
+}
+
+/// The filters every new or edited tweet goes through, configured from the
+/// environment:
+///
+/// - `FILTER_WORDS_REJECT`, `FILTER_WORDS_HOLD` and `FILTER_WORDS_LABEL`
+///   list banned words and phrases for each action.
+/// - `FILTER_BLOCKED_DOMAINS` lists link domains, handled per
+///   `FILTER_BLOCKED_DOMAINS_ACTION` (`reject` by default).
+/// - `FILTER_DUPLICATE_WINDOW_HOURS` (off by default) is how far back a
+///   user's own tweets count as duplicates, handled per
+///   `FILTER_DUPLICATE_ACTION` (`hold` by default). Replies are exempt.
+///
+/// Each list can also be read from a file named by the same key with a
+/// `_FILE` suffix.
+pub struct ContentFilters {
+    filters: Vec<Filter>,
+}
+
+impl ContentFilters {
+    pub fn from_env() -> Self {
+        let mut filters = vec![];
+
+        for (key, action) in [
+            ("FILTER_WORDS_REJECT", FilterAction::Reject),
+            ("FILTER_WORDS_HOLD", FilterAction::Hold),
+            ("FILTER_WORDS_LABEL", FilterAction::Label),
+        ] {
+            let filter = WordFilter::new(action, read_list(key));
+            if !filter.is_empty() {
+                filters.push(Filter::Words(filter));
+            }
+        }
+
+        let links = LinkFilter::new(
+            env_or("FILTER_BLOCKED_DOMAINS_ACTION", FilterAction::Reject),
+            read_list("FILTER_BLOCKED_DOMAINS"),
+        );
+        if !links.is_empty() {
+            filters.push(Filter::Links(links));
+        }
+
+        let window_hours = env_or("FILTER_DUPLICATE_WINDOW_HOURS", 0_i64);
+        if window_hours > 0 {
+            filters.push(Filter::Duplicates(DuplicateFilter::new(
+                env_or("FILTER_DUPLICATE_ACTION", FilterAction::Hold),
+                window_hours * 60 * 60 * 1000,
+            )));
+        }
+
+        ContentFilters { filters }
+    }
+
+    /// Runs every filter over `submission`. The harshest verdict wins: a
+    /// rejection comes back as the matching error, otherwise the tweet may
+    /// be held for review and/or labelled sensitive.
+    pub async fn screen(
+        &self,
+        data: web::Data<Tweetbook>,
+        submission: &Submission<'_>,
+    ) -> Result<Screening, UserError> {
+        let mut screening = Screening {
+            fingerprint: fingerprint(submission.text),
+            ..Screening::default()
+        };
+        let mut verdicts = vec![];
+
+        for filter in &self.filters {
+            match filter
+                .check(data.clone(), submission, &screening.fingerprint)
+                .await
+            {
+                Ok(Some(verdict)) => verdicts.push(verdict),
+                Ok(None) => {}
+                Err(_) => return Err(UserError::InternalServerError),
+            }
+        }
+
+        if let Some(rejected) = verdicts
+            .iter()
+            .find(|verdict| verdict.action == FilterAction::Reject)
+        {
+            return Err(rejected.reason.error());
+        }
+
+        for verdict in verdicts {
+            match verdict.action {
+                FilterAction::Hold => screening.hold = true,
+                FilterAction::Label => screening.sensitive = true,
+                FilterAction::Reject => {}
+            }
+            if !screening.reasons.contains(&verdict.reason) {
+                screening.reasons.push(verdict.reason);
+            }
+        }
+
+        Ok(screening)
+    }
+}
@@ -0,0 +1,169 @@
+use sha2::{Digest, Sha256};
+
+/// Appends the plain lowercase form of `c` to `out`: look-alike letters
+/// from other scripts, accented, fullwidth, circled and mathematical
+/// letters become the ASCII letter they imitate, and invisible characters
+/// are dropped.
+fn fold(c: char, out: &mut String) {
+    let code = c as u32;
+    let folded = match code {
+        // Zero-width characters, soft hyphens and combining marks.
+        0x200B..=0x200F | 0x2060 | 0xFEFF | 0x00AD | 0x0300..=0x036F => return,
+        // Fullwidth ASCII.
+        0xFF01..=0xFF5E => char::from_u32(code - 0xFEE0),
+        // Circled letters.
+        0x24B6..=0x24CF => char::from_u32('a' as u32 + code - 0x24B6),
+        0x24D0..=0x24E9 => char::from_u32('a' as u32 + code - 0x24D0),
+        // Mathematical alphanumeric symbols, in runs of A-Z then a-z.
+        0x1D400..=0x1D6A3 => char::from_u32('a' as u32 + (code - 0x1D400) % 52 % 26),
+        0x1D7CE..=0x1D7FF => char::from_u32('0' as u32 + (code - 0x1D7CE) % 10),
+        _ => None,
+    };
+    if let Some(folded) = folded {
+        out.extend(folded.to_lowercase());
+        return;
+    }
+
+    for c in c.to_lowercase() {
+        let ascii = match c {
+            'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' | 'ă' | 'ą' | 'а' | 'α' | 'ɑ' => {
+                'a'
+            }
+            'в' | 'β' | 'ь' => 'b',
+            'ç' | 'ć' | 'č' | 'с' | 'ϲ' => 'c',
+            'ď' | 'đ' | 'ԁ' => 'd',
+            'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ę' | 'ě' | 'е' | 'ё' | 'ε' => 'e',
+            'ğ' | 'ɡ' => 'g',
+            'н' | 'һ' => 'h',
+            'ì' | 'í' | 'î' | 'ï' | 'ī' | 'ı' | 'і' | 'ї' | 'ι' => 'i',
+            'ј' => 'j',
+            'к' | 'κ' => 'k',
+            'ł' | 'ӏ' => 'l',
+            'м' => 'm',
+            'ñ' | 'ń' | 'ň' | 'п' | 'η' => 'n',
+            'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' | 'о' | 'ο' | 'σ' => 'o',
+            'р' | 'ρ' => 'p',
+            'ř' | 'г' => 'r',
+            'ś' | 'š' | 'ş' | 'ѕ' => 's',
+            'ť' | 'т' | 'τ' => 't',
+            'ù' | 'ú' | 'û' | 'ü' | 'ū' | 'ů' | 'υ' => 'u',
+            'ν' => 'v',
+            'ш' | 'ω' => 'w',
+            'х' | 'χ' => 'x',
+            'ý' | 'ÿ' | 'у' | 'γ' => 'y',
+            'ž' | 'ź' | 'ż' => 'z',
+            'ß' => {
+                out.push_str("ss");
+                continue;
+            }
+            c => c,
+        };
+        out.push(ascii);
+    }
+}
+
+/// The letter a digit or symbol stands in for in leetspeak.
+fn unleet(c: char) -> char {
+    match c {
+        '0' => 'o',
+        '1' | '!' => 'i',
+        '3' => 'e',
+        '4' | '@' => 'a',
+        '5' | '$' => 's',
+        '7' | '+' => 't',
+        '8' => 'b',
+        '9' => 'g',
+        '|' => 'l',
+        c => c,
+    }
+}
+
+fn is_leet_symbol(c: char) -> bool {
+    "@$!|+".contains(c)
+}
+
+/// Words of `text` after folding look-alike characters, lowercasing and
+/// undoing leetspeak, so `Ｈ3ll0` and `hello` compare equal. Digits and
+/// symbols are only read as letters inside words that have letters, and
+/// symbols only when a letter or digit follows them.
+pub fn words(text: &str) -> Vec<String> {
+    let mut folded = String::with_capacity(text.len());
+    for c in text.chars() {
+        fold(c, &mut folded);
+    }
+    let chars: Vec<char> = folded.chars().collect();
+
+    let mut words = vec![];
+    let mut word = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        let next_is_alphanumeric = chars.get(i + 1).is_some_and(|c| c.is_alphanumeric());
+
+        if c.is_alphanumeric() || (is_leet_symbol(c) && next_is_alphanumeric) {
+            word.push(c);
+        } else if !word.is_empty() {
+            words.push(std::mem::take(&mut word));
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+
+    words
+        .into_iter()
+        .map(|word| {
+            if word.chars().any(|c| c.is_alphabetic()) {
+                word.chars().map(unleet).collect()
+            } else {
+                word
+            }
+        })
+        .collect()
+}
+
+/// `text` reduced to its normalized words, for comparing tweets that only
+/// differ in case, spacing, punctuation or disguised characters.
+pub fn normalize(text: &str) -> String {
+    words(text).join(" ")
+}
+
+/// Stable hash of the normalized text, stored with each tweet to find
+/// duplicates.
+pub fn fingerprint(text: &str) -> String {
+    hex::encode(Sha256::digest(normalize(text).as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_and_lowercases_words() {
+        assert_eq!(words("Hello, World!  again"), ["hello", "world", "again"]);
+        assert_eq!(words(""), Vec::<String>::new());
+    }
+
+    #[test]
+    fn undoes_leetspeak_only_inside_words() {
+        assert_eq!(words("H3ll0 w0rld"), ["hello", "world"]);
+        assert_eq!(words("$p@m"), ["spam"]);
+        assert_eq!(words("r00m 101"), ["room", "101"]);
+        assert_eq!(words("wow! $ 100"), ["wow", "100"]);
+    }
+
+    #[test]
+    fn folds_look_alike_characters() {
+        assert_eq!(words("Ｈｅｌｌｏ"), ["hello"]);
+        assert_eq!(words("ⓗⓔⓛⓛⓞ"), ["hello"]);
+        assert_eq!(words("𝐡𝐞𝐥𝐥𝐨"), ["hello"]);
+        assert_eq!(words("hеllо"), ["hello"]);
+        assert_eq!(words("héllö straße"), ["hello", "strasse"]);
+        assert_eq!(words("he\u{200B}llo"), ["hello"]);
+    }
+
+    #[test]
+    fn fingerprints_equivalent_text_alike() {
+        assert_eq!(normalize("  Buy   NOW!!! "), "buy now");
+        assert_eq!(fingerprint("Buy now"), fingerprint("BUY... n0w"));
+        assert_ne!(fingerprint("Buy now"), fingerprint("Buy later"));
+    }
+}
@@ -0,0 +1,165 @@
+use super::{normalize::words, FilterAction, FilterReason, Submission, Verdict};
+
+/// Matches words and phrases from a list against the normalized text of a
+/// tweet, whole words only so that banning `ass` leaves `class` alone.
+/// Letters stretched out (`baaad`) or spelled out with separators
+/// (`b a d`, `b.a.d`) still match.
+pub struct WordFilter {
+    action: FilterAction,
+    entries: Vec<Vec<String>>,
+}
+
+/// `word` with runs of the same letter collapsed to one.
+fn squeeze(word: &str) -> String {
+    let mut squeezed = String::with_capacity(word.len());
+    for c in word.chars() {
+        if !squeezed.ends_with(c) {
+            squeezed.push(c);
+        }
+    }
+    squeezed
+}
+
+fn word_matches(word: &str, banned: &str) -> bool {
+    word == banned || (word.len() > banned.len() && squeeze(word) == squeeze(banned))
+}
+
+/// `words` with each run of two or more single letters joined into one
+/// word.
+fn join_spelled_out(words: &[String]) -> Vec<String> {
+    let mut joined: Vec<String> = vec![];
+    let mut run = String::new();
+    let mut run_len = 0;
+
+    for word in words {
+        if word.chars().count() == 1 {
+            run.push_str(word);
+            run_len += 1;
+            continue;
+        }
+        if run_len > 1 {
+            joined.push(std::mem::take(&mut run));
+        }
+        run.clear();
+        run_len = 0;
+        joined.push(word.clone());
+    }
+    if run_len > 1 {
+        joined.push(run);
+    }
+
+    joined
+}
+
+impl WordFilter {
+    pub fn new(action: FilterAction, list: Vec<String>) -> Self {
+        let entries = list
+            .iter()
+            .map(|entry| words(entry))
+            .filter(|entry| !entry.is_empty())
+            .collect();
+
+        WordFilter { action, entries }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn contains(&self, words: &[String]) -> bool {
+        self.entries.iter().any(|entry| {
+            words.len() >= entry.len()
+                && words.windows(entry.len()).any(|window| {
+                    window
+                        .iter()
+                        .zip(entry)
+                        .all(|(word, banned)| word_matches(word, banned))
+                })
+        })
+    }
+
+    pub fn check(&self, submission: &Submission<'_>) -> Option<Verdict> {
+        let words = words(submission.text);
+
+        if self.contains(&words) || self.contains(&join_spelled_out(&words)) {
+            Some(Verdict {
+                action: self.action,
+                reason: FilterReason::BannedWord,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::oid::ObjectId;
+
+    use super::*;
+
+    fn flags(filter: &WordFilter, text: &str) -> bool {
+        filter
+            .check(&Submission {
+                author: ObjectId::new(),
+                text,
+                tweet: None,
+                reply_to: None,
+            })
+            .is_some()
+    }
+
+    fn filter(list: &[&str]) -> WordFilter {
+        WordFilter::new(
+            FilterAction::Hold,
+            list.iter().map(|entry| entry.to_string()).collect(),
+        )
+    }
+
+    #[test]
+    fn matches_whole_words_only() {
+        let filter = filter(&["ass"]);
+        assert!(flags(&filter, "what an ass."));
+        assert!(!flags(&filter, "first class"));
+        assert!(!flags(&filter, "assess"));
+    }
+
+    #[test]
+    fn matches_disguised_words() {
+        let filter = filter(&["bad"]);
+        assert!(flags(&filter, "so B4D"));
+        assert!(flags(&filter, "baaaad"));
+        assert!(flags(&filter, "b a d"));
+        assert!(flags(&filter, "b.a.d"));
+        assert!(flags(&filter, "ｂａｄ"));
+        assert!(!flags(&filter, "bald"));
+    }
+
+    #[test]
+    fn matches_phrases() {
+        let filter = filter(&["buy followers"]);
+        assert!(flags(&filter, "Buy   F0LLOWERS today"));
+        assert!(!flags(&filter, "followers buy"));
+        assert!(!flags(&filter, "buy"));
+    }
+
+    #[test]
+    fn reports_its_action() {
+        let verdict = filter(&["bad"])
+            .check(&Submission {
+                author: ObjectId::new(),
+                text: "bad",
+                tweet: None,
+                reply_to: None,
+            })
+            .unwrap();
+        assert_eq!(verdict.action, FilterAction::Hold);
+        assert_eq!(verdict.reason, FilterReason::BannedWord);
+    }
+
+    #[test]
+    fn ignores_empty_entries() {
+        assert!(filter(&["", "!!"]).is_empty());
+        assert!(!filter(&["", "bad"]).is_empty());
+    }
+}
@@ -0,0 +1,5 @@
+pub mod purge;
+pub mod scheduler;
+pub mod spam;
+pub mod trends;
+pub mod unfurl;
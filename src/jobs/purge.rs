@@ -0,0 +1,23 @@
+use actix_web::{rt, web};
+
+use crate::{
+    models::{init::Tweetbook, messages::Message},
+    utils::config::env_interval,
+};
+
+/// Periodically hard-deletes tweets whose trash retention has elapsed.
+pub fn spawn(data: web::Data<Tweetbook>) {
+    let every = env_interval("TWEET_PURGE_INTERVAL_SECONDS", 60 * 60);
+
+    rt::spawn(async move {
+        let mut interval = rt::time::interval(every);
+
+        loop {
+            interval.tick().await;
+
+            if let Err(error) = Message::purge_deleted(data.clone()).await {
+                println!("Failed to purge deleted tweets: {:?}", error);
+            }
+        }
+    });
+}
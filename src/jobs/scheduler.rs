@@ -0,0 +1,24 @@
+use actix_web::{rt, web};
+
+use crate::{
+    models::{drafts::Draft, init::Tweetbook},
+    utils::config::env_interval,
+};
+
+/// Posts scheduled tweets once their publish time has come, checking every
+/// `SCHEDULER_INTERVAL_SECONDS`.
+pub fn spawn(data: web::Data<Tweetbook>) {
+    let every = env_interval("SCHEDULER_INTERVAL_SECONDS", 30);
+
+    rt::spawn(async move {
+        let mut interval = rt::time::interval(every);
+
+        loop {
+            interval.tick().await;
+
+            if let Err(error) = Draft::publish_due(data.clone()).await {
+                println!("Failed to publish scheduled tweets: {:?}", error);
+            }
+        }
+    });
+}
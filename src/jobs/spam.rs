@@ -0,0 +1,24 @@
+use actix_web::{rt, web};
+
+use crate::{
+    models::{init::Tweetbook, spam::Activity},
+    utils::config::env_interval,
+};
+
+/// Periodically rescores recently active users and updates their spam
+/// restrictions.
+pub fn spawn(data: web::Data<Tweetbook>) {
+    let every = env_interval("SPAM_SCORE_INTERVAL_SECONDS", 60);
+
+    rt::spawn(async move {
+        let mut interval = rt::time::interval(every);
+
+        loop {
+            interval.tick().await;
+
+            if let Err(error) = Activity::score_users(data.clone()).await {
+                println!("Failed to score spam activity: {:?}", error);
+            }
+        }
+    });
+}
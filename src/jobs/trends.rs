@@ -0,0 +1,23 @@
+use actix_web::{rt, web};
+
+use crate::{
+    models::{init::Tweetbook, trends::Trends},
+    utils::config::env_interval,
+};
+
+/// Periodically recomputes the trending hashtags snapshot.
+pub fn spawn(data: web::Data<Tweetbook>) {
+    let every = env_interval("TRENDS_REFRESH_SECONDS", 5 * 60);
+
+    rt::spawn(async move {
+        let mut interval = rt::time::interval(every);
+
+        loop {
+            interval.tick().await;
+
+            if let Err(error) = Trends::refresh(data.clone()).await {
+                println!("Failed to refresh trends: {:?}", error);
+            }
+        }
+    });
+}
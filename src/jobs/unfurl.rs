@@ -0,0 +1,14 @@
+use actix_web::{rt, web};
+use mongodb::bson::oid::ObjectId;
+
+use crate::models::{cards::Card, init::Tweetbook};
+
+/// Fetches the link preview for a just posted or edited tweet in the
+/// background, so posting never waits on someone else's server.
+pub fn spawn(data: web::Data<Tweetbook>, tweet: ObjectId, url: String) {
+    rt::spawn(async move {
+        if let Err(error) = Card::attach(data, tweet, url).await {
+            println!("Failed to attach link preview: {:?}", error);
+        }
+    });
+}
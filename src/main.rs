@@ -1,23 +1,98 @@
 mod api;
+mod events;
+mod filters;
+mod jobs;
 mod models;
+mod search;
+mod storage;
 mod utils;
 
 use actix_web::{web, App, HttpServer};
 use actix_web_lab::web::spa;
-use api::{auth::auth, messages::messages, user::user};
-use models::init::Tweetbook;
+use api::{
+    admin::admin, auth::auth, bookmarks::bookmarks, conversations::conversations, drafts::drafts,
+    hashtags::hashtags, lists::lists, media::media, messages::messages,
+    notifications::notifications, reports::reports, search::search, stream::stream, trends::trends,
+    user::user,
+};
+use models::{
+    bookmarks::Bookmark, cards::Card, conversations::Conversation, direct_messages::DirectMessage,
+    drafts::Draft, init::Tweetbook, lists::UserList, messages::Message, moderation::ModerationLog,
+    notifications::Notification, reports::Report, spam::Activity, users::User,
+};
+use search::TweetSearch;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     let db = Tweetbook::init().await;
     let db_data = web::Data::new(db);
 
+    if let Err(error) = User::ensure_indexes(db_data.clone()).await {
+        println!("Failed to prepare user search: {:?}", error);
+    }
+    if let Err(error) = User::bootstrap_admins(db_data.clone()).await {
+        println!("Failed to grant admin roles: {:?}", error);
+    }
+    if let Err(error) = Message::ensure_indexes(db_data.clone()).await {
+        println!("Failed to prepare duplicate detection: {:?}", error);
+    }
+    if let Err(error) = Notification::ensure_indexes(db_data.clone()).await {
+        println!("Failed to prepare notifications: {:?}", error);
+    }
+    if let Err(error) = Conversation::ensure_indexes(db_data.clone()).await {
+        println!("Failed to prepare conversations: {:?}", error);
+    }
+    if let Err(error) = DirectMessage::ensure_indexes(db_data.clone()).await {
+        println!("Failed to prepare direct messages: {:?}", error);
+    }
+    if let Err(error) = Bookmark::ensure_indexes(db_data.clone()).await {
+        println!("Failed to prepare bookmarks: {:?}", error);
+    }
+    if let Err(error) = UserList::ensure_indexes(db_data.clone()).await {
+        println!("Failed to prepare lists: {:?}", error);
+    }
+    if let Err(error) = Draft::ensure_indexes(db_data.clone()).await {
+        println!("Failed to prepare drafts: {:?}", error);
+    }
+    if let Err(error) = Report::ensure_indexes(db_data.clone()).await {
+        println!("Failed to prepare reports: {:?}", error);
+    }
+    if let Err(error) = ModerationLog::ensure_indexes(db_data.clone()).await {
+        println!("Failed to prepare moderation log: {:?}", error);
+    }
+    if let Err(error) = Activity::ensure_indexes(db_data.clone()).await {
+        println!("Failed to prepare spam detection: {:?}", error);
+    }
+    if let Err(error) = Card::ensure_indexes(db_data.clone()).await {
+        println!("Failed to prepare link previews: {:?}", error);
+    }
+    if let Err(error) = db_data.search.rebuild(db_data.clone()).await {
+        println!("Failed to prepare tweet search: {:?}", error);
+    }
+
+    jobs::purge::spawn(db_data.clone());
+    jobs::trends::spawn(db_data.clone());
+    jobs::scheduler::spawn(db_data.clone());
+    jobs::spam::spawn(db_data.clone());
+
     HttpServer::new(move || {
         App::new()
             .app_data(db_data.clone())
             .configure(auth) // Auth related routes
             .configure(messages) // Tweets related routes
             .configure(user) // User related routes
+            .configure(hashtags) // Hashtag related routes
+            .configure(trends) // Trending hashtags
+            .configure(search) // Tweet search
+            .configure(notifications) // Notifications
+            .configure(stream) // Real-time events
+            .configure(conversations) // Direct messages
+            .configure(media) // Image uploads
+            .configure(bookmarks) // Private bookmarks
+            .configure(lists) // Curated lists
+            .configure(drafts) // Drafts and scheduled tweets
+            .configure(reports) // Reporting tweets and users
+            .configure(admin) // Moderation
             .service(
                 spa()
                     .index_file("./public/index.html")
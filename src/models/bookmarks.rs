@@ -0,0 +1,332 @@
+use actix_web::web;
+use futures::StreamExt;
+use mongodb::{
+    bson::{doc, from_document, oid::ObjectId, DateTime, Document},
+    error::Error,
+    options::{FindOneAndUpdateOptions, FindOptions, IndexOptions, ReturnDocument, UpdateOptions},
+    Collection, IndexModel,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::utils::pagination::{Paginated, Pagination};
+
+use super::{init::Tweetbook, messages::Message};
+
+pub const MAX_FOLDER_NAME_CHARS: usize = 50;
+
+/// A named collection of bookmarks. Folders and bookmarks are only ever
+/// returned to their owner.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BookmarkFolder {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub name: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime,
+}
+
+#[derive(Debug, Deserialize)]
+struct BookmarkPage {
+    total: u64,
+    items: Vec<Message>,
+}
+
+/// Bookmarks live in their own collection, one document per user and
+/// tweet with an optional folder:
+/// `{ user, tweet, folder, createdAt }`.
+pub struct Bookmark;
+
+impl Bookmark {
+    pub fn get_collection<T>(data: web::Data<Tweetbook>) -> Collection<T> {
+        data.db.collection::<T>("bookmarks")
+    }
+
+    pub fn get_folder_collection<T>(data: web::Data<Tweetbook>) -> Collection<T> {
+        data.db.collection::<T>("bookmarkFolders")
+    }
+
+    pub async fn ensure_indexes(data: web::Data<Tweetbook>) -> Result<(), Error> {
+        let bookmarks = Self::get_collection::<Document>(data.clone())
+            .create_indexes(
+                vec![
+                    IndexModel::builder()
+                        .keys(doc! { "tweet": 1, "user": 1 })
+                        .options(IndexOptions::builder().unique(true).build())
+                        .build(),
+                    IndexModel::builder()
+                        .keys(doc! { "user": 1, "folder": 1, "createdAt": -1 })
+                        .build(),
+                ],
+                None,
+            )
+            .await;
+        match bookmarks {
+            Ok(_) => {}
+            Err(error) => return Err(error),
+        }
+
+        let folders = Self::get_folder_collection::<Document>(data)
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "user": 1, "createdAt": 1 })
+                    .build(),
+                None,
+            )
+            .await;
+
+        match folders {
+            Ok(_) => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Trimmed folder name, if it is 1 to 50 characters long.
+    pub fn parse_folder_name(name: &str) -> Option<String> {
+        let name = name.trim();
+        if name.is_empty() || name.chars().count() > MAX_FOLDER_NAME_CHARS {
+            None
+        } else {
+            Some(name.to_string())
+        }
+    }
+
+    /// Bookmarks `tweet` for `user`, or moves an existing bookmark into
+    /// `folder` (out of any folder when `None`).
+    pub async fn add(
+        data: web::Data<Tweetbook>,
+        user: ObjectId,
+        tweet: ObjectId,
+        folder: Option<ObjectId>,
+    ) -> Result<(), Error> {
+        let added = Self::get_collection::<Document>(data)
+            .update_one(
+                doc! { "tweet": tweet, "user": user },
+                doc! {
+                    "$set": { "folder": folder },
+                    "$setOnInsert": { "createdAt": DateTime::now() },
+                },
+                UpdateOptions::builder().upsert(true).build(),
+            )
+            .await;
+
+        match added {
+            Ok(_) => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+
+    pub async fn remove(
+        data: web::Data<Tweetbook>,
+        user: ObjectId,
+        tweet: ObjectId,
+    ) -> Result<(), Error> {
+        let removed = Self::get_collection::<Document>(data)
+            .delete_one(doc! { "tweet": tweet, "user": user }, None)
+            .await;
+
+        match removed {
+            Ok(_) => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Drops every bookmark of tweets that are gone for good.
+    pub async fn remove_for_tweets(
+        data: web::Data<Tweetbook>,
+        tweets: &[ObjectId],
+    ) -> Result<(), Error> {
+        let removed = Self::get_collection::<Document>(data)
+            .delete_many(doc! { "tweet": { "$in": tweets } }, None)
+            .await;
+
+        match removed {
+            Ok(_) => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Newest-first page of `user`'s bookmarked tweets that are still
+    /// visible to them, optionally only those in `folder`.
+    pub async fn get_bookmarks(
+        data: web::Data<Tweetbook>,
+        user: ObjectId,
+        folder: Option<ObjectId>,
+        pagination: &Pagination,
+    ) -> Result<Paginated<Message>, Error> {
+        let mut filter = doc! { "user": user };
+        if let Some(folder) = folder {
+            filter.insert("folder", folder);
+        }
+
+        let pages = Self::get_collection::<Document>(data)
+            .aggregate(
+                vec![
+                    doc! { "$match": filter },
+                    doc! { "$sort": { "createdAt": -1 } },
+                    doc! {
+                        "$lookup": {
+                            "from": "messages",
+                            "localField": "tweet",
+                            "foreignField": "_id",
+                            "pipeline": Message::enrichment_stages(user),
+                            "as": "tweet",
+                        }
+                    },
+                    doc! { "$unwind": "$tweet" },
+                    doc! {
+                        "$facet": {
+                            "total": [{ "$count": "count" }],
+                            "items": [
+                                { "$skip": pagination.skip() as i64 },
+                                { "$limit": pagination.limit() as i64 },
+                                { "$replaceRoot": { "newRoot": "$tweet" } },
+                            ],
+                        }
+                    },
+                    doc! {
+                        "$project": {
+                            "total": { "$ifNull": [{ "$first": "$total.count" }, 0] },
+                            "items": 1,
+                        }
+                    },
+                ],
+                None,
+            )
+            .await;
+
+        match pages {
+            Ok(mut cursor) => match cursor.next().await {
+                Some(Ok(page)) => {
+                    let page: BookmarkPage = from_document(page).unwrap();
+                    Ok(Paginated::new(page.items, page.total, pagination))
+                }
+                Some(Err(error)) => Err(error),
+                None => Ok(Paginated::new(vec![], 0, pagination)),
+            },
+            Err(error) => Err(error),
+        }
+    }
+
+    pub async fn get_folders(
+        data: web::Data<Tweetbook>,
+        user: ObjectId,
+    ) -> Result<Vec<BookmarkFolder>, Error> {
+        let cursor = Self::get_folder_collection::<BookmarkFolder>(data)
+            .find(
+                doc! { "user": user },
+                FindOptions::builder().sort(doc! { "createdAt": 1 }).build(),
+            )
+            .await;
+
+        match cursor {
+            Ok(mut found) => {
+                let mut folders: Vec<BookmarkFolder> = vec![];
+
+                while let Some(res) = found.next().await {
+                    match res {
+                        Ok(folder) => folders.push(folder),
+                        Err(error) => return Err(error),
+                    }
+                }
+
+                Ok(folders)
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Whether `folder` exists and belongs to `user`.
+    pub async fn owns_folder(
+        data: web::Data<Tweetbook>,
+        user: ObjectId,
+        folder: ObjectId,
+    ) -> Result<bool, Error> {
+        let found = Self::get_folder_collection::<Document>(data)
+            .count_documents(doc! { "_id": folder, "user": user }, None)
+            .await;
+
+        match found {
+            Ok(count) => Ok(count > 0),
+            Err(error) => Err(error),
+        }
+    }
+
+    pub async fn create_folder(
+        data: web::Data<Tweetbook>,
+        user: ObjectId,
+        name: String,
+    ) -> Result<BookmarkFolder, Error> {
+        let folder = BookmarkFolder {
+            id: ObjectId::new(),
+            name,
+            created_at: DateTime::now(),
+        };
+
+        let inserted = Self::get_folder_collection::<Document>(data)
+            .insert_one(
+                doc! {
+                    "_id": folder.id,
+                    "user": user,
+                    "name": &folder.name,
+                    "createdAt": folder.created_at,
+                },
+                None,
+            )
+            .await;
+
+        match inserted {
+            Ok(_) => Ok(folder),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Renames one of `user`'s folders, returning `None` if there is no
+    /// such folder.
+    pub async fn rename_folder(
+        data: web::Data<Tweetbook>,
+        user: ObjectId,
+        folder: ObjectId,
+        name: String,
+    ) -> Result<Option<BookmarkFolder>, Error> {
+        Self::get_folder_collection::<BookmarkFolder>(data)
+            .find_one_and_update(
+                doc! { "_id": folder, "user": user },
+                doc! { "$set": { "name": name } },
+                FindOneAndUpdateOptions::builder()
+                    .return_document(ReturnDocument::After)
+                    .build(),
+            )
+            .await
+    }
+
+    /// Deletes one of `user`'s folders. The bookmarks in it are kept, just
+    /// no longer filed. Returns whether the folder existed.
+    pub async fn delete_folder(
+        data: web::Data<Tweetbook>,
+        user: ObjectId,
+        folder: ObjectId,
+    ) -> Result<bool, Error> {
+        let deleted = Self::get_folder_collection::<Document>(data.clone())
+            .delete_one(doc! { "_id": folder, "user": user }, None)
+            .await;
+
+        match deleted {
+            Ok(result) if result.deleted_count == 0 => Ok(false),
+            Ok(_) => {
+                let unfiled = Self::get_collection::<Document>(data)
+                    .update_many(
+                        doc! { "user": user, "folder": folder },
+                        doc! { "$set": { "folder": null } },
+                        None,
+                    )
+                    .await;
+
+                match unfiled {
+                    Ok(_) => Ok(true),
+                    Err(error) => Err(error),
+                }
+            }
+            Err(error) => Err(error),
+        }
+    }
+}
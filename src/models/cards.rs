@@ -0,0 +1,121 @@
+use std::time::Duration;
+
+use actix_web::web;
+use mongodb::{
+    bson::{doc, oid::ObjectId, to_bson, DateTime, Document},
+    error::Error,
+    options::{IndexOptions, UpdateOptions},
+    Collection, IndexModel,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::utils::{config::env_or, unfurl};
+
+use super::{init::Tweetbook, messages::Message};
+
+/// A link preview built from a page's Open Graph or Twitter card metadata.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Card {
+    pub url: String,
+    pub title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    #[serde(rename = "siteName", skip_serializing_if = "Option::is_none")]
+    pub site_name: Option<String>,
+}
+
+/// A cached unfurl; `card` is null for links that had nothing to show, so
+/// they aren't fetched again either.
+#[derive(Debug, Deserialize)]
+struct CachedCard {
+    card: Option<Card>,
+}
+
+impl Card {
+    pub fn get_collection<T>(data: web::Data<Tweetbook>) -> Collection<T> {
+        data.db.collection::<T>("linkCards")
+    }
+
+    /// Unfurled links are kept for `LINK_CARD_CACHE_HOURS` (a day by default).
+    pub async fn ensure_indexes(data: web::Data<Tweetbook>) -> Result<(), Error> {
+        let ttl = Duration::from_secs(env_or("LINK_CARD_CACHE_HOURS", 24) * 60 * 60);
+
+        let index = Self::get_collection::<Document>(data)
+            .create_indexes(
+                vec![
+                    IndexModel::builder()
+                        .keys(doc! { "url": 1 })
+                        .options(IndexOptions::builder().unique(true).build())
+                        .build(),
+                    IndexModel::builder()
+                        .keys(doc! { "fetchedAt": 1 })
+                        .options(IndexOptions::builder().expire_after(ttl).build())
+                        .build(),
+                ],
+                None,
+            )
+            .await;
+
+        match index {
+            Ok(_) => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// The preview for `url`, from the cache or freshly fetched.
+    pub async fn for_url(data: web::Data<Tweetbook>, url: &str) -> Result<Option<Card>, Error> {
+        let cached = Self::get_collection::<CachedCard>(data.clone())
+            .find_one(doc! { "url": url }, None)
+            .await;
+
+        match cached {
+            Ok(Some(cached)) => Ok(cached.card),
+            Ok(None) => {
+                let card = unfurl::fetch(url).await;
+
+                let stored = Self::get_collection::<Document>(data)
+                    .update_one(
+                        doc! { "url": url },
+                        doc! { "$set": { "card": to_bson(&card).unwrap(), "fetchedAt": DateTime::now() } },
+                        UpdateOptions::builder().upsert(true).build(),
+                    )
+                    .await;
+
+                match stored {
+                    Ok(_) => Ok(card),
+                    Err(error) => Err(error),
+                }
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Sets the preview for `url` on `tweet`, unless the tweet was edited
+    /// to link somewhere else in the meantime.
+    pub async fn attach(
+        data: web::Data<Tweetbook>,
+        tweet: ObjectId,
+        url: String,
+    ) -> Result<(), Error> {
+        let card = match Self::for_url(data.clone(), &url).await {
+            Ok(Some(card)) => card,
+            Ok(None) => return Ok(()),
+            Err(error) => return Err(error),
+        };
+
+        let attached = Message::get_collection::<Document>(data)
+            .update_one(
+                doc! { "_id": tweet, "entities.urls.0.url": url },
+                doc! { "$set": { "card": to_bson(&card).unwrap() } },
+                None,
+            )
+            .await;
+
+        match attached {
+            Ok(_) => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+}
@@ -0,0 +1,314 @@
+use actix_web::web;
+use futures::StreamExt;
+use mongodb::{
+    bson::{doc, from_document, oid::ObjectId, DateTime, Document},
+    error::Error,
+    Collection, IndexModel,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::utils::{
+    config::env_or,
+    pagination::{Paginated, Pagination},
+};
+
+use super::{
+    direct_messages::DirectMessage,
+    init::Tweetbook,
+    users::{MinUser, User},
+};
+
+/// Preview of the latest message the viewer hasn't deleted for themselves.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LastMessage {
+    pub sender: ObjectId,
+    pub text: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime,
+}
+
+/// A one-to-one or small-group conversation as seen by one participant.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Conversation {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub participants: Vec<MinUser>,
+    #[serde(rename = "lastMessage", skip_serializing_if = "Option::is_none")]
+    pub last_message: Option<LastMessage>,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: DateTime,
+    /// Messages from others since the viewer last read the conversation.
+    #[serde(default)]
+    pub unread: u64,
+}
+
+/// A conversation as stored, with participant ids only.
+#[derive(Debug, Deserialize)]
+pub struct StoredConversation {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub participants: Vec<ObjectId>,
+}
+
+impl Conversation {
+    pub fn get_collection<T>(data: web::Data<Tweetbook>) -> Collection<T> {
+        data.db.collection::<T>("conversations")
+    }
+
+    /// Largest number of people, including its creator, in a conversation.
+    pub fn max_participants() -> usize {
+        env_or("DM_MAX_PARTICIPANTS", 10_usize)
+    }
+
+    pub async fn ensure_indexes(data: web::Data<Tweetbook>) -> Result<(), Error> {
+        let index = Self::get_collection::<Document>(data)
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "participants": 1, "updatedAt": -1 })
+                    .build(),
+                None,
+            )
+            .await;
+
+        match index {
+            Ok(_) => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Where a participant's read marker is stored on the conversation.
+    fn last_read_field(user: ObjectId) -> String {
+        format!("lastRead.{}", user.to_hex())
+    }
+
+    /// Starts a conversation between `participants`. A one-to-one
+    /// conversation that already exists is returned instead of a new one.
+    pub async fn start(
+        data: web::Data<Tweetbook>,
+        participants: Vec<ObjectId>,
+    ) -> Result<ObjectId, Error> {
+        let collection = Self::get_collection::<Document>(data);
+
+        if participants.len() == 2 {
+            let existing = collection
+                .find_one(
+                    doc! {
+                        "participants": { "$all": &participants, "$size": 2 }
+                    },
+                    None,
+                )
+                .await;
+
+            match existing {
+                Ok(Some(conversation)) => return Ok(conversation.get_object_id("_id").unwrap()),
+                Ok(None) => {}
+                Err(error) => return Err(error),
+            }
+        }
+
+        let now = DateTime::now();
+        let conversation = collection
+            .insert_one(
+                doc! {
+                    "participants": &participants,
+                    "lastRead": {},
+                    "createdAt": now,
+                    "updatedAt": now,
+                },
+                None,
+            )
+            .await;
+
+        match conversation {
+            Ok(inserted) => Ok(inserted.inserted_id.as_object_id().unwrap()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// The conversation `id`, if `user` takes part in it.
+    pub async fn get_for_participant(
+        data: web::Data<Tweetbook>,
+        id: ObjectId,
+        user: ObjectId,
+    ) -> Result<Option<StoredConversation>, Error> {
+        Self::get_collection::<StoredConversation>(data)
+            .find_one(doc! { "_id": id, "participants": user }, None)
+            .await
+    }
+
+    fn view_stages(viewer: ObjectId) -> Vec<Document> {
+        vec![
+            doc! {
+                "$lookup": {
+                    "from": "direct_messages",
+                    "let": {
+                        "conversation": "$_id",
+                        "lastRead": { "$ifNull": [format!("${}", Self::last_read_field(viewer)), DateTime::MIN] },
+                    },
+                    "pipeline": [
+                        {
+                            "$match": {
+                                "sender": { "$ne": viewer },
+                                "hiddenFor": { "$ne": viewer },
+                                "$expr": {
+                                    "$and": [
+                                        { "$eq": ["$conversation", "$$conversation"] },
+                                        { "$gt": ["$createdAt", "$$lastRead"] },
+                                    ]
+                                },
+                            }
+                        },
+                        { "$count": "count" },
+                    ],
+                    "as": "unreadCount",
+                }
+            },
+            doc! {
+                "$addFields": {
+                    "unread": { "$toLong": { "$ifNull": [{ "$first": "$unreadCount.count" }, 0] } }
+                }
+            },
+            doc! {
+                "$lookup": {
+                    "from": "direct_messages",
+                    "let": { "conversation": "$_id" },
+                    "pipeline": [
+                        {
+                            "$match": {
+                                "hiddenFor": { "$ne": viewer },
+                                "$expr": { "$eq": ["$conversation", "$$conversation"] },
+                            }
+                        },
+                        { "$sort": { "createdAt": -1 } },
+                        { "$limit": 1 },
+                        { "$project": { "_id": 0, "sender": 1, "text": 1, "createdAt": 1 } },
+                    ],
+                    "as": "latest",
+                }
+            },
+            doc! {
+                "$addFields": {
+                    "lastMessage": { "$ifNull": [{ "$first": "$latest" }, null] }
+                }
+            },
+            User::min_user_lookup("participants", viewer, "participants"),
+            doc! { "$project": { "lastRead": 0, "unreadCount": 0, "latest": 0 } },
+        ]
+    }
+
+    /// The conversation `id` as seen by `viewer`.
+    pub async fn get_conversation(
+        data: web::Data<Tweetbook>,
+        id: ObjectId,
+        viewer: ObjectId,
+    ) -> Result<Option<Conversation>, Error> {
+        let mut pipeline = vec![doc! { "$match": { "_id": id, "participants": viewer } }];
+        pipeline.extend(Self::view_stages(viewer));
+
+        let cursor = Self::get_collection::<Document>(data)
+            .aggregate(pipeline, None)
+            .await;
+
+        match cursor {
+            Ok(mut conversations) => match conversations.next().await {
+                Some(Ok(conversation)) => Ok(Some(from_document(conversation).unwrap())),
+                Some(Err(error)) => Err(error),
+                None => Ok(None),
+            },
+            Err(error) => Err(error),
+        }
+    }
+
+    /// `user`'s conversations, most recently active first.
+    pub async fn get_conversations(
+        data: web::Data<Tweetbook>,
+        user: ObjectId,
+        pagination: &Pagination,
+    ) -> Result<Paginated<Conversation>, Error> {
+        let filter = doc! { "participants": user };
+
+        let total = Self::get_collection::<Document>(data.clone())
+            .count_documents(filter.clone(), None)
+            .await;
+
+        match total {
+            Ok(total) => {
+                let mut pipeline = vec![
+                    doc! { "$match": filter },
+                    doc! { "$sort": { "updatedAt": -1 } },
+                    doc! { "$skip": pagination.skip() as i64 },
+                    doc! { "$limit": pagination.limit() as i64 },
+                ];
+                pipeline.extend(Self::view_stages(user));
+
+                let cursor = Self::get_collection::<Document>(data)
+                    .aggregate(pipeline, None)
+                    .await;
+
+                match cursor {
+                    Ok(mut conversations) => {
+                        let mut items: Vec<Conversation> = vec![];
+
+                        while let Some(res) = conversations.next().await {
+                            items.push(from_document(res.unwrap()).unwrap());
+                        }
+
+                        Ok(Paginated::new(items, total, pagination))
+                    }
+                    Err(error) => Err(error),
+                }
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Bumps the conversation to `message`; its sender has read everything
+    /// up to it. The preview isn't stored, since what it shows depends on
+    /// which messages each participant deleted for themselves.
+    pub async fn touch(data: web::Data<Tweetbook>, message: &DirectMessage) -> Result<(), Error> {
+        let sender = match &message.sender {
+            Some(sender) => sender.id,
+            None => return Ok(()),
+        };
+
+        let updated = Self::get_collection::<Document>(data)
+            .update_one(
+                doc! { "_id": message.conversation },
+                doc! {
+                    "$set": {
+                        "updatedAt": message.created_at,
+                        Self::last_read_field(sender): message.created_at,
+                    },
+                    "$unset": { "lastMessage": "" },
+                },
+                None,
+            )
+            .await;
+
+        match updated {
+            Ok(_) => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+
+    pub async fn mark_read(
+        data: web::Data<Tweetbook>,
+        id: ObjectId,
+        user: ObjectId,
+    ) -> Result<(), Error> {
+        let updated = Self::get_collection::<Document>(data)
+            .update_one(
+                doc! { "_id": id, "participants": user },
+                doc! { "$set": { Self::last_read_field(user): DateTime::now() } },
+                None,
+            )
+            .await;
+
+        match updated {
+            Ok(_) => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+}
@@ -0,0 +1,256 @@
+use actix_web::web;
+use futures::StreamExt;
+use mongodb::{
+    bson::{doc, from_document, oid::ObjectId, DateTime, Document},
+    error::Error,
+    Collection, Cursor, IndexModel,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    events::{BusEvent, Channel},
+    utils::pagination::{Paginated, Pagination},
+};
+
+use super::{
+    conversations::{Conversation, StoredConversation},
+    init::Tweetbook,
+    users::{MinUser, User},
+};
+
+pub const MAX_DIRECT_MESSAGE_CHARS: usize = 1000;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DirectMessage {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub conversation: ObjectId,
+    pub sender: Option<MinUser>,
+    pub text: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime,
+    #[serde(rename = "isMine", default)]
+    pub is_mine: bool,
+}
+
+impl DirectMessage {
+    pub fn get_collection<T>(data: web::Data<Tweetbook>) -> Collection<T> {
+        data.db.collection::<T>("direct_messages")
+    }
+
+    pub async fn ensure_indexes(data: web::Data<Tweetbook>) -> Result<(), Error> {
+        let index = Self::get_collection::<Document>(data)
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "conversation": 1, "createdAt": -1 })
+                    .build(),
+                None,
+            )
+            .await;
+
+        match index {
+            Ok(_) => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+
+    async fn parse_aggrigate(cursor: Result<Cursor<Document>, Error>) -> Result<Vec<Self>, Error> {
+        match cursor {
+            Ok(mut messages) => {
+                let mut result: Vec<Self> = vec![];
+
+                while let Some(res) = messages.next().await {
+                    result.push(from_document(res.unwrap()).unwrap());
+                }
+                Ok(result)
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Stages resolving the sender and viewer-relative fields.
+    fn view_stages(viewer: ObjectId) -> Vec<Document> {
+        vec![
+            doc! {
+                "$addFields": {
+                    "isMine": { "$eq": ["$sender", viewer] }
+                }
+            },
+            User::min_user_lookup("sender", viewer, "sender"),
+            doc! {
+                "$addFields": {
+                    "sender": { "$first": "$sender" }
+                }
+            },
+            doc! { "$project": { "hiddenFor": 0, "conversationDoc": 0 } },
+        ]
+    }
+
+    /// Messages `viewer` hasn't deleted for themselves and whose sender they
+    /// haven't blocked.
+    async fn visible_filter(
+        data: web::Data<Tweetbook>,
+        viewer: ObjectId,
+    ) -> Result<Document, Error> {
+        match User::get_relation_ids(data, viewer, "blocked").await {
+            Ok(blocked) => Ok(doc! {
+                "hiddenFor": { "$ne": viewer },
+                "sender": { "$nin": blocked },
+            }),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// The message `id` if `viewer` takes part in its conversation and may
+    /// see it.
+    pub async fn get_visible(
+        data: web::Data<Tweetbook>,
+        id: ObjectId,
+        viewer: ObjectId,
+    ) -> Result<Option<Self>, Error> {
+        let mut filter = match Self::visible_filter(data.clone(), viewer).await {
+            Ok(filter) => filter,
+            Err(error) => return Err(error),
+        };
+        filter.insert("_id", id);
+
+        let mut pipeline = vec![
+            doc! { "$match": filter },
+            doc! {
+                "$lookup": {
+                    "from": "conversations",
+                    "localField": "conversation",
+                    "foreignField": "_id",
+                    "as": "conversationDoc",
+                }
+            },
+            doc! { "$match": { "conversationDoc.participants": viewer } },
+        ];
+        pipeline.extend(Self::view_stages(viewer));
+
+        let messages = Self::get_collection::<Document>(data)
+            .aggregate(pipeline, None)
+            .await;
+
+        match Self::parse_aggrigate(messages).await {
+            Ok(mut messages) => Ok(messages.pop()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Newest-first page of a conversation's messages as seen by `viewer`.
+    pub async fn get_messages(
+        data: web::Data<Tweetbook>,
+        conversation: ObjectId,
+        viewer: ObjectId,
+        pagination: &Pagination,
+    ) -> Result<Paginated<Self>, Error> {
+        let mut filter = match Self::visible_filter(data.clone(), viewer).await {
+            Ok(filter) => filter,
+            Err(error) => return Err(error),
+        };
+        filter.insert("conversation", conversation);
+
+        let total = Self::get_collection::<Document>(data.clone())
+            .count_documents(filter.clone(), None)
+            .await;
+
+        match total {
+            Ok(total) => {
+                let mut pipeline = vec![
+                    doc! { "$match": filter },
+                    doc! { "$sort": { "createdAt": -1 } },
+                    doc! { "$skip": pagination.skip() as i64 },
+                    doc! { "$limit": pagination.limit() as i64 },
+                ];
+                pipeline.extend(Self::view_stages(viewer));
+
+                let messages = Self::get_collection::<Document>(data)
+                    .aggregate(pipeline, None)
+                    .await;
+
+                match Self::parse_aggrigate(messages).await {
+                    Ok(items) => Ok(Paginated::new(items, total, pagination)),
+                    Err(error) => Err(error),
+                }
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Sends `text` from `sender` to a conversation they take part in and
+    /// pushes it to every participant's open streams. `None` if the message
+    /// is gone by the time it's read back, e.g. deleted concurrently.
+    pub async fn send(
+        data: web::Data<Tweetbook>,
+        conversation: &StoredConversation,
+        sender: ObjectId,
+        text: String,
+    ) -> Result<Option<Self>, Error> {
+        let inserted = Self::get_collection::<Document>(data.clone())
+            .insert_one(
+                doc! {
+                    "conversation": conversation.id,
+                    "sender": sender,
+                    "text": text,
+                    "createdAt": DateTime::now(),
+                    "hiddenFor": [],
+                },
+                None,
+            )
+            .await;
+
+        match inserted {
+            Ok(inserted) => {
+                let id = inserted.inserted_id.as_object_id().unwrap();
+
+                match Self::get_visible(data.clone(), id, sender).await {
+                    Ok(Some(message)) => match Conversation::touch(data.clone(), &message).await {
+                        Ok(_) => {
+                            for participant in &conversation.participants {
+                                data.events.publish(
+                                    Channel::Inbox(*participant),
+                                    BusEvent::DirectMessage { message: id },
+                                );
+                            }
+
+                            Ok(Some(message))
+                        }
+                        Err(error) => Err(error),
+                    },
+                    Ok(None) => Ok(None),
+                    Err(error) => Err(error),
+                }
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Deletes the message `id` of `conversation` for `viewer` only,
+    /// returning whether they could see it.
+    pub async fn delete_for(
+        data: web::Data<Tweetbook>,
+        conversation: ObjectId,
+        id: ObjectId,
+        viewer: ObjectId,
+    ) -> Result<bool, Error> {
+        match Self::get_visible(data.clone(), id, viewer).await {
+            Ok(Some(message)) if message.conversation == conversation => {
+                let hidden = Self::get_collection::<Document>(data)
+                    .update_one(
+                        doc! { "_id": id },
+                        doc! { "$addToSet": { "hiddenFor": viewer } },
+                        None,
+                    )
+                    .await;
+
+                match hidden {
+                    Ok(_) => Ok(true),
+                    Err(error) => Err(error),
+                }
+            }
+            Ok(_) => Ok(false),
+            Err(error) => Err(error),
+        }
+    }
+}
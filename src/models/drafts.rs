@@ -0,0 +1,348 @@
+use actix_web::web;
+use futures::StreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, to_bson, DateTime, Document},
+    error::Error,
+    options::{FindOneAndUpdateOptions, FindOptions, ReturnDocument},
+    Collection, IndexModel,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::utils::{
+    config::env_or,
+    error::UserError,
+    pagination::{Paginated, Pagination},
+};
+
+use super::{init::Tweetbook, messages::Message, polls::PollRequest, users::User};
+
+/// A tweet saved for later. Drafts with a `publishAt` are scheduled and
+/// posted by the scheduler once that time comes; `failure` says why the
+/// last scheduled attempt didn't go out, in which case it is a plain draft
+/// again.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Draft {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    #[serde(skip_serializing)]
+    pub user: ObjectId,
+    pub text: String,
+    #[serde(rename = "replyTo", skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<ObjectId>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub media: Vec<ObjectId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub poll: Option<PollRequest>,
+    #[serde(rename = "publishAt", skip_serializing_if = "Option::is_none")]
+    pub publish_at: Option<DateTime>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failure: Option<String>,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: DateTime,
+}
+
+impl Draft {
+    pub fn get_collection<T>(data: web::Data<Tweetbook>) -> Collection<T> {
+        data.db.collection::<T>("drafts")
+    }
+
+    pub async fn ensure_indexes(data: web::Data<Tweetbook>) -> Result<(), Error> {
+        let index = Self::get_collection::<Document>(data)
+            .create_indexes(
+                vec![
+                    IndexModel::builder()
+                        .keys(doc! { "user": 1, "publishAt": 1, "updatedAt": -1 })
+                        .build(),
+                    IndexModel::builder()
+                        .keys(doc! { "publishAt": 1, "claimedAt": 1 })
+                        .build(),
+                ],
+                None,
+            )
+            .await;
+
+        match index {
+            Ok(_) => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+
+    pub async fn create(
+        data: web::Data<Tweetbook>,
+        user: ObjectId,
+        content: Document,
+        publish_at: Option<DateTime>,
+    ) -> Result<Draft, Error> {
+        let now = DateTime::now();
+        let mut draft = content;
+        draft.insert("user", user);
+        draft.insert("publishAt", publish_at);
+        draft.insert("claimedAt", None::<DateTime>);
+        draft.insert("createdAt", now);
+        draft.insert("updatedAt", now);
+
+        let inserted = Self::get_collection::<Document>(data.clone())
+            .insert_one(draft, None)
+            .await;
+
+        match inserted {
+            Ok(inserted) => {
+                let draft = Self::get_collection::<Draft>(data)
+                    .find_one(doc! { "_id": inserted.inserted_id }, None)
+                    .await;
+
+                match draft {
+                    Ok(draft) => Ok(draft.unwrap()),
+                    Err(error) => Err(error),
+                }
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    pub async fn get_draft(
+        data: web::Data<Tweetbook>,
+        id: ObjectId,
+        user: ObjectId,
+    ) -> Result<Option<Draft>, Error> {
+        Self::get_collection::<Draft>(data)
+            .find_one(doc! { "_id": id, "user": user }, None)
+            .await
+    }
+
+    /// `user`'s plain drafts, most recently edited first, or with
+    /// `scheduled` their scheduled tweets, soonest first.
+    pub async fn get_drafts(
+        data: web::Data<Tweetbook>,
+        user: ObjectId,
+        scheduled: bool,
+        pagination: &Pagination,
+    ) -> Result<Paginated<Draft>, Error> {
+        let (filter, sort) = if scheduled {
+            (
+                doc! { "user": user, "publishAt": { "$ne": null } },
+                doc! { "publishAt": 1 },
+            )
+        } else {
+            (
+                doc! { "user": user, "publishAt": null },
+                doc! { "updatedAt": -1 },
+            )
+        };
+
+        let total = Self::get_collection::<Document>(data.clone())
+            .count_documents(filter.clone(), None)
+            .await;
+
+        match total {
+            Ok(total) => {
+                let cursor = Self::get_collection::<Draft>(data)
+                    .find(
+                        filter,
+                        FindOptions::builder()
+                            .sort(sort)
+                            .skip(pagination.skip())
+                            .limit(pagination.limit() as i64)
+                            .build(),
+                    )
+                    .await;
+
+                match cursor {
+                    Ok(mut found) => {
+                        let mut items: Vec<Draft> = vec![];
+
+                        while let Some(res) = found.next().await {
+                            match res {
+                                Ok(draft) => items.push(draft),
+                                Err(error) => return Err(error),
+                            }
+                        }
+
+                        Ok(Paginated::new(items, total, pagination))
+                    }
+                    Err(error) => Err(error),
+                }
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Matches drafts nobody is publishing. A claim older than
+    /// `DRAFT_CLAIM_LEASE_SECONDS` (five minutes by default) is taken to
+    /// belong to a process that died mid-publish, so the draft is free again.
+    fn unclaimed(now: DateTime) -> Document {
+        let lease_millis = env_or("DRAFT_CLAIM_LEASE_SECONDS", 300_i64).max(1) * 1000;
+
+        doc! {
+            "$or": [
+                { "claimedAt": null },
+                { "claimedAt": { "$lt": DateTime::from_millis(now.timestamp_millis() - lease_millis) } },
+            ]
+        }
+    }
+
+    /// Applies `set` to one of `user`'s drafts that the scheduler isn't
+    /// publishing right now, returning the updated draft.
+    pub async fn update(
+        data: web::Data<Tweetbook>,
+        id: ObjectId,
+        user: ObjectId,
+        mut set: Document,
+    ) -> Result<Option<Draft>, Error> {
+        let now = DateTime::now();
+        set.insert("updatedAt", now);
+
+        let mut filter = Self::unclaimed(now);
+        filter.extend(doc! { "_id": id, "user": user });
+
+        Self::get_collection::<Draft>(data)
+            .find_one_and_update(
+                filter,
+                doc! { "$set": set },
+                FindOneAndUpdateOptions::builder()
+                    .return_document(ReturnDocument::After)
+                    .build(),
+            )
+            .await
+    }
+
+    pub async fn delete(
+        data: web::Data<Tweetbook>,
+        id: ObjectId,
+        user: ObjectId,
+    ) -> Result<bool, Error> {
+        let mut filter = Self::unclaimed(DateTime::now());
+        filter.extend(doc! { "_id": id, "user": user });
+
+        let deleted = Self::get_collection::<Document>(data)
+            .delete_one(filter, None)
+            .await;
+
+        match deleted {
+            Ok(result) => Ok(result.deleted_count > 0),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Posts the draft through [`Message::publish`] and removes it. If the
+    /// tweet can't be posted (the author has been suspended, the replied-to
+    /// tweet is gone, an upload was used elsewhere, ...) the draft is kept,
+    /// unscheduled, with the reason.
+    pub async fn publish(data: web::Data<Tweetbook>, draft: Draft) -> Result<Message, UserError> {
+        let suspended = User::get_collection::<Document>(data.clone())
+            .count_documents(
+                doc! { "_id": draft.user, "suspendedAt": { "$ne": null } },
+                None,
+            )
+            .await;
+
+        let published = match suspended {
+            Ok(0) => {
+                Message::publish(
+                    data.clone(),
+                    draft.user,
+                    draft.text,
+                    draft.reply_to,
+                    &draft.media,
+                    draft.poll.as_ref(),
+                )
+                .await
+            }
+            Ok(_) => Err(UserError::AccountSuspended),
+            Err(_) => Err(UserError::InternalServerError),
+        };
+
+        let collection = Self::get_collection::<Document>(data);
+        let cleanup = match &published {
+            Ok(_) => collection
+                .delete_one(doc! { "_id": draft.id }, None)
+                .await
+                .map(|_| ()),
+            Err(error) => collection
+                .update_one(
+                    doc! { "_id": draft.id },
+                    doc! {
+                        "$set": {
+                            "publishAt": null,
+                            "claimedAt": null,
+                            "failure": error.to_string(),
+                            "updatedAt": DateTime::now(),
+                        }
+                    },
+                    None,
+                )
+                .await
+                .map(|_| ()),
+        };
+
+        match cleanup {
+            Ok(_) => published,
+            Err(_) => Err(UserError::InternalServerError),
+        }
+    }
+
+    /// Claims one of `user`'s drafts for publishing now, so the scheduler
+    /// can't post it a second time.
+    pub async fn claim(
+        data: web::Data<Tweetbook>,
+        id: ObjectId,
+        user: ObjectId,
+    ) -> Result<Option<Draft>, Error> {
+        let now = DateTime::now();
+        let mut filter = Self::unclaimed(now);
+        filter.extend(doc! { "_id": id, "user": user });
+
+        Self::get_collection::<Draft>(data)
+            .find_one_and_update(filter, doc! { "$set": { "claimedAt": now } }, None)
+            .await
+    }
+
+    /// Claims the next scheduled draft that is due, including one whose
+    /// claim has expired. Claiming is atomic, so with several server
+    /// processes each draft is still posted once.
+    pub async fn claim_due(data: web::Data<Tweetbook>) -> Result<Option<Draft>, Error> {
+        let now = DateTime::now();
+        let mut filter = Self::unclaimed(now);
+        filter.insert("publishAt", doc! { "$lte": now });
+
+        Self::get_collection::<Draft>(data)
+            .find_one_and_update(filter, doc! { "$set": { "claimedAt": now } }, None)
+            .await
+    }
+
+    /// Publishes every scheduled draft that is due, returning how many were
+    /// posted.
+    pub async fn publish_due(data: web::Data<Tweetbook>) -> Result<u64, Error> {
+        let mut published = 0;
+
+        loop {
+            match Self::claim_due(data.clone()).await {
+                Ok(Some(draft)) => {
+                    if Self::publish(data.clone(), draft).await.is_ok() {
+                        published += 1;
+                    }
+                }
+                Ok(None) => return Ok(published),
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    /// Stored form of a draft's content.
+    pub fn content(
+        text: &str,
+        reply_to: Option<ObjectId>,
+        media: &[ObjectId],
+        poll: Option<&PollRequest>,
+    ) -> Document {
+        doc! {
+            "text": text,
+            "replyTo": reply_to,
+            "media": media,
+            "poll": to_bson(&poll).unwrap(),
+            "failure": null,
+        }
+    }
+}
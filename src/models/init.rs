@@ -3,11 +3,17 @@ use mongodb::{
     options::{ClientOptions, ResolverConfig},
     Client, Database,
 };
-use std::env;
+use std::{env, sync::Arc};
+
+use crate::{events::EventBus, filters::ContentFilters, search::SearchIndex, storage::Storage};
 
 #[derive(Clone)]
 pub struct Tweetbook {
     pub db: Database,
+    pub search: Arc<SearchIndex>,
+    pub events: Arc<EventBus>,
+    pub storage: Arc<Storage>,
+    pub filters: Arc<ContentFilters>,
 }
 
 impl Tweetbook {
@@ -23,6 +29,12 @@ impl Tweetbook {
 
         let client = Client::with_options(options).unwrap();
         let db = client.database("TweetBook");
-        Tweetbook { db }
+        Tweetbook {
+            db,
+            search: Arc::new(SearchIndex::from_env()),
+            events: Arc::new(EventBus::default()),
+            storage: Arc::new(Storage::from_env()),
+            filters: Arc::new(ContentFilters::from_env()),
+        }
     }
 }
@@ -0,0 +1,461 @@
+use actix_web::web;
+use futures::StreamExt;
+use mongodb::{
+    bson::{doc, from_document, oid::ObjectId, DateTime, Document},
+    error::Error,
+    Collection, IndexModel,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::utils::{
+    config::env_or,
+    pagination::{Paginated, Pagination},
+};
+
+use super::{
+    init::Tweetbook,
+    messages::Message,
+    users::{MinUser, User, UserConnections},
+};
+
+pub const MAX_LIST_NAME_CHARS: usize = 25;
+pub const MAX_LIST_DESCRIPTION_CHARS: usize = 100;
+
+/// A curated list of accounts as seen by one viewer. Private lists are only
+/// ever shown to their owner.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserList {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub private: bool,
+    pub owner: MinUser,
+    #[serde(rename = "memberCount")]
+    pub member_count: u64,
+    #[serde(rename = "subscriberCount")]
+    pub subscriber_count: u64,
+    #[serde(rename = "subscribedByMe", default)]
+    pub subscribed_by_me: bool,
+    #[serde(rename = "isMine", default)]
+    pub is_mine: bool,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: DateTime,
+}
+
+/// A list as stored, with member ids only.
+#[derive(Debug, Deserialize)]
+pub struct StoredList {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub owner: ObjectId,
+    pub members: Vec<ObjectId>,
+}
+
+impl UserList {
+    pub fn get_collection<T>(data: web::Data<Tweetbook>) -> Collection<T> {
+        data.db.collection::<T>("lists")
+    }
+
+    /// Most accounts a list can hold, `LIST_MAX_MEMBERS` (5000 by default).
+    pub fn max_members() -> usize {
+        env_or("LIST_MAX_MEMBERS", 5000_usize)
+    }
+
+    pub async fn ensure_indexes(data: web::Data<Tweetbook>) -> Result<(), Error> {
+        let index = Self::get_collection::<Document>(data)
+            .create_indexes(
+                vec![
+                    IndexModel::builder()
+                        .keys(doc! { "owner": 1, "updatedAt": -1 })
+                        .build(),
+                    IndexModel::builder()
+                        .keys(doc! { "subscribers": 1, "updatedAt": -1 })
+                        .build(),
+                ],
+                None,
+            )
+            .await;
+
+        match index {
+            Ok(_) => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Trimmed list name, if it is 1 to 25 characters long.
+    pub fn parse_name(name: &str) -> Option<String> {
+        let name = name.trim();
+        if name.is_empty() || name.chars().count() > MAX_LIST_NAME_CHARS {
+            None
+        } else {
+            Some(name.to_string())
+        }
+    }
+
+    /// Trimmed description, `Some(None)` when blank and `None` when longer
+    /// than 100 characters.
+    pub fn parse_description(description: &str) -> Option<Option<String>> {
+        let description = description.trim();
+        if description.chars().count() > MAX_LIST_DESCRIPTION_CHARS {
+            None
+        } else if description.is_empty() {
+            Some(None)
+        } else {
+            Some(Some(description.to_string()))
+        }
+    }
+
+    pub async fn create(
+        data: web::Data<Tweetbook>,
+        owner: ObjectId,
+        name: String,
+        description: Option<String>,
+        private: bool,
+    ) -> Result<ObjectId, Error> {
+        let now = DateTime::now();
+        let list = Self::get_collection::<Document>(data)
+            .insert_one(
+                doc! {
+                    "owner": owner,
+                    "name": name,
+                    "description": description,
+                    "private": private,
+                    "members": [],
+                    "subscribers": [],
+                    "createdAt": now,
+                    "updatedAt": now,
+                },
+                None,
+            )
+            .await;
+
+        match list {
+            Ok(inserted) => Ok(inserted.inserted_id.as_object_id().unwrap()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// The list `id` if `viewer` may see it: their own lists, and public
+    /// lists whose owner hasn't blocked them or been blocked by them.
+    pub async fn get_visible(
+        data: web::Data<Tweetbook>,
+        id: ObjectId,
+        viewer: ObjectId,
+    ) -> Result<Option<StoredList>, Error> {
+        let list = Self::get_collection::<StoredList>(data.clone())
+            .find_one(
+                doc! {
+                    "_id": id,
+                    "$or": [{ "owner": viewer }, { "private": false }],
+                },
+                None,
+            )
+            .await;
+
+        match list {
+            Ok(Some(list)) if list.owner != viewer => {
+                match User::is_blocked_between(data, list.owner, viewer).await {
+                    Ok(true) => Ok(None),
+                    Ok(false) => Ok(Some(list)),
+                    Err(error) => Err(error),
+                }
+            }
+            other => other,
+        }
+    }
+
+    fn view_stages(viewer: ObjectId) -> Vec<Document> {
+        vec![
+            doc! {
+                "$addFields": {
+                    "memberCount": { "$size": "$members" },
+                    "subscriberCount": { "$size": "$subscribers" },
+                    "subscribedByMe": { "$in": [viewer, "$subscribers"] },
+                    "isMine": { "$eq": ["$owner", viewer] },
+                }
+            },
+            User::min_user_lookup("owner", viewer, "owner"),
+            doc! { "$addFields": { "owner": { "$first": "$owner" } } },
+            doc! { "$project": { "members": 0, "subscribers": 0 } },
+        ]
+    }
+
+    /// The list `id` as seen by `viewer`. Visibility is checked by
+    /// [`UserList::get_visible`].
+    pub async fn get_list(
+        data: web::Data<Tweetbook>,
+        id: ObjectId,
+        viewer: ObjectId,
+    ) -> Result<Option<UserList>, Error> {
+        let mut pipeline = vec![doc! { "$match": { "_id": id } }];
+        pipeline.extend(Self::view_stages(viewer));
+
+        let cursor = Self::get_collection::<Document>(data)
+            .aggregate(pipeline, None)
+            .await;
+
+        match cursor {
+            Ok(mut lists) => match lists.next().await {
+                Some(Ok(list)) => Ok(Some(from_document(list).unwrap())),
+                Some(Err(error)) => Err(error),
+                None => Ok(None),
+            },
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Lists `user` owns or subscribes to, most recently changed first.
+    pub async fn get_lists(
+        data: web::Data<Tweetbook>,
+        user: ObjectId,
+        pagination: &Pagination,
+    ) -> Result<Paginated<UserList>, Error> {
+        let filter = doc! { "$or": [{ "owner": user }, { "subscribers": user }] };
+
+        let total = Self::get_collection::<Document>(data.clone())
+            .count_documents(filter.clone(), None)
+            .await;
+
+        match total {
+            Ok(total) => {
+                let mut pipeline = vec![
+                    doc! { "$match": filter },
+                    doc! { "$sort": { "updatedAt": -1 } },
+                    doc! { "$skip": pagination.skip() as i64 },
+                    doc! { "$limit": pagination.limit() as i64 },
+                ];
+                pipeline.extend(Self::view_stages(user));
+
+                let cursor = Self::get_collection::<Document>(data)
+                    .aggregate(pipeline, None)
+                    .await;
+
+                match cursor {
+                    Ok(mut lists) => {
+                        let mut items: Vec<UserList> = vec![];
+
+                        while let Some(res) = lists.next().await {
+                            items.push(from_document(res.unwrap()).unwrap());
+                        }
+
+                        Ok(Paginated::new(items, total, pagination))
+                    }
+                    Err(error) => Err(error),
+                }
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Applies `set` to one of `owner`'s lists, returning whether it exists.
+    /// Making a list private also drops its subscribers.
+    pub async fn update(
+        data: web::Data<Tweetbook>,
+        id: ObjectId,
+        owner: ObjectId,
+        mut set: Document,
+    ) -> Result<bool, Error> {
+        if set.get_bool("private") == Ok(true) {
+            set.insert("subscribers", Vec::<ObjectId>::new());
+        }
+        set.insert("updatedAt", DateTime::now());
+
+        let updated = Self::get_collection::<Document>(data)
+            .update_one(
+                doc! { "_id": id, "owner": owner },
+                doc! { "$set": set },
+                None,
+            )
+            .await;
+
+        match updated {
+            Ok(result) => Ok(result.matched_count > 0),
+            Err(error) => Err(error),
+        }
+    }
+
+    pub async fn delete(
+        data: web::Data<Tweetbook>,
+        id: ObjectId,
+        owner: ObjectId,
+    ) -> Result<bool, Error> {
+        let deleted = Self::get_collection::<Document>(data)
+            .delete_one(doc! { "_id": id, "owner": owner }, None)
+            .await;
+
+        match deleted {
+            Ok(result) => Ok(result.deleted_count > 0),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Adds `member` to the list unless it is full. Returns whether the
+    /// list has room (adding an existing member is a no-op).
+    pub async fn add_member(
+        data: web::Data<Tweetbook>,
+        id: ObjectId,
+        member: ObjectId,
+    ) -> Result<bool, Error> {
+        let added = Self::get_collection::<Document>(data)
+            .update_one(
+                doc! {
+                    "_id": id,
+                    "$or": [
+                        { "members": member },
+                        { "$expr": { "$lt": [{ "$size": "$members" }, Self::max_members() as i64] } },
+                    ],
+                },
+                doc! {
+                    "$addToSet": { "members": member },
+                    "$set": { "updatedAt": DateTime::now() },
+                },
+                None,
+            )
+            .await;
+
+        match added {
+            Ok(result) => Ok(result.matched_count > 0),
+            Err(error) => Err(error),
+        }
+    }
+
+    pub async fn remove_member(
+        data: web::Data<Tweetbook>,
+        id: ObjectId,
+        member: ObjectId,
+    ) -> Result<(), Error> {
+        let removed = Self::get_collection::<Document>(data)
+            .update_one(
+                doc! { "_id": id },
+                doc! {
+                    "$pull": { "members": member },
+                    "$set": { "updatedAt": DateTime::now() },
+                },
+                None,
+            )
+            .await;
+
+        match removed {
+            Ok(_) => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+
+    pub async fn subscribe(
+        data: web::Data<Tweetbook>,
+        id: ObjectId,
+        user: ObjectId,
+    ) -> Result<(), Error> {
+        let subscribed = Self::get_collection::<Document>(data)
+            .update_one(
+                doc! { "_id": id },
+                doc! { "$addToSet": { "subscribers": user } },
+                None,
+            )
+            .await;
+
+        match subscribed {
+            Ok(_) => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+
+    pub async fn unsubscribe(
+        data: web::Data<Tweetbook>,
+        id: ObjectId,
+        user: ObjectId,
+    ) -> Result<(), Error> {
+        let unsubscribed = Self::get_collection::<Document>(data)
+            .update_one(
+                doc! { "_id": id },
+                doc! { "$pull": { "subscribers": user } },
+                None,
+            )
+            .await;
+
+        match unsubscribed {
+            Ok(_) => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Takes `user` off every list owned by `owner`, as members and as
+    /// subscribers. Used when either blocks the other.
+    pub async fn remove_from_lists_of(
+        data: web::Data<Tweetbook>,
+        owner: ObjectId,
+        user: ObjectId,
+    ) -> Result<(), Error> {
+        let removed = Self::get_collection::<Document>(data)
+            .update_many(
+                doc! { "owner": owner },
+                doc! { "$pull": { "members": user, "subscribers": user } },
+                None,
+            )
+            .await;
+
+        match removed {
+            Ok(_) => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Pages through a list's members.
+    pub async fn get_members(
+        data: web::Data<Tweetbook>,
+        id: ObjectId,
+        viewer: ObjectId,
+        pagination: &Pagination,
+    ) -> Result<Paginated<MinUser>, Error> {
+        let members = Self::get_collection::<Document>(data)
+            .aggregate(
+                vec![
+                    doc! { "$match": { "_id": id } },
+                    doc! {
+                        "$project": {
+                            "total": { "$size": "$members" },
+                            "ids": {
+                                "$slice": ["$members", pagination.skip() as i64, pagination.limit() as i64]
+                            }
+                        }
+                    },
+                    User::min_user_lookup("ids", viewer, "items"),
+                ],
+                None,
+            )
+            .await;
+
+        match members {
+            Ok(mut pages) => match pages.next().await {
+                Some(Ok(page)) => {
+                    let page: UserConnections = from_document(page).unwrap();
+                    Ok(Paginated::new(page.items, page.total, pagination))
+                }
+                Some(Err(error)) => Err(error),
+                None => Ok(Paginated::new(vec![], 0, pagination)),
+            },
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Newest tweets from the list's members that `viewer` may see and
+    /// hasn't muted, through the same enrichment as every other tweet read.
+    pub async fn get_timeline(
+        data: web::Data<Tweetbook>,
+        list: &StoredList,
+        viewer: ObjectId,
+        pagination: &Pagination,
+    ) -> Result<Paginated<Message>, Error> {
+        Message::get_timeline_page(
+            data,
+            doc! { "user": { "$in": &list.members } },
+            viewer,
+            pagination,
+        )
+        .await
+    }
+}
@@ -0,0 +1,293 @@
+use std::str::FromStr;
+
+use actix_web::web;
+use futures::StreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, to_bson, DateTime, Document},
+    error::Error,
+    options::FindOptions,
+    Collection,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    storage::MediaStorage,
+    utils::{error::UserError, images::ProcessedImage},
+};
+
+use super::init::Tweetbook;
+
+/// Most images a single tweet can carry.
+pub const MAX_MEDIA_PER_TWEET: usize = 4;
+
+/// Where an upload's files live, as recorded alongside it.
+#[derive(Debug, Deserialize)]
+struct StoredFiles {
+    #[serde(rename = "_id")]
+    id: ObjectId,
+    key: String,
+    #[serde(rename = "thumbnailKey")]
+    thumbnail_key: String,
+}
+
+/// What an upload is for. Tweet media is uploaded first and attached when
+/// the tweet is posted; avatars are applied straight away.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MediaPurpose {
+    Tweet,
+    Avatar,
+}
+
+/// An uploaded image as embedded in tweets and returned by uploads.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Media {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub url: String,
+    #[serde(rename = "thumbnailUrl")]
+    pub thumbnail_url: String,
+    #[serde(rename = "contentType")]
+    pub content_type: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Media {
+    pub fn get_collection<T>(data: web::Data<Tweetbook>) -> Collection<T> {
+        data.db.collection::<T>("media")
+    }
+
+    /// Writes a processed upload and its thumbnail to storage and records
+    /// it as owned by `owner`.
+    pub async fn store(
+        data: web::Data<Tweetbook>,
+        owner: ObjectId,
+        image: ProcessedImage,
+        purpose: MediaPurpose,
+    ) -> Result<Media, UserError> {
+        let id = ObjectId::new();
+        let key = format!("{}/{}.{}", owner.to_hex(), id.to_hex(), image.extension);
+        let thumbnail_key = format!(
+            "{}/{}-thumb.{}",
+            owner.to_hex(),
+            id.to_hex(),
+            image.extension
+        );
+
+        let written = match data
+            .storage
+            .put(&key, image.bytes, image.content_type)
+            .await
+        {
+            Ok(_) => {
+                data.storage
+                    .put(&thumbnail_key, image.thumbnail, image.content_type)
+                    .await
+            }
+            Err(error) => Err(error),
+        };
+        if written.is_err() {
+            let _ = data.storage.delete(&key).await;
+            return Err(UserError::InternalServerError);
+        }
+
+        let media = Media {
+            id,
+            url: data.storage.url(&key),
+            thumbnail_url: data.storage.url(&thumbnail_key),
+            content_type: image.content_type.to_string(),
+            width: image.width,
+            height: image.height,
+        };
+
+        let mut stored = to_bson(&media).unwrap().as_document().unwrap().clone();
+        stored.insert("owner", owner);
+        stored.insert("purpose", to_bson(&purpose).unwrap());
+        stored.insert("key", key);
+        stored.insert("thumbnailKey", thumbnail_key);
+        stored.insert("tweet", None::<ObjectId>);
+        stored.insert("createdAt", DateTime::now());
+
+        match Self::get_collection::<Document>(data)
+            .insert_one(stored, None)
+            .await
+        {
+            Ok(_) => Ok(media),
+            Err(_) => Err(UserError::InternalServerError),
+        }
+    }
+
+    /// Parses upload ids given with a tweet, dropping repeats. At most
+    /// `MAX_MEDIA_PER_TWEET` are allowed.
+    pub fn parse_ids(ids: &[String]) -> Result<Vec<ObjectId>, UserError> {
+        let mut parsed = vec![];
+        for id in ids {
+            match ObjectId::from_str(id) {
+                Ok(id) => {
+                    if !parsed.contains(&id) {
+                        parsed.push(id);
+                    }
+                }
+                Err(_) => return Err(UserError::MediaNotExists),
+            }
+        }
+
+        if parsed.len() > MAX_MEDIA_PER_TWEET {
+            return Err(UserError::TooManyAttachments);
+        }
+        Ok(parsed)
+    }
+
+    /// `owner`'s tweet uploads among `ids` that aren't attached to a tweet
+    /// yet, in the order given.
+    pub async fn get_unattached(
+        data: web::Data<Tweetbook>,
+        ids: &[ObjectId],
+        owner: ObjectId,
+    ) -> Result<Vec<Media>, Error> {
+        let cursor = Self::get_collection::<Media>(data)
+            .find(
+                doc! {
+                    "_id": { "$in": ids },
+                    "owner": owner,
+                    "purpose": to_bson(&MediaPurpose::Tweet).unwrap(),
+                    "tweet": null,
+                },
+                None,
+            )
+            .await;
+
+        match cursor {
+            Ok(mut found) => {
+                let mut media: Vec<Media> = vec![];
+
+                while let Some(res) = found.next().await {
+                    media.push(res.unwrap());
+                }
+                media.sort_by_key(|item| ids.iter().position(|id| *id == item.id));
+
+                Ok(media)
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    pub async fn attach(
+        data: web::Data<Tweetbook>,
+        media: &[Media],
+        tweet: ObjectId,
+    ) -> Result<(), Error> {
+        let ids: Vec<ObjectId> = media.iter().map(|item| item.id).collect();
+
+        let attached = Self::get_collection::<Document>(data)
+            .update_many(
+                doc! { "_id": { "$in": ids } },
+                doc! { "$set": { "tweet": tweet } },
+                None,
+            )
+            .await;
+
+        match attached {
+            Ok(_) => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Deletes the uploads attached to `tweets`, records first and then
+    /// their files, returning how many were removed.
+    pub async fn remove_for_tweets(
+        data: web::Data<Tweetbook>,
+        tweets: &[ObjectId],
+    ) -> Result<u64, Error> {
+        let cursor = Self::get_collection::<StoredFiles>(data.clone())
+            .find(
+                doc! { "tweet": { "$in": tweets } },
+                FindOptions::builder()
+                    .projection(doc! { "key": 1, "thumbnailKey": 1 })
+                    .build(),
+            )
+            .await;
+
+        let stored = match cursor {
+            Ok(mut found) => {
+                let mut stored: Vec<StoredFiles> = vec![];
+                while let Some(res) = found.next().await {
+                    match res {
+                        Ok(files) => stored.push(files),
+                        Err(error) => return Err(error),
+                    }
+                }
+                stored
+            }
+            Err(error) => return Err(error),
+        };
+        if stored.is_empty() {
+            return Ok(0);
+        }
+
+        let ids: Vec<ObjectId> = stored.iter().map(|files| files.id).collect();
+        let removed = Self::get_collection::<Document>(data.clone())
+            .delete_many(doc! { "_id": { "$in": ids } }, None)
+            .await;
+
+        match removed {
+            Ok(result) => {
+                Self::delete_files(data.storage.as_ref(), &stored).await;
+                Ok(result.deleted_count)
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Deletes each upload's image and thumbnail. The records are gone by
+    /// now, so a file that can't be deleted is only logged.
+    async fn delete_files(storage: &impl MediaStorage, stored: &[StoredFiles]) {
+        for files in stored {
+            for key in [&files.key, &files.thumbnail_key] {
+                if let Err(error) = storage.delete(key).await {
+                    println!("Failed to delete media {}: {:?}", key, error);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::storage::local::LocalStorage;
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn deletes_images_and_thumbnails() {
+        let dir = std::env::temp_dir().join(format!("tweetbook-media-{}", ObjectId::new()));
+        let storage = LocalStorage::new(dir.clone());
+        for key in ["owner/a.jpg", "owner/a-thumb.jpg", "owner/b.jpg"] {
+            storage
+                .put(key, b"image".to_vec(), "image/jpeg")
+                .await
+                .unwrap();
+        }
+
+        let purged = StoredFiles {
+            id: ObjectId::new(),
+            key: "owner/a.jpg".to_string(),
+            thumbnail_key: "owner/a-thumb.jpg".to_string(),
+        };
+        let already_gone = StoredFiles {
+            id: ObjectId::new(),
+            key: "owner/c.jpg".to_string(),
+            thumbnail_key: "owner/c-thumb.jpg".to_string(),
+        };
+        Media::delete_files(&storage, &[purged, already_gone]).await;
+
+        assert!(!dir.join("owner/a.jpg").exists());
+        assert!(!dir.join("owner/a-thumb.jpg").exists());
+        assert!(dir.join("owner/b.jpg").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
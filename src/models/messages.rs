@@ -3,16 +3,38 @@ use std::str::FromStr;
 use actix_web::web;
 use futures::StreamExt;
 use mongodb::{
-    bson::{doc, from_document, oid::ObjectId, DateTime, Document},
+    bson::{doc, from_document, oid::ObjectId, to_bson, DateTime, Document},
     error::Error,
     options::UpdateModifications,
-    Collection, Cursor,
+    Collection, Cursor, IndexModel,
 };
 use serde::{Deserialize, Serialize};
 
-use crate::models::users::User;
+use crate::{
+    events::{BusEvent, Channel},
+    filters::{Screening, Submission},
+    jobs,
+    models::users::{User, UserConnections},
+    search::TweetSearch,
+    utils::{
+        config::env_or,
+        entities::{extract_hashtags, extract_mentions, extract_urls},
+        error::UserError,
+        pagination::{Paginated, Pagination},
+    },
+};
 
-use super::{init::Tweetbook, users::MinUser};
+use super::{
+    bookmarks::Bookmark,
+    cards::Card,
+    init::Tweetbook,
+    media::{Media, MAX_MEDIA_PER_TWEET},
+    notifications::Notification,
+    polls::{NewPoll, Poll, PollRequest},
+    reports::Report,
+    spam::{Activity, Restriction},
+    users::MinUser,
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Message {
@@ -24,7 +46,101 @@ pub struct Message {
     pub created_at: DateTime,
     #[serde(rename = "updatedAt")]
     pub updated_at: DateTime,
-    pub likes: Vec<MinUser>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub likes: Option<Vec<MinUser>>,
+    #[serde(rename = "likeCount", default)]
+    pub like_count: u64,
+    #[serde(rename = "likedByMe", default)]
+    pub liked_by_me: bool,
+    #[serde(rename = "isMine", default)]
+    pub is_mine: bool,
+    #[serde(rename = "bookmarkedByMe", default)]
+    pub bookmarked_by_me: bool,
+    #[serde(default)]
+    pub edited: bool,
+    /// Labelled by the content filters; clients may blur it.
+    #[serde(default)]
+    pub sensitive: bool,
+    /// Held by the content filters until a moderator approves it. Only the
+    /// author sees it meanwhile.
+    #[serde(rename = "heldForReview", default)]
+    pub held_for_review: bool,
+    #[serde(rename = "replyTo", skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<ReplyTo>,
+    #[serde(default)]
+    pub entities: Entities,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub media: Vec<Media>,
+    /// Preview of the first link, filled in shortly after posting.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub card: Option<Card>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub poll: Option<Poll>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Entities {
+    pub hashtags: Vec<Hashtag>,
+    pub mentions: Vec<Mention>,
+    #[serde(default)]
+    pub urls: Vec<Link>,
+}
+
+/// Offsets are in characters, `end` exclusive, and include the `#`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Hashtag {
+    pub tag: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Offsets are in characters, `end` exclusive, and include the `@`.
+/// `userId` is missing when no user has that username.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Mention {
+    pub username: String,
+    #[serde(rename = "userId", skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<ObjectId>,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Offsets are in characters, `end` exclusive.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Link {
+    pub url: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// The tweet a reply answers. Once that tweet is deleted, or for viewers
+/// who may not see it, only a tombstone with its id remains.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplyTo {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(default)]
+    pub deleted: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MessageVersion {
+    pub text: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime,
+}
+
+#[derive(Debug, Deserialize)]
+struct MessageHistory {
+    versions: Vec<MessageVersion>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MessagePage {
+    total: u64,
+    items: Vec<Message>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -39,6 +155,23 @@ impl Message {
         data.db.collection::<T>("messages")
     }
 
+    /// Index backing duplicate detection in the content filters.
+    pub async fn ensure_indexes(data: web::Data<Tweetbook>) -> Result<(), Error> {
+        let index = Self::get_collection::<Document>(data)
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "user": 1, "fingerprint": 1, "createdAt": -1 })
+                    .build(),
+                None,
+            )
+            .await;
+
+        match index {
+            Ok(_) => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+
     async fn parse_aggrigate<T>(cursor: Result<Cursor<Document>, Error>) -> Result<Vec<T>, Error>
     where
         T: for<'de> Deserialize<'de>,
@@ -57,88 +190,664 @@ impl Message {
         }
     }
 
+    /// Stages shared by every tweet read: `visibility_stages` followed by
+    /// `presentation_stages`.
+    pub fn enrichment_stages(viewer: ObjectId) -> Vec<Document> {
+        let mut stages = Self::visibility_stages(viewer);
+        stages.extend(Self::presentation_stages(viewer));
+        stages
+    }
+
+    /// Drops deleted tweets and other users' tweets held for review,
+    /// resolves the author and hides tweets `viewer` may not see (private
+    /// authors they don't follow, blocks in either direction). Only
+    /// filters, so pages can be cut right after it.
+    pub fn visibility_stages(viewer: ObjectId) -> Vec<Document> {
+        vec![
+            doc! {
+                "$match": {
+                    "deletedAt": null,
+                    "$or": [
+                        { "heldForReview": { "$ne": true } },
+                        { "user": viewer },
+                    ]
+                }
+            },
+            doc! { "$addFields": { "isMine": { "$eq": ["$user", viewer] } } },
+            doc! {
+                "$lookup": {
+                    "from": "users",
+                    "localField": "user",
+                    "foreignField": "_id",
+                    "as": "user",
+                }
+            },
+            doc! {
+                "$addFields": {
+                    "user": {
+                        "$first": "$user"
+                    }
+                }
+            },
+            doc! {
+                "$lookup": {
+                    "from": "users",
+                    "pipeline": [
+                        { "$match": { "_id": viewer } },
+                        { "$project": { "blocked": 1, "muted": 1 } },
+                    ],
+                    "as": "viewer",
+                }
+            },
+            doc! {
+                "$addFields": {
+                    "viewer": {
+                        "$first": "$viewer"
+                    }
+                }
+            },
+            doc! {
+                "$match": {
+                    "user.blocked": { "$ne": viewer },
+                    "$expr": {
+                        "$not": [{
+                            "$in": ["$user._id", { "$ifNull": ["$viewer.blocked", []] }]
+                        }]
+                    },
+                    "$or": [
+                        { "isMine": true },
+                        { "user.private": { "$ne": true } },
+                        { "user.followers": viewer },
+                    ]
+                }
+            },
+        ]
+    }
+
+    /// Leaves out tweets by users the viewer muted. Runs after
+    /// `visibility_stages`, which looks the viewer up. Muting only quiets
+    /// timelines; muted users' tweets can still be opened directly.
+    fn unmuted_stage() -> Document {
+        doc! {
+            "$match": {
+                "$expr": {
+                    "$not": [{
+                        "$in": ["$user._id", { "$ifNull": ["$viewer.muted", []] }]
+                    }]
+                }
+            }
+        }
+    }
+
+    /// Computes the like and bookmark state relative to `viewer` and
+    /// resolves the replied-to tweet, tombstoned if it was deleted or
+    /// `viewer` may not see it. The liker list itself is dropped.
+    pub fn presentation_stages(viewer: ObjectId) -> Vec<Document> {
+        let mut parent_stages = Self::visibility_stages(viewer);
+        parent_stages.push(doc! { "$project": { "text": 1, "deleted": { "$literal": false } } });
+
+        vec![
+            doc! {
+                "$addFields": {
+                    "likeCount": { "$size": { "$ifNull": ["$likes", []] } },
+                    "likedByMe": { "$in": [viewer, { "$ifNull": ["$likes", []] }] },
+                }
+            },
+            Poll::view_stage(viewer),
+            doc! {
+                "$lookup": {
+                    "from": "messages",
+                    "localField": "replyTo",
+                    "foreignField": "_id",
+                    "pipeline": parent_stages,
+                    "as": "parent",
+                }
+            },
+            doc! {
+                "$addFields": {
+                    "replyTo": {
+                        "$cond": [
+                            { "$eq": [{ "$type": "$replyTo" }, "objectId"] },
+                            {
+                                "$ifNull": [
+                                    { "$first": "$parent" },
+                                    { "_id": "$replyTo", "deleted": true }
+                                ]
+                            },
+                            "$$REMOVE"
+                        ]
+                    }
+                }
+            },
+            doc! {
+                "$lookup": {
+                    "from": "bookmarks",
+                    "localField": "_id",
+                    "foreignField": "tweet",
+                    "pipeline": [
+                        { "$match": { "user": viewer } },
+                        { "$project": { "_id": 1 } },
+                    ],
+                    "as": "bookmark",
+                }
+            },
+            doc! {
+                "$addFields": {
+                    "bookmarkedByMe": { "$gt": [{ "$size": "$bookmark" }, 0] },
+                    "user.followedByMe": {
+                        "$in": [viewer, { "$ifNull": ["$user.followers", []] }]
+                    }
+                }
+            },
+            doc! {
+                "$project": {
+                    "parent": 0,
+                    "viewer": 0,
+                    "bookmark": 0,
+                    "likes": 0,
+                    "history": 0,
+                }
+            },
+            doc! { "$project": User::public_projection("user.", false) },
+        ]
+    }
+
     pub async fn get_message_by_query<T>(
         data: web::Data<Tweetbook>,
         query: Option<Document>,
+        viewer: ObjectId,
     ) -> Result<Vec<T>, Error>
     where
         T: for<'de> Deserialize<'de>,
     {
+        let mut pipeline = vec![query.unwrap_or_else(|| doc! {"$match": {}})];
+        pipeline.extend(Self::enrichment_stages(viewer));
+
         let users = Self::get_collection::<T>(data)
+            .aggregate(pipeline, None)
+            .await;
+
+        Self::parse_aggrigate::<T>(users).await
+    }
+
+    /// Every tweet `viewer` may see, minus the users they muted.
+    pub async fn get_timeline_messages(
+        data: web::Data<Tweetbook>,
+        viewer: ObjectId,
+    ) -> Result<Vec<Message>, Error> {
+        let mut pipeline = Self::visibility_stages(viewer);
+        pipeline.push(Self::unmuted_stage());
+        pipeline.extend(Self::presentation_stages(viewer));
+
+        let messages = Self::get_collection::<Message>(data)
+            .aggregate(pipeline, None)
+            .await;
+
+        Self::parse_aggrigate::<Message>(messages).await
+    }
+
+    /// Fetches a single tweet if it exists and is visible to `viewer`.
+    pub async fn get_visible_message(
+        data: web::Data<Tweetbook>,
+        message_id: ObjectId,
+        viewer: ObjectId,
+    ) -> Result<Option<Message>, Error> {
+        let messages = Self::get_message_by_query::<Message>(
+            data,
+            Some(doc! {
+                "$match": {
+                    "_id": message_id
+                }
+            }),
+            viewer,
+        )
+        .await;
+
+        match messages {
+            Ok(mut msgs) => Ok(msgs.pop()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// The requested page of `ids`, ranked search hits, keeping only the
+    /// tweets `viewer` may see and in the order of `ids`.
+    pub async fn get_ranked_messages(
+        data: web::Data<Tweetbook>,
+        ids: Vec<ObjectId>,
+        viewer: ObjectId,
+        pagination: &Pagination,
+    ) -> Result<Paginated<Message>, Error> {
+        let stages = vec![
+            doc! { "$match": { "_id": { "$in": &ids } } },
+            doc! { "$addFields": { "rank": { "$indexOfArray": [&ids, "$_id"] } } },
+            doc! { "$sort": { "rank": 1 } },
+            doc! { "$unset": "rank" },
+        ];
+        // Authors the spam scoring hides are left out, except to themselves.
+        let unhidden = vec![doc! {
+            "$match": {
+                "$or": [
+                    { "isMine": true },
+                    { "user.restriction": { "$nin": Restriction::Hidden.at_least() } },
+                ]
+            }
+        }];
+
+        Self::get_visible_page(data, stages, unhidden, viewer, pagination).await
+    }
+
+    /// Newest-first page of the tweets matching `filter` that `viewer` may
+    /// see, with `user.followedByMe` set relative to `viewer`.
+    pub async fn get_message_page(
+        data: web::Data<Tweetbook>,
+        filter: Document,
+        viewer: ObjectId,
+        pagination: &Pagination,
+    ) -> Result<Paginated<Message>, Error> {
+        let stages = vec![
+            doc! { "$match": filter },
+            doc! { "$sort": { "createdAt": -1 } },
+        ];
+
+        Self::get_visible_page(data, stages, vec![], viewer, pagination).await
+    }
+
+    /// `get_message_page` for timelines, leaving out the users `viewer`
+    /// muted.
+    pub async fn get_timeline_page(
+        data: web::Data<Tweetbook>,
+        filter: Document,
+        viewer: ObjectId,
+        pagination: &Pagination,
+    ) -> Result<Paginated<Message>, Error> {
+        let stages = vec![
+            doc! { "$match": filter },
+            doc! { "$sort": { "createdAt": -1 } },
+        ];
+
+        Self::get_visible_page(
+            data,
+            stages,
+            vec![Self::unmuted_stage()],
+            viewer,
+            pagination,
+        )
+        .await
+    }
+
+    /// A page of the tweets `stages` select and order, narrowed further by
+    /// `visible_stages`, which run after the visibility checks and can see
+    /// the author as `user`. The page is cut and counted after both, so
+    /// tweets left out neither shorten it nor show up in `total`.
+    async fn get_visible_page(
+        data: web::Data<Tweetbook>,
+        stages: Vec<Document>,
+        visible_stages: Vec<Document>,
+        viewer: ObjectId,
+        pagination: &Pagination,
+    ) -> Result<Paginated<Message>, Error> {
+        let mut items = vec![
+            doc! { "$skip": pagination.skip() as i64 },
+            doc! { "$limit": pagination.limit() as i64 },
+        ];
+        items.extend(Self::presentation_stages(viewer));
+
+        let mut pipeline = stages;
+        pipeline.extend(Self::visibility_stages(viewer));
+        pipeline.extend(visible_stages);
+        pipeline.push(doc! {
+            "$facet": {
+                "total": [{ "$count": "count" }],
+                "items": items,
+            }
+        });
+        pipeline.push(doc! {
+            "$project": {
+                "total": { "$ifNull": [{ "$first": "$total.count" }, 0] },
+                "items": 1,
+            }
+        });
+
+        let pages = Self::get_collection::<Document>(data)
+            .aggregate(pipeline, None)
+            .await;
+
+        match pages {
+            Ok(mut cursor) => match cursor.next().await {
+                Some(Ok(page)) => {
+                    let page: MessagePage = from_document(page).unwrap();
+                    Ok(Paginated::new(page.items, page.total, pagination))
+                }
+                Some(Err(error)) => Err(error),
+                None => Ok(Paginated::new(vec![], 0, pagination)),
+            },
+            Err(error) => Err(error),
+        }
+    }
+
+    pub async fn get_user_messages(
+        data: web::Data<Tweetbook>,
+        user_id: ObjectId,
+        viewer: ObjectId,
+        pagination: &Pagination,
+    ) -> Result<Paginated<Message>, Error> {
+        Self::get_message_page(data, doc! { "user": user_id }, viewer, pagination).await
+    }
+
+    /// Tweets tagged with `tag`, matched case-insensitively.
+    pub async fn get_hashtag_messages(
+        data: web::Data<Tweetbook>,
+        tag: String,
+        viewer: ObjectId,
+        pagination: &Pagination,
+    ) -> Result<Paginated<Message>, Error> {
+        Self::get_message_page(
+            data,
+            doc! { "entities.hashtags.tag": tag.trim_start_matches('#').to_lowercase() },
+            viewer,
+            pagination,
+        )
+        .await
+    }
+
+    /// Tweets mentioning `user_id`.
+    pub async fn get_mention_messages(
+        data: web::Data<Tweetbook>,
+        user_id: ObjectId,
+        pagination: &Pagination,
+    ) -> Result<Paginated<Message>, Error> {
+        Self::get_message_page(
+            data,
+            doc! { "entities.mentions.userId": user_id },
+            user_id,
+            pagination,
+        )
+        .await
+    }
+
+    /// Parses hashtags, mentions and links out of `text`, resolving
+    /// mentioned usernames to user ids.
+    pub async fn build_entities(data: web::Data<Tweetbook>, text: &str) -> Result<Entities, Error> {
+        let hashtags = extract_hashtags(text)
+            .into_iter()
+            .map(|token| Hashtag {
+                tag: token.value,
+                start: token.start,
+                end: token.end,
+            })
+            .collect();
+        let urls = extract_urls(text)
+            .into_iter()
+            .map(|token| Link {
+                url: token.value,
+                start: token.start,
+                end: token.end,
+            })
+            .collect();
+
+        let mentions = extract_mentions(text);
+        if mentions.is_empty() {
+            return Ok(Entities {
+                hashtags,
+                mentions: vec![],
+                urls,
+            });
+        }
+
+        let usernames: Vec<&String> = mentions.iter().map(|token| &token.value).collect();
+        let users = User::get_user_by_query::<MinUser>(
+            data,
+            doc! {
+                "$match": { "username": { "$in": usernames } }
+            },
+        )
+        .await;
+
+        match users {
+            Ok(users) => Ok(Entities {
+                hashtags,
+                mentions: mentions
+                    .into_iter()
+                    .map(|token| Mention {
+                        user_id: users
+                            .iter()
+                            .find(|user| user.username == token.value)
+                            .map(|user| user.id),
+                        username: token.value,
+                        start: token.start,
+                        end: token.end,
+                    })
+                    .collect(),
+                urls,
+            }),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Pages through the users who liked a tweet.
+    pub async fn get_message_likes(
+        data: web::Data<Tweetbook>,
+        message_id: ObjectId,
+        viewer: ObjectId,
+        pagination: &Pagination,
+    ) -> Result<Paginated<MinUser>, Error> {
+        let likes = Self::get_collection::<Document>(data)
             .aggregate(
                 vec![
-                    query.unwrap_or_else(|| doc! {"$match": {}}),
+                    doc! { "$match": { "_id": message_id } },
                     doc! {
-                        "$lookup": {
-                            "from": "users",
-                            "localField": "user",
-                            "foreignField": "_id",
-                            "as": "user",
-                        }
-                    },
-                    doc! {
-                        "$addFields": {
-                            "user": {
-                                "$first": "$user"
-                            }
-                        }
-                    },
-                    doc! {
-                        "$lookup": {
-                            "from": "users",
-                            "localField": "likes",
-                            "foreignField": "_id",
-                            "as": "likes",
+                        "$project": {
+                            "ids": { "$ifNull": ["$likes", []] }
                         }
                     },
                     doc! {
                         "$project": {
-                            "user.messages": 0,
-                            "likes.messages": 0,
+                            "total": { "$size": "$ids" },
+                            "ids": {
+                                "$slice": ["$ids", pagination.skip() as i64, pagination.limit() as i64]
+                            }
                         }
                     },
+                    User::min_user_lookup("ids", viewer, "items"),
                 ],
                 None,
             )
             .await;
 
-        Self::parse_aggrigate::<T>(users).await
+        match Self::parse_aggrigate::<UserConnections>(likes).await {
+            Ok(mut pages) => match pages.pop() {
+                Some(page) => Ok(Paginated::new(page.items, page.total, pagination)),
+                None => Ok(Paginated::new(vec![], 0, pagination)),
+            },
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Posts a tweet for `author` the way every tweet is posted, whether
+    /// right away or from a schedule: it counts towards the author's rate
+    /// limit and waits on any challenge they owe, the replied-to tweet must
+    /// be visible to the author, uploads must be the author's and not yet
+    /// attached, a tweet has either images or a poll, and replies and
+    /// mentions are notified once it is in.
+    pub async fn publish(
+        data: web::Data<Tweetbook>,
+        author: ObjectId,
+        text: String,
+        reply_to: Option<ObjectId>,
+        media: &[ObjectId],
+        poll: Option<&PollRequest>,
+    ) -> Result<Message, UserError> {
+        match Activity::track_tweet(data.clone(), author, &text).await {
+            Ok(_) => {}
+            Err(error) => return Err(error),
+        }
+
+        let mut parent_author = None;
+        let reply_to = match reply_to {
+            Some(parent_id) => {
+                match Self::get_visible_message(data.clone(), parent_id, author).await {
+                    Ok(Some(parent)) => {
+                        parent_author = parent.user.map(|user| user.id);
+                        Some(ReplyTo {
+                            id: parent.id,
+                            text: Some(parent.text),
+                            deleted: false,
+                        })
+                    }
+                    Ok(None) => return Err(UserError::TweetNotExists),
+                    Err(_) => return Err(UserError::InternalServerError),
+                }
+            }
+            None => None,
+        };
+
+        let media = if media.is_empty() {
+            vec![]
+        } else if media.len() > MAX_MEDIA_PER_TWEET {
+            return Err(UserError::TooManyAttachments);
+        } else {
+            match Media::get_unattached(data.clone(), media, author).await {
+                Ok(found) if found.len() == media.len() => found,
+                Ok(_) => return Err(UserError::MediaNotExists),
+                Err(_) => return Err(UserError::InternalServerError),
+            }
+        };
+
+        let poll = match poll {
+            Some(_) if !media.is_empty() => return Err(UserError::InvalidPoll),
+            Some(poll) => match NewPoll::parse(poll) {
+                Some(poll) => Some(poll),
+                None => return Err(UserError::InvalidPoll),
+            },
+            None => None,
+        };
+
+        let submission = Submission {
+            author,
+            text: &text,
+            tweet: None,
+            reply_to: reply_to.as_ref().map(|parent| parent.id),
+        };
+        let screening = match data.filters.screen(data.clone(), &submission).await {
+            Ok(screening) => screening,
+            Err(error) => return Err(error),
+        };
+
+        match Self::insert_message(
+            data.clone(),
+            text,
+            author.to_string(),
+            reply_to,
+            media,
+            poll,
+            &screening,
+        )
+        .await
+        {
+            Ok(message) if message.held_for_review => {
+                match Report::flag(data, message.id, author, &screening.reasons).await {
+                    Ok(_) => Ok(message),
+                    Err(_) => Err(UserError::InternalServerError),
+                }
+            }
+            Ok(message) => {
+                let _ = Notification::notify_tweet(data, &message, parent_author).await;
+                Ok(message)
+            }
+            Err(_) => Err(UserError::InternalServerError),
+        }
     }
 
     pub async fn insert_message(
         data: web::Data<Tweetbook>,
         text: String,
         user_id: String,
+        reply_to: Option<ReplyTo>,
+        media: Vec<Media>,
+        poll: Option<NewPoll>,
+        screening: &Screening,
     ) -> Result<Message, Error> {
+        let entities = match Self::build_entities(data.clone(), &text).await {
+            Ok(entities) => entities,
+            Err(error) => return Err(error),
+        };
+
+        let mut new_message = doc! {
+            "user": ObjectId::from_str(user_id.as_str()).unwrap(),
+            "text": text.clone(),
+            "entities": to_bson(&entities).unwrap(),
+            "fingerprint": &screening.fingerprint,
+            "sensitive": screening.sensitive,
+            "createdAt": DateTime::now(),
+            "updatedAt": DateTime::now()
+        };
+        if screening.hold {
+            new_message.insert("heldForReview", true);
+        }
+        if let Some(parent) = &reply_to {
+            new_message.insert("replyTo", parent.id);
+        }
+        if !media.is_empty() {
+            new_message.insert("media", to_bson(&media).unwrap());
+        }
+        if let Some(poll) = &poll {
+            new_message.insert("poll", poll.to_document());
+        }
+
         let message = Self::get_collection::<Document>(data.clone())
-            .insert_one(
-                doc! {
-                    "user": ObjectId::from_str(user_id.as_str()).unwrap(),
-                    "text": text.clone(),
-                    "createdAt": DateTime::now(),
-                    "updatedAt": DateTime::now()
-                },
-                None,
-            )
+            .insert_one(new_message, None)
             .await;
 
         match message {
             Ok(inserted) => {
                 let message_id = inserted.inserted_id.as_object_id().unwrap();
 
-                let user_resp =
-                    User::update_user(data, user_id, doc! { "$push": { "messages": message_id }})
-                        .await;
+                let user_resp = User::update_user(
+                    data.clone(),
+                    user_id,
+                    doc! { "$push": { "messages": message_id }},
+                )
+                .await;
 
                 match user_resp {
-                    Ok(user) => Ok(Self {
-                        id: message_id,
-                        text: text,
-                        user: Some(user),
-                        created_at: DateTime::now(),
-                        updated_at: DateTime::now(),
-                        likes: vec![],
-                    }),
+                    Ok(user) => {
+                        let author = user.id;
+                        let message = Self {
+                            id: message_id,
+                            text,
+                            user: Some(user),
+                            created_at: DateTime::now(),
+                            updated_at: DateTime::now(),
+                            likes: None,
+                            like_count: 0,
+                            liked_by_me: false,
+                            is_mine: true,
+                            bookmarked_by_me: false,
+                            edited: false,
+                            sensitive: screening.sensitive,
+                            held_for_review: screening.hold,
+                            reply_to,
+                            entities,
+                            media,
+                            card: None,
+                            poll: poll.map(|poll| poll.to_view()),
+                        };
+                        if !message.media.is_empty() {
+                            let _ = Media::attach(data.clone(), &message.media, message_id).await;
+                        }
+                        if let Some(link) = message.entities.urls.first() {
+                            jobs::unfurl::spawn(data.clone(), message_id, link.url.clone());
+                        }
+                        data.search.index(&message);
+                        if !message.held_for_review {
+                            data.events.publish(
+                                Channel::Tweets(author),
+                                BusEvent::NewTweet { tweet: message_id },
+                            );
+                        }
+
+                        Ok(message)
+                    }
                     Err(error) => Err(error),
                 }
             }
@@ -150,6 +859,7 @@ impl Message {
         data: web::Data<Tweetbook>,
         message_id: String,
         update: impl Into<UpdateModifications>,
+        viewer: ObjectId,
     ) -> Result<Message, Error> {
         let message_updated = Self::get_collection::<MinMessage>(data.clone())
             .find_one_and_update(
@@ -164,17 +874,32 @@ impl Message {
         match message_updated {
             Ok(message) => {
                 let msg_res = Message::get_message_by_query::<Message>(
-                    data,
+                    data.clone(),
                     Some(doc! {
                         "$match": {
                             "_id": message.unwrap().id
                         }
                     }),
+                    viewer,
                 )
                 .await;
 
                 match msg_res {
-                    Ok(mut msg) => Ok(msg.remove(0)),
+                    Ok(mut msg) => {
+                        let msg = msg.remove(0);
+                        data.search.index(&msg);
+                        if let Some(author) = &msg.user {
+                            data.events.publish(
+                                Channel::Inbox(author.id),
+                                BusEvent::TweetUpdated {
+                                    tweet: msg.id,
+                                    like_count: msg.like_count,
+                                },
+                            );
+                        }
+
+                        Ok(msg)
+                    }
                     Err(error) => Err(error),
                 }
             }
@@ -182,13 +907,134 @@ impl Message {
         }
     }
 
+    /// How long after posting a tweet its author may still edit it.
+    pub fn edit_window_millis() -> i64 {
+        env_or("TWEET_EDIT_WINDOW_MINUTES", 30_i64) * 60 * 1000
+    }
+
+    /// Replaces the text of a tweet, keeping the previous version in its
+    /// edit history. The link preview is dropped and fetched again for the
+    /// new text. `screening` is the content filters' verdict on the new
+    /// text; an edit can put a tweet on hold but never releases it.
+    /// Replaces the text of `viewer`'s tweet, keeping the old version in
+    /// its history. Users the new text mentions that `previous` (the
+    /// mentions before the edit) didn't are notified, unless the tweet is
+    /// held for review.
+    pub async fn edit_message(
+        data: web::Data<Tweetbook>,
+        message_id: ObjectId,
+        text: String,
+        viewer: ObjectId,
+        screening: &Screening,
+        previous: &[Mention],
+    ) -> Result<Message, Error> {
+        let entities = match Self::build_entities(data.clone(), &text).await {
+            Ok(entities) => entities,
+            Err(error) => return Err(error),
+        };
+
+        let link = entities.urls.first().map(|link| link.url.clone());
+
+        let message = Self::update_message(
+            data.clone(),
+            message_id.to_string(),
+            vec![doc! {
+                "$set": {
+                    "history": {
+                        "$concatArrays": [
+                            { "$ifNull": ["$history", []] },
+                            [{ "text": "$text", "createdAt": "$updatedAt" }],
+                        ]
+                    },
+                    "text": { "$literal": text },
+                    "entities": { "$literal": to_bson(&entities).unwrap() },
+                    "updatedAt": "$$NOW",
+                    "edited": true,
+                    "card": "$$REMOVE",
+                    "fingerprint": &screening.fingerprint,
+                    "sensitive": screening.sensitive,
+                    "heldForReview": {
+                        "$or": [{ "$eq": ["$heldForReview", true] }, screening.hold]
+                    },
+                }
+            }],
+            viewer,
+        )
+        .await;
+
+        if let (Ok(_), Some(link)) = (&message, link) {
+            jobs::unfurl::spawn(data.clone(), message_id, link);
+        }
+        match &message {
+            Ok(message) if message.held_for_review => {
+                if screening.hold {
+                    match Report::flag(data, message_id, viewer, &screening.reasons).await {
+                        Ok(_) => {}
+                        Err(error) => return Err(error),
+                    }
+                }
+            }
+            Ok(message) => {
+                let _ = Notification::notify_new_mentions(data, message, previous).await;
+            }
+            Err(_) => {}
+        }
+
+        message
+    }
+
+    /// Every version of a tweet, oldest first, ending with the current text.
+    pub async fn get_message_history(
+        data: web::Data<Tweetbook>,
+        message_id: ObjectId,
+    ) -> Result<Vec<MessageVersion>, Error> {
+        let history = Self::get_collection::<Document>(data)
+            .aggregate(
+                vec![
+                    doc! { "$match": { "_id": message_id } },
+                    doc! {
+                        "$project": {
+                            "versions": {
+                                "$concatArrays": [
+                                    { "$ifNull": ["$history", []] },
+                                    [{ "text": "$text", "createdAt": "$updatedAt" }],
+                                ]
+                            }
+                        }
+                    },
+                ],
+                None,
+            )
+            .await;
+
+        match Self::parse_aggrigate::<MessageHistory>(history).await {
+            Ok(mut histories) => match histories.pop() {
+                Some(history) => Ok(history.versions),
+                None => Ok(vec![]),
+            },
+            Err(error) => Err(error),
+        }
+    }
+
+    /// How long a deleted tweet can still be restored by its author.
+    pub fn undo_window_millis() -> i64 {
+        env_or("TWEET_UNDO_WINDOW_SECONDS", 60_i64) * 1000
+    }
+
+    /// How long deleted tweets are kept before being purged for good.
+    pub fn trash_retention_millis() -> i64 {
+        env_or("TWEET_TRASH_RETENTION_DAYS", 30_i64) * 24 * 60 * 60 * 1000
+    }
+
+    /// Marks a tweet as deleted, hiding it from every read. Returns `false`
+    /// when the user has no such live tweet.
     pub async fn delete_message(
         data: web::Data<Tweetbook>,
         tweet_id: String,
         user_id: String,
-    ) -> Result<(), Error> {
+    ) -> Result<bool, Error> {
         let delete_response = Self::get_collection::<MinMessage>(data.clone())
-            .find_one_and_delete(
+            .find_one_and_update(
                 doc! {"$and": [
                     {
                         "$expr": {
@@ -200,25 +1046,231 @@ impl Message {
                             "$eq": ["$user", {"$toObjectId": user_id.clone()}]
                         }
                     },
+                    { "deletedAt": null },
                 ]},
+                doc! { "$set": { "deletedAt": DateTime::now() }},
                 None,
             )
             .await;
 
         match delete_response {
-            Ok(deleted_tweet) => {
+            Ok(Some(deleted_tweet)) => {
+                data.search.remove(deleted_tweet.id);
+
                 let updated_user = User::update_user(
-                    data,
+                    data.clone(),
                     user_id,
-                    doc! { "$pull": { "messages": { "$in": vec![deleted_tweet.unwrap().id]} }},
+                    doc! { "$pull": { "messages": { "$in": vec![deleted_tweet.id]} }},
+                )
+                .await;
+
+                match updated_user {
+                    Ok(user) => match User::unpin_tweet(data, user.id, deleted_tweet.id).await {
+                        Ok(_) => Ok(true),
+                        Err(error) => Err(error),
+                    },
+                    Err(error) => Err(error),
+                }
+            }
+            Ok(None) => Ok(false),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Brings back a tweet deleted within the undo window. Returns `false`
+    /// when there is nothing the user can restore.
+    pub async fn restore_message(
+        data: web::Data<Tweetbook>,
+        tweet_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<bool, Error> {
+        let cutoff =
+            DateTime::from_millis(DateTime::now().timestamp_millis() - Self::undo_window_millis());
+
+        let restore_response = Self::get_collection::<MinMessage>(data.clone())
+            .find_one_and_update(
+                doc! {
+                    "_id": tweet_id,
+                    "user": user_id,
+                    "deletedAt": { "$gte": cutoff },
+                    "hiddenAt": null,
+                },
+                doc! { "$unset": { "deletedAt": "" }},
+                None,
+            )
+            .await;
+
+        match restore_response {
+            Ok(Some(restored_tweet)) => {
+                let updated_user = User::update_user(
+                    data.clone(),
+                    user_id.to_string(),
+                    doc! { "$addToSet": { "messages": restored_tweet.id }},
                 )
                 .await;
 
                 match updated_user {
-                    Ok(_) => Ok(()),
+                    Ok(_) => {
+                        if let Ok(Some(msg)) =
+                            Self::get_visible_message(data.clone(), restored_tweet.id, user_id)
+                                .await
+                        {
+                            data.search.index(&msg);
+                        }
+
+                        Ok(true)
+                    }
                     Err(error) => Err(error),
                 }
             }
+            Ok(None) => Ok(false),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Hides a tweet on a moderator's decision. It is trashed like a
+    /// deleted tweet, but its author can't restore it and it is never
+    /// purged. Returns the author,
+    /// or `None` when there is no such tweet or it is already hidden.
+    pub async fn hide(
+        data: web::Data<Tweetbook>,
+        tweet_id: ObjectId,
+    ) -> Result<Option<ObjectId>, Error> {
+        let now = DateTime::now();
+        let hidden = Self::get_collection::<Document>(data.clone())
+            .find_one_and_update(
+                doc! { "_id": tweet_id, "hiddenAt": null },
+                doc! {
+                    "$set": { "hiddenAt": now },
+                    "$min": { "deletedAt": now },
+                },
+                None,
+            )
+            .await;
+
+        match hidden {
+            Ok(Some(tweet)) => {
+                let author = match tweet.get_object_id("user") {
+                    Ok(author) => author,
+                    Err(_) => return Ok(None),
+                };
+                data.search.remove(tweet_id);
+
+                let updated_user = User::update_user(
+                    data.clone(),
+                    author.to_string(),
+                    doc! { "$pull": { "messages": tweet_id }},
+                )
+                .await;
+
+                match updated_user {
+                    Ok(_) => match User::unpin_tweet(data, author, tweet_id).await {
+                        Ok(_) => Ok(Some(author)),
+                        Err(error) => Err(error),
+                    },
+                    Err(error) => Err(error),
+                }
+            }
+            Ok(None) => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Publishes a tweet held for review: it shows up for everyone, gets
+    /// indexed and notifies the users it replies to or mentions. Returns
+    /// `false` when the tweet isn't held.
+    pub async fn release(data: web::Data<Tweetbook>, tweet_id: ObjectId) -> Result<bool, Error> {
+        let released = Self::get_collection::<Document>(data.clone())
+            .find_one_and_update(
+                doc! { "_id": tweet_id, "heldForReview": true, "deletedAt": null },
+                doc! { "$unset": { "heldForReview": "" } },
+                None,
+            )
+            .await;
+
+        let author = match released {
+            Ok(Some(tweet)) => match tweet.get_object_id("user") {
+                Ok(author) => author,
+                Err(_) => return Ok(false),
+            },
+            Ok(None) => return Ok(false),
+            Err(error) => return Err(error),
+        };
+
+        match Self::get_visible_message(data.clone(), tweet_id, author).await {
+            Ok(Some(message)) => {
+                data.search.index(&message);
+                data.events.publish(
+                    Channel::Tweets(author),
+                    BusEvent::NewTweet { tweet: tweet_id },
+                );
+
+                let parent_author = match &message.reply_to {
+                    Some(parent) => {
+                        match Self::get_visible_message(data.clone(), parent.id, author).await {
+                            Ok(parent) => parent.and_then(|parent| parent.user.map(|user| user.id)),
+                            Err(error) => return Err(error),
+                        }
+                    }
+                    None => None,
+                };
+
+                match Notification::notify_tweet(data, &message, parent_author).await {
+                    Ok(_) => Ok(true),
+                    Err(error) => Err(error),
+                }
+            }
+            Ok(None) => Ok(true),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Hard-deletes tweets that have been in the trash longer than the
+    /// retention period, along with any bookmarks of them and their
+    /// uploaded images. Tweets hidden by
+    /// a moderator are kept, so the decision can still be reviewed.
+    pub async fn purge_deleted(data: web::Data<Tweetbook>) -> Result<u64, Error> {
+        let cutoff = DateTime::from_millis(
+            DateTime::now().timestamp_millis() - Self::trash_retention_millis(),
+        );
+
+        let expired = Self::get_collection::<MinMessage>(data.clone())
+            .find(
+                doc! { "deletedAt": { "$lt": cutoff }, "hiddenAt": null },
+                None,
+            )
+            .await;
+
+        let ids: Vec<ObjectId> = match expired {
+            Ok(mut found) => {
+                let mut ids = vec![];
+                while let Some(Ok(message)) = found.next().await {
+                    ids.push(message.id);
+                }
+                ids
+            }
+            Err(error) => return Err(error),
+        };
+        if ids.is_empty() {
+            return Ok(0);
+        }
+
+        let purged = Self::get_collection::<Document>(data.clone())
+            .delete_many(doc! { "_id": { "$in": &ids } }, None)
+            .await;
+
+        let purged = match purged {
+            Ok(result) => result.deleted_count,
+            Err(error) => return Err(error),
+        };
+
+        match Bookmark::remove_for_tweets(data.clone(), &ids).await {
+            Ok(_) => {}
+            Err(error) => return Err(error),
+        }
+
+        match Media::remove_for_tweets(data, &ids).await {
+            Ok(_) => Ok(purged),
             Err(error) => Err(error),
         }
     }
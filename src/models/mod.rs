@@ -1,3 +1,17 @@
+pub mod bookmarks;
+pub mod cards;
+pub mod conversations;
+pub mod direct_messages;
+pub mod drafts;
 pub mod init;
+pub mod lists;
+pub mod media;
 pub mod messages;
+pub mod moderation;
+pub mod notifications;
+pub mod polls;
+pub mod reports;
+pub mod spam;
+pub mod stats;
+pub mod trends;
 pub mod users;
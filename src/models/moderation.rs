@@ -0,0 +1,208 @@
+use actix_web::web;
+use futures::StreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, to_bson, DateTime, Document},
+    error::Error,
+    options::FindOptions,
+    Collection, IndexModel,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::utils::{
+    error::UserError,
+    pagination::{Paginated, Pagination},
+};
+
+use super::{
+    init::Tweetbook,
+    messages::Message,
+    reports::{Report, ReportKind, ReportStatus},
+    users::User,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModerationAction {
+    Dismiss,
+    HideTweet,
+    SuspendUser,
+    UnsuspendUser,
+    DeleteTweet,
+    ResetPassword,
+    ChangeRole,
+    ApproveTweet,
+}
+
+/// One entry of the audit trail. Entries are only ever appended.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ModerationLog {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub moderator: ObjectId,
+    pub action: ModerationAction,
+    pub kind: ReportKind,
+    pub target: ObjectId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub report: Option<ObjectId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime,
+}
+
+impl ModerationLog {
+    pub fn get_collection<T>(data: web::Data<Tweetbook>) -> Collection<T> {
+        data.db.collection::<T>("moderationLog")
+    }
+
+    pub async fn ensure_indexes(data: web::Data<Tweetbook>) -> Result<(), Error> {
+        let index = Self::get_collection::<Document>(data)
+            .create_indexes(
+                vec![
+                    IndexModel::builder().keys(doc! { "createdAt": -1 }).build(),
+                    IndexModel::builder()
+                        .keys(doc! { "target": 1, "createdAt": -1 })
+                        .build(),
+                ],
+                None,
+            )
+            .await;
+
+        match index {
+            Ok(_) => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Settles an open report with `action`, closes the other open reports
+    /// on the same target and records the decision.
+    pub async fn act_on_report(
+        data: web::Data<Tweetbook>,
+        moderator: ObjectId,
+        report: &Report,
+        action: ModerationAction,
+        note: Option<String>,
+    ) -> Result<(), UserError> {
+        if report.status != ReportStatus::Open {
+            return Err(UserError::InvalidModerationAction);
+        }
+
+        let (status, kind, target) = match action {
+            ModerationAction::Dismiss => (ReportStatus::Dismissed, report.kind, report.target),
+            ModerationAction::ApproveTweet if report.kind == ReportKind::Tweet => {
+                match Message::release(data.clone(), report.target).await {
+                    Ok(_) => (ReportStatus::Dismissed, ReportKind::Tweet, report.target),
+                    Err(_) => return Err(UserError::InternalServerError),
+                }
+            }
+            ModerationAction::HideTweet if report.kind == ReportKind::Tweet => {
+                match Message::hide(data.clone(), report.target).await {
+                    Ok(_) => (ReportStatus::Actioned, ReportKind::Tweet, report.target),
+                    Err(_) => return Err(UserError::InternalServerError),
+                }
+            }
+            ModerationAction::SuspendUser if report.target_user != moderator => {
+                match User::outranks(data.clone(), moderator, report.target_user).await {
+                    Ok(true) => {}
+                    Ok(false) => return Err(UserError::Forbidden),
+                    Err(_) => return Err(UserError::InternalServerError),
+                }
+
+                match User::set_suspended(data.clone(), report.target_user, true).await {
+                    Ok(_) => (ReportStatus::Actioned, ReportKind::User, report.target_user),
+                    Err(_) => return Err(UserError::InternalServerError),
+                }
+            }
+            _ => return Err(UserError::InvalidModerationAction),
+        };
+
+        match Report::resolve(data.clone(), report, status, moderator).await {
+            Ok(_) => {}
+            Err(_) => return Err(UserError::InternalServerError),
+        }
+
+        match Self::record(data, moderator, action, kind, target, Some(report.id), note).await {
+            Ok(_) => Ok(()),
+            Err(_) => Err(UserError::InternalServerError),
+        }
+    }
+
+    pub async fn record(
+        data: web::Data<Tweetbook>,
+        moderator: ObjectId,
+        action: ModerationAction,
+        kind: ReportKind,
+        target: ObjectId,
+        report: Option<ObjectId>,
+        note: Option<String>,
+    ) -> Result<(), Error> {
+        let recorded = Self::get_collection::<Document>(data)
+            .insert_one(
+                doc! {
+                    "moderator": moderator,
+                    "action": to_bson(&action).unwrap(),
+                    "kind": to_bson(&kind).unwrap(),
+                    "target": target,
+                    "report": report,
+                    "note": note,
+                    "createdAt": DateTime::now(),
+                },
+                None,
+            )
+            .await;
+
+        match recorded {
+            Ok(_) => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// The audit trail, newest first, optionally only the entries about
+    /// `target`.
+    pub async fn get_log(
+        data: web::Data<Tweetbook>,
+        target: Option<ObjectId>,
+        pagination: &Pagination,
+    ) -> Result<Paginated<ModerationLog>, Error> {
+        let filter = match target {
+            Some(target) => doc! { "target": target },
+            None => doc! {},
+        };
+
+        let total = Self::get_collection::<Document>(data.clone())
+            .count_documents(filter.clone(), None)
+            .await;
+
+        match total {
+            Ok(total) => {
+                let cursor = Self::get_collection::<ModerationLog>(data)
+                    .find(
+                        filter,
+                        FindOptions::builder()
+                            .sort(doc! { "createdAt": -1 })
+                            .skip(pagination.skip())
+                            .limit(pagination.limit() as i64)
+                            .build(),
+                    )
+                    .await;
+
+                match cursor {
+                    Ok(mut found) => {
+                        let mut items: Vec<ModerationLog> = vec![];
+
+                        while let Some(res) = found.next().await {
+                            match res {
+                                Ok(entry) => items.push(entry),
+                                Err(error) => return Err(error),
+                            }
+                        }
+
+                        Ok(Paginated::new(items, total, pagination))
+                    }
+                    Err(error) => Err(error),
+                }
+            }
+            Err(error) => Err(error),
+        }
+    }
+}
@@ -0,0 +1,468 @@
+use actix_web::web;
+use futures::StreamExt;
+use mongodb::{
+    bson::{doc, from_document, oid::ObjectId, to_bson, DateTime, Document},
+    error::Error,
+    options::{FindOneOptions, UpdateOptions},
+    Collection, IndexModel,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    events::{BusEvent, Channel},
+    utils::pagination::{Paginated, Pagination},
+};
+
+use super::{
+    init::Tweetbook,
+    messages::{Mention, Message},
+    users::{Access, MinUser, User},
+};
+
+/// How many of the most recent actors are returned with a grouped
+/// notification; the rest are only counted.
+const LATEST_ACTORS: i64 = 3;
+/// How many of the most recent actors a group keeps, so a viral tweet
+/// doesn't grow it without bound. `actorCount` keeps counting past it.
+const STORED_ACTORS: i64 = 50;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotificationKind {
+    Like,
+    Follow,
+    Reply,
+    Mention,
+}
+
+/// An unread notification groups every actor who did the same thing to the
+/// same tweet ("X and 3 others liked your tweet"); once read, the next one
+/// starts a new group.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Notification {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub kind: NotificationKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tweet: Option<ObjectId>,
+    /// Most recent actors first, at most `LATEST_ACTORS` of them.
+    pub actors: Vec<MinUser>,
+    #[serde(rename = "actorCount")]
+    pub actor_count: u64,
+    pub read: bool,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: DateTime,
+    #[serde(default, skip_serializing)]
+    latest: Vec<ObjectId>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NotificationPage {
+    #[serde(flatten)]
+    pub page: Paginated<Notification>,
+    pub unread: u64,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+/// Categories a user wants to be notified about, stored on the user as
+/// `notificationPrefs`. Everything is on until turned off.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NotificationPrefs {
+    #[serde(default = "enabled")]
+    pub likes: bool,
+    #[serde(default = "enabled")]
+    pub follows: bool,
+    #[serde(default = "enabled")]
+    pub replies: bool,
+    #[serde(default = "enabled")]
+    pub mentions: bool,
+}
+
+impl Default for NotificationPrefs {
+    fn default() -> Self {
+        NotificationPrefs {
+            likes: true,
+            follows: true,
+            replies: true,
+            mentions: true,
+        }
+    }
+}
+
+impl NotificationPrefs {
+    pub fn allows(&self, kind: NotificationKind) -> bool {
+        match kind {
+            NotificationKind::Like => self.likes,
+            NotificationKind::Follow => self.follows,
+            NotificationKind::Reply => self.replies,
+            NotificationKind::Mention => self.mentions,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Recipient {
+    #[serde(rename = "notificationPrefs", default)]
+    notification_prefs: NotificationPrefs,
+}
+
+impl Notification {
+    pub fn get_collection<T>(data: web::Data<Tweetbook>) -> Collection<T> {
+        data.db.collection::<T>("notifications")
+    }
+
+    pub async fn ensure_indexes(data: web::Data<Tweetbook>) -> Result<(), Error> {
+        let index = Self::get_collection::<Document>(data)
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "recipient": 1, "read": 1, "updatedAt": -1 })
+                    .build(),
+                None,
+            )
+            .await;
+
+        match index {
+            Ok(_) => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Records that `actor` did `kind` to `recipient` (on `tweet`), folding
+    /// it into the recipient's unread group for the same kind and tweet.
+    /// The group keeps the last `STORED_ACTORS` actors and counts the rest;
+    /// someone dropped from that list counts again if they act again.
+    /// Nothing is written for self-actions, for actors the recipient has
+    /// blocked or muted, or for categories the recipient turned off.
+    pub async fn notify(
+        data: web::Data<Tweetbook>,
+        recipient: ObjectId,
+        actor: ObjectId,
+        kind: NotificationKind,
+        tweet: Option<ObjectId>,
+    ) -> Result<(), Error> {
+        if recipient == actor {
+            return Ok(());
+        }
+
+        let recipient_user = User::get_collection::<Recipient>(data.clone())
+            .find_one(
+                doc! {
+                    "_id": recipient,
+                    "blocked": { "$ne": actor },
+                    "muted": { "$ne": actor },
+                },
+                FindOneOptions::builder()
+                    .projection(doc! { "notificationPrefs": 1 })
+                    .build(),
+            )
+            .await;
+
+        match recipient_user {
+            Ok(Some(user)) if user.notification_prefs.allows(kind) => {
+                let now = DateTime::now();
+                let kind_bson = to_bson(&kind).unwrap();
+
+                let grouped = Self::get_collection::<Document>(data.clone())
+                    .update_one(
+                        doc! {
+                            "recipient": recipient,
+                            "kind": kind_bson.clone(),
+                            "tweet": tweet,
+                            "read": false,
+                        },
+                        vec![doc! {
+                            "$set": {
+                                "recipient": recipient,
+                                "kind": kind_bson,
+                                "tweet": tweet,
+                                "read": false,
+                                "actors": {
+                                    "$slice": [
+                                        {
+                                            "$concatArrays": [
+                                                {
+                                                    "$filter": {
+                                                        "input": { "$ifNull": ["$actors", []] },
+                                                        "cond": { "$ne": ["$$this", actor] },
+                                                    }
+                                                },
+                                                [actor],
+                                            ]
+                                        },
+                                        -STORED_ACTORS,
+                                    ]
+                                },
+                                "actorCount": {
+                                    "$add": [
+                                        {
+                                            "$ifNull": [
+                                                "$actorCount",
+                                                { "$size": { "$ifNull": ["$actors", []] } },
+                                            ]
+                                        },
+                                        {
+                                            "$cond": [
+                                                { "$in": [actor, { "$ifNull": ["$actors", []] }] },
+                                                0,
+                                                1,
+                                            ]
+                                        },
+                                    ]
+                                },
+                                "createdAt": { "$ifNull": ["$createdAt", now] },
+                                "updatedAt": now,
+                            }
+                        }],
+                        UpdateOptions::builder().upsert(true).build(),
+                    )
+                    .await;
+
+                match grouped {
+                    Ok(_) => {
+                        data.events.publish(
+                            Channel::Inbox(recipient),
+                            BusEvent::Notification { kind, tweet },
+                        );
+                        Ok(())
+                    }
+                    Err(error) => Err(error),
+                }
+            }
+            Ok(_) => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Notifies the author of the tweet `message` replies to and everyone it
+    /// mentions, as long as they are allowed to see it.
+    pub async fn notify_tweet(
+        data: web::Data<Tweetbook>,
+        message: &Message,
+        parent_author: Option<ObjectId>,
+    ) -> Result<(), Error> {
+        let author = match &message.user {
+            Some(user) => user.id,
+            None => return Ok(()),
+        };
+
+        let mut recipients: Vec<(ObjectId, NotificationKind, ObjectId)> = vec![];
+        if let (Some(parent_author), Some(parent)) = (parent_author, &message.reply_to) {
+            recipients.push((parent_author, NotificationKind::Reply, parent.id));
+        }
+        for mention in &message.entities.mentions {
+            if let Some(user_id) = mention.user_id {
+                if recipients
+                    .iter()
+                    .all(|(recipient, _, _)| *recipient != user_id)
+                {
+                    recipients.push((user_id, NotificationKind::Mention, message.id));
+                }
+            }
+        }
+
+        Self::notify_recipients(data, author, recipients).await
+    }
+
+    /// Notifies the users an edit of `message` mentions that weren't
+    /// mentioned in `previous`, its mentions before the edit.
+    pub async fn notify_new_mentions(
+        data: web::Data<Tweetbook>,
+        message: &Message,
+        previous: &[Mention],
+    ) -> Result<(), Error> {
+        let author = match &message.user {
+            Some(user) => user.id,
+            None => return Ok(()),
+        };
+
+        let mut recipients: Vec<(ObjectId, NotificationKind, ObjectId)> = vec![];
+        for mention in &message.entities.mentions {
+            if let Some(user_id) = mention.user_id {
+                if previous.iter().all(|old| old.user_id != Some(user_id))
+                    && recipients
+                        .iter()
+                        .all(|(recipient, _, _)| *recipient != user_id)
+                {
+                    recipients.push((user_id, NotificationKind::Mention, message.id));
+                }
+            }
+        }
+
+        Self::notify_recipients(data, author, recipients).await
+    }
+
+    /// Sends each `(recipient, kind, tweet)` notification from `author`
+    /// that the recipient's privacy settings let through.
+    async fn notify_recipients(
+        data: web::Data<Tweetbook>,
+        author: ObjectId,
+        recipients: Vec<(ObjectId, NotificationKind, ObjectId)>,
+    ) -> Result<(), Error> {
+        for (recipient, kind, tweet) in recipients {
+            match User::access(data.clone(), author, recipient).await {
+                Ok(Access::Allowed) => {
+                    match Self::notify(data.clone(), recipient, author, kind, Some(tweet)).await {
+                        Ok(_) => {}
+                        Err(error) => return Err(error),
+                    }
+                }
+                Ok(_) => {}
+                Err(error) => return Err(error),
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn unread_count(data: web::Data<Tweetbook>, user: ObjectId) -> Result<u64, Error> {
+        Self::get_collection::<Document>(data)
+            .count_documents(doc! { "recipient": user, "read": false }, None)
+            .await
+    }
+
+    /// Newest-first page of `user`'s notifications with their unread count.
+    pub async fn get_notifications(
+        data: web::Data<Tweetbook>,
+        user: ObjectId,
+        pagination: &Pagination,
+    ) -> Result<NotificationPage, Error> {
+        let filter = doc! { "recipient": user };
+
+        let total = Self::get_collection::<Document>(data.clone())
+            .count_documents(filter.clone(), None)
+            .await;
+        let total = match total {
+            Ok(total) => total,
+            Err(error) => return Err(error),
+        };
+
+        let unread = match Self::unread_count(data.clone(), user).await {
+            Ok(unread) => unread,
+            Err(error) => return Err(error),
+        };
+
+        let cursor = Self::get_collection::<Document>(data)
+            .aggregate(
+                vec![
+                    doc! { "$match": filter },
+                    doc! { "$sort": { "updatedAt": -1 } },
+                    doc! { "$skip": pagination.skip() as i64 },
+                    doc! { "$limit": pagination.limit() as i64 },
+                    doc! {
+                        "$addFields": {
+                            "actorCount": {
+                                "$toLong": { "$ifNull": ["$actorCount", { "$size": "$actors" }] }
+                            },
+                            "latest": {
+                                "$reverseArray": { "$slice": ["$actors", -LATEST_ACTORS] }
+                            },
+                        }
+                    },
+                    User::min_user_lookup("latest", user, "actors"),
+                ],
+                None,
+            )
+            .await;
+
+        match cursor {
+            Ok(mut notifications) => {
+                let mut items: Vec<Notification> = vec![];
+
+                while let Some(res) = notifications.next().await {
+                    let mut notification: Notification = from_document(res.unwrap()).unwrap();
+                    let latest = &notification.latest;
+                    notification
+                        .actors
+                        .sort_by_key(|actor| latest.iter().position(|id| *id == actor.id));
+                    items.push(notification);
+                }
+
+                Ok(NotificationPage {
+                    page: Paginated::new(items, total, pagination),
+                    unread,
+                })
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Marks one of `user`'s notifications read, returning whether it
+    /// exists.
+    pub async fn mark_read(
+        data: web::Data<Tweetbook>,
+        user: ObjectId,
+        notification_id: ObjectId,
+    ) -> Result<bool, Error> {
+        let updated = Self::get_collection::<Document>(data)
+            .update_one(
+                doc! { "_id": notification_id, "recipient": user },
+                doc! { "$set": { "read": true } },
+                None,
+            )
+            .await;
+
+        match updated {
+            Ok(result) => Ok(result.matched_count > 0),
+            Err(error) => Err(error),
+        }
+    }
+
+    pub async fn mark_all_read(data: web::Data<Tweetbook>, user: ObjectId) -> Result<(), Error> {
+        let updated = Self::get_collection::<Document>(data)
+            .update_many(
+                doc! { "recipient": user, "read": false },
+                doc! { "$set": { "read": true } },
+                None,
+            )
+            .await;
+
+        match updated {
+            Ok(_) => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+
+    pub async fn get_prefs(
+        data: web::Data<Tweetbook>,
+        user: ObjectId,
+    ) -> Result<NotificationPrefs, Error> {
+        let recipient = User::get_collection::<Recipient>(data)
+            .find_one(
+                doc! { "_id": user },
+                FindOneOptions::builder()
+                    .projection(doc! { "notificationPrefs": 1 })
+                    .build(),
+            )
+            .await;
+
+        match recipient {
+            Ok(Some(recipient)) => Ok(recipient.notification_prefs),
+            Ok(None) => Ok(NotificationPrefs::default()),
+            Err(error) => Err(error),
+        }
+    }
+
+    pub async fn set_prefs(
+        data: web::Data<Tweetbook>,
+        user: ObjectId,
+        prefs: &NotificationPrefs,
+    ) -> Result<(), Error> {
+        let updated = User::get_collection::<Document>(data)
+            .update_one(
+                doc! { "_id": user },
+                doc! { "$set": { "notificationPrefs": to_bson(prefs).unwrap() } },
+                None,
+            )
+            .await;
+
+        match updated {
+            Ok(_) => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+}
@@ -0,0 +1,216 @@
+use actix_web::web;
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime, Document},
+    error::Error,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::utils::config::env_or;
+
+use super::{init::Tweetbook, messages::Message};
+
+pub const MIN_POLL_OPTIONS: usize = 2;
+pub const MAX_POLL_OPTIONS: usize = 4;
+pub const MAX_POLL_OPTION_CHARS: usize = 25;
+
+/// A poll as seen by one viewer. Per-option `votes` are only present once
+/// the viewer has voted, for the poll's author, or after it closed;
+/// `totalVotes` is always shown.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Poll {
+    pub options: Vec<PollOption>,
+    #[serde(rename = "totalVotes")]
+    pub total_votes: i64,
+    #[serde(rename = "closesAt")]
+    pub closes_at: DateTime,
+    pub closed: bool,
+    /// Index of the option the viewer voted for.
+    #[serde(rename = "myVote", skip_serializing_if = "Option::is_none")]
+    pub my_vote: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PollOption {
+    pub text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub votes: Option<i64>,
+}
+
+/// A poll as asked for, before it opens: its options and how long it runs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PollRequest {
+    pub options: Vec<String>,
+    #[serde(rename = "durationMinutes")]
+    pub duration_minutes: i64,
+}
+
+/// A validated poll about to be posted with a tweet.
+pub struct NewPoll {
+    pub options: Vec<String>,
+    pub closes_at: DateTime,
+}
+
+impl NewPoll {
+    /// Trims the options and checks there are 2 to 4 of them, each 1 to 25
+    /// characters, and that the poll runs between `POLL_MIN_MINUTES` (5)
+    /// and `POLL_MAX_MINUTES` (7 days). The poll closes that long from now.
+    pub fn parse(request: &PollRequest) -> Option<NewPoll> {
+        let duration_minutes = request.duration_minutes;
+        let options: Vec<String> = request
+            .options
+            .iter()
+            .map(|option| option.trim().to_string())
+            .collect();
+
+        let valid_options = (MIN_POLL_OPTIONS..=MAX_POLL_OPTIONS).contains(&options.len())
+            && options.iter().all(|option| {
+                !option.is_empty() && option.chars().count() <= MAX_POLL_OPTION_CHARS
+            });
+        let valid_duration = (env_or("POLL_MIN_MINUTES", 5_i64)
+            ..=env_or("POLL_MAX_MINUTES", 7 * 24 * 60_i64))
+            .contains(&duration_minutes);
+
+        if !valid_options || !valid_duration {
+            return None;
+        }
+
+        Some(NewPoll {
+            options,
+            closes_at: DateTime::from_millis(
+                DateTime::now().timestamp_millis() + duration_minutes * 60 * 1000,
+            ),
+        })
+    }
+
+    /// How the poll is stored on the tweet; `ballots` records who voted for
+    /// what and is never returned.
+    pub fn to_document(&self) -> Document {
+        let options: Vec<Document> = self
+            .options
+            .iter()
+            .map(|option| doc! { "text": option, "votes": 0_i64 })
+            .collect();
+
+        doc! {
+            "options": options,
+            "ballots": [],
+            "closesAt": self.closes_at,
+        }
+    }
+
+    /// The fresh poll as its author sees it.
+    pub fn to_view(&self) -> Poll {
+        Poll {
+            options: self
+                .options
+                .iter()
+                .map(|option| PollOption {
+                    text: option.clone(),
+                    votes: Some(0),
+                })
+                .collect(),
+            total_votes: 0,
+            closes_at: self.closes_at,
+            closed: false,
+            my_vote: None,
+        }
+    }
+}
+
+impl Poll {
+    /// Replaces the stored poll with `viewer`'s view of it. Expects `isMine`
+    /// to already be set.
+    pub fn view_stage(viewer: ObjectId) -> Document {
+        doc! {
+            "$addFields": {
+                "poll": {
+                    "$cond": [
+                        { "$eq": [{ "$type": "$poll" }, "object"] },
+                        {
+                            "$let": {
+                                "vars": {
+                                    "ballot": {
+                                        "$first": {
+                                            "$filter": {
+                                                "input": "$poll.ballots",
+                                                "cond": { "$eq": ["$$this.user", viewer] },
+                                            }
+                                        }
+                                    },
+                                    "closed": { "$lte": ["$poll.closesAt", "$$NOW"] },
+                                },
+                                "in": {
+                                    "$let": {
+                                        "vars": {
+                                            "showVotes": {
+                                                "$or": [
+                                                    "$$closed",
+                                                    "$isMine",
+                                                    { "$ne": [{ "$type": "$$ballot" }, "missing"] },
+                                                ]
+                                            },
+                                        },
+                                        "in": {
+                                            "options": {
+                                                "$map": {
+                                                    "input": "$poll.options",
+                                                    "as": "option",
+                                                    "in": {
+                                                        "text": "$$option.text",
+                                                        "votes": {
+                                                            "$cond": ["$$showVotes", "$$option.votes", "$$REMOVE"]
+                                                        },
+                                                    },
+                                                }
+                                            },
+                                            "totalVotes": { "$size": "$poll.ballots" },
+                                            "closesAt": "$poll.closesAt",
+                                            "closed": "$$closed",
+                                            "myVote": { "$ifNull": ["$$ballot.option", "$$REMOVE"] },
+                                        },
+                                    }
+                                },
+                            }
+                        },
+                        "$$REMOVE",
+                    ]
+                }
+            }
+        }
+    }
+
+    /// Records `voter`'s vote for `option`. The filter only matches while
+    /// the poll is open and `voter` hasn't voted yet, so tallies are frozen
+    /// once it closes and nobody votes twice, even with concurrent
+    /// requests. Returns whether the vote was counted.
+    pub async fn vote(
+        data: web::Data<Tweetbook>,
+        tweet: ObjectId,
+        voter: ObjectId,
+        option: usize,
+    ) -> Result<bool, Error> {
+        let votes = format!("poll.options.{}.votes", option);
+
+        let voted = Message::get_collection::<Document>(data)
+            .update_one(
+                doc! {
+                    "_id": tweet,
+                    "deletedAt": null,
+                    "poll.closesAt": { "$gt": DateTime::now() },
+                    "poll.ballots.user": { "$ne": voter },
+                    format!("poll.options.{}", option): { "$exists": true },
+                },
+                doc! {
+                    "$push": { "poll.ballots": { "user": voter, "option": option as i64 } },
+                    "$inc": { votes: 1_i64 },
+                },
+                None,
+            )
+            .await;
+
+        match voted {
+            Ok(result) => Ok(result.modified_count == 1),
+            Err(error) => Err(error),
+        }
+    }
+}
@@ -0,0 +1,314 @@
+use actix_web::web;
+use futures::StreamExt;
+use mongodb::{
+    bson::{doc, from_document, oid::ObjectId, to_bson, DateTime, Document},
+    error::Error,
+    options::{FindOneOptions, UpdateOptions},
+    Collection, IndexModel,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    filters::FilterReason,
+    utils::pagination::{Paginated, Pagination},
+};
+
+use super::{
+    init::Tweetbook,
+    users::{MinUser, User},
+};
+
+pub const MAX_REPORT_DETAILS_CHARS: usize = 500;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportKind {
+    Tweet,
+    User,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportReason {
+    Spam,
+    Harassment,
+    HateSpeech,
+    Violence,
+    SelfHarm,
+    Misinformation,
+    Impersonation,
+    Other,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportStatus {
+    Open,
+    Dismissed,
+    Actioned,
+}
+
+/// The reported tweet as moderators see it, including hidden and deleted
+/// tweets that regular reads drop.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReportedTweet {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub text: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime,
+    #[serde(default)]
+    pub deleted: bool,
+    #[serde(default)]
+    pub hidden: bool,
+}
+
+/// A user's report of a tweet or another user. `targetUser` is the
+/// reported user, or the author of the reported tweet. Reports without a
+/// `reporter` are tweets the content filters held for review, with the
+/// filters that matched.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Report {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reporter: Option<ObjectId>,
+    pub kind: ReportKind,
+    pub target: ObjectId,
+    #[serde(rename = "targetUser")]
+    pub target_user: ObjectId,
+    pub reason: ReportReason,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub filters: Vec<FilterReason>,
+    pub status: ReportStatus,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime,
+    #[serde(rename = "resolvedAt", skip_serializing_if = "Option::is_none")]
+    pub resolved_at: Option<DateTime>,
+    #[serde(rename = "resolvedBy", skip_serializing_if = "Option::is_none")]
+    pub resolved_by: Option<ObjectId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tweet: Option<ReportedTweet>,
+    #[serde(rename = "reportedUser", skip_serializing_if = "Option::is_none")]
+    pub reported_user: Option<MinUser>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReportPage {
+    total: u64,
+    items: Vec<Report>,
+}
+
+impl Report {
+    pub fn get_collection<T>(data: web::Data<Tweetbook>) -> Collection<T> {
+        data.db.collection::<T>("reports")
+    }
+
+    pub async fn ensure_indexes(data: web::Data<Tweetbook>) -> Result<(), Error> {
+        let index = Self::get_collection::<Document>(data)
+            .create_indexes(
+                vec![
+                    IndexModel::builder()
+                        .keys(doc! { "status": 1, "createdAt": 1 })
+                        .build(),
+                    IndexModel::builder()
+                        .keys(doc! { "kind": 1, "target": 1, "status": 1 })
+                        .build(),
+                    IndexModel::builder()
+                        .keys(doc! { "reporter": 1, "kind": 1, "target": 1, "status": 1 })
+                        .build(),
+                ],
+                None,
+            )
+            .await;
+
+        match index {
+            Ok(_) => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Files a report. A user has at most one open report per target, so
+    /// reporting again only updates the reason and details.
+    pub async fn file(
+        data: web::Data<Tweetbook>,
+        reporter: ObjectId,
+        kind: ReportKind,
+        target: ObjectId,
+        target_user: ObjectId,
+        reason: ReportReason,
+        details: Option<String>,
+    ) -> Result<(), Error> {
+        let filed = Self::get_collection::<Document>(data)
+            .update_one(
+                doc! {
+                    "reporter": reporter,
+                    "kind": to_bson(&kind).unwrap(),
+                    "target": target,
+                    "status": to_bson(&ReportStatus::Open).unwrap(),
+                },
+                doc! {
+                    "$set": {
+                        "reason": to_bson(&reason).unwrap(),
+                        "details": details,
+                    },
+                    "$setOnInsert": {
+                        "targetUser": target_user,
+                        "createdAt": DateTime::now(),
+                    },
+                },
+                UpdateOptions::builder().upsert(true).build(),
+            )
+            .await;
+
+        match filed {
+            Ok(_) => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Queues a tweet the content filters held for a moderator's review.
+    pub async fn flag(
+        data: web::Data<Tweetbook>,
+        tweet: ObjectId,
+        author: ObjectId,
+        reasons: &[FilterReason],
+    ) -> Result<(), Error> {
+        let flagged = Self::get_collection::<Document>(data)
+            .update_one(
+                doc! {
+                    "reporter": null,
+                    "kind": to_bson(&ReportKind::Tweet).unwrap(),
+                    "target": tweet,
+                    "status": to_bson(&ReportStatus::Open).unwrap(),
+                },
+                doc! {
+                    "$set": { "filters": to_bson(reasons).unwrap() },
+                    "$setOnInsert": {
+                        "targetUser": author,
+                        "reason": to_bson(&ReportReason::Other).unwrap(),
+                        "createdAt": DateTime::now(),
+                    },
+                },
+                UpdateOptions::builder().upsert(true).build(),
+            )
+            .await;
+
+        match flagged {
+            Ok(_) => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+
+    pub async fn get_report(
+        data: web::Data<Tweetbook>,
+        id: ObjectId,
+    ) -> Result<Option<Report>, Error> {
+        Self::get_collection::<Report>(data)
+            .find_one(doc! { "_id": id }, FindOneOptions::default())
+            .await
+    }
+
+    /// The review queue: reports with `status`, oldest first, with the
+    /// reported tweet and user resolved.
+    pub async fn get_queue(
+        data: web::Data<Tweetbook>,
+        status: ReportStatus,
+        moderator: ObjectId,
+        pagination: &Pagination,
+    ) -> Result<Paginated<Report>, Error> {
+        let reports = Self::get_collection::<Document>(data)
+            .aggregate(
+                vec![
+                    doc! { "$match": { "status": to_bson(&status).unwrap() } },
+                    doc! { "$sort": { "createdAt": 1 } },
+                    doc! {
+                        "$facet": {
+                            "total": [{ "$count": "count" }],
+                            "items": [
+                                { "$skip": pagination.skip() as i64 },
+                                { "$limit": pagination.limit() as i64 },
+                                {
+                                    "$lookup": {
+                                        "from": "messages",
+                                        "localField": "target",
+                                        "foreignField": "_id",
+                                        "pipeline": [{
+                                            "$project": {
+                                                "text": 1,
+                                                "createdAt": 1,
+                                                "deleted": { "$eq": [{ "$type": "$deletedAt" }, "date"] },
+                                                "hidden": { "$eq": [{ "$type": "$hiddenAt" }, "date"] },
+                                            }
+                                        }],
+                                        "as": "tweet",
+                                    }
+                                },
+                                User::min_user_lookup("targetUser", moderator, "reportedUser"),
+                                {
+                                    "$addFields": {
+                                        "tweet": { "$first": "$tweet" },
+                                        "reportedUser": { "$first": "$reportedUser" },
+                                    }
+                                },
+                            ],
+                        }
+                    },
+                    doc! {
+                        "$project": {
+                            "total": { "$ifNull": [{ "$first": "$total.count" }, 0] },
+                            "items": 1,
+                        }
+                    },
+                ],
+                None,
+            )
+            .await;
+
+        match reports {
+            Ok(mut cursor) => match cursor.next().await {
+                Some(Ok(page)) => match from_document::<ReportPage>(page) {
+                    Ok(page) => Ok(Paginated::new(page.items, page.total, pagination)),
+                    Err(error) => Err(error.into()),
+                },
+                Some(Err(error)) => Err(error),
+                None => Ok(Paginated::new(vec![], 0, pagination)),
+            },
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Closes every open report on the same target as `report`, so one
+    /// decision settles duplicates from other reporters.
+    pub async fn resolve(
+        data: web::Data<Tweetbook>,
+        report: &Report,
+        status: ReportStatus,
+        moderator: ObjectId,
+    ) -> Result<u64, Error> {
+        let resolved = Self::get_collection::<Document>(data)
+            .update_many(
+                doc! {
+                    "kind": to_bson(&report.kind).unwrap(),
+                    "target": report.target,
+                    "status": to_bson(&ReportStatus::Open).unwrap(),
+                },
+                doc! {
+                    "$set": {
+                        "status": to_bson(&status).unwrap(),
+                        "resolvedAt": DateTime::now(),
+                        "resolvedBy": moderator,
+                    }
+                },
+                None,
+            )
+            .await;
+
+        match resolved {
+            Ok(result) => Ok(result.modified_count),
+            Err(error) => Err(error),
+        }
+    }
+}
@@ -0,0 +1,655 @@
+use std::{collections::HashMap, time::Duration};
+
+use actix_web::web;
+use futures::StreamExt;
+use mongodb::{
+    bson::{doc, from_document, oid::ObjectId, to_bson, Bson, DateTime, Document},
+    error::Error,
+    options::{FindOneOptions, IndexOptions},
+    Collection, IndexModel,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    filters::normalize::{fingerprint, normalize},
+    utils::{challenge, config::env_or, error::UserError},
+};
+
+use super::{init::Tweetbook, users::User};
+
+const MINUTE_MILLIS: i64 = 60 * 1000;
+const HOUR_MILLIS: i64 = 60 * MINUTE_MILLIS;
+const DAY_MILLIS: i64 = 24 * HOUR_MILLIS;
+
+/// An action that counts towards a user's spam score.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ActivityKind {
+    Follow,
+    Unfollow,
+    Like,
+    Tweet,
+}
+
+/// What an account that looks automated is held back from, each level
+/// including the ones before it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Restriction {
+    #[default]
+    None,
+    /// Held to the slower `SPAM_THROTTLED_ACTIONS_PER_MINUTE`.
+    Throttled,
+    /// Left out of user, mention and tweet search.
+    Hidden,
+    /// Has to solve a challenge before following, liking or tweeting again.
+    Challenge,
+}
+
+impl Restriction {
+    /// This level and the ones above it, as stored, for use in `$in`.
+    pub fn at_least(self) -> Vec<Bson> {
+        [
+            Restriction::None,
+            Restriction::Throttled,
+            Restriction::Hidden,
+            Restriction::Challenge,
+        ]
+        .into_iter()
+        .filter(|restriction| *restriction >= self)
+        .map(|restriction| to_bson(&restriction).unwrap())
+        .collect()
+    }
+}
+
+/// The spam related state of a user.
+#[derive(Debug, Default, Deserialize)]
+struct Standing {
+    #[serde(default)]
+    restriction: Restriction,
+    #[serde(rename = "challengePassedAt", default)]
+    challenge_passed_at: Option<DateTime>,
+}
+
+#[derive(Debug, Deserialize)]
+struct KindCount {
+    kind: ActivityKind,
+    total: u64,
+    /// The most in any one hour.
+    peak: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct UserActivity {
+    #[serde(rename = "_id")]
+    user: ObjectId,
+    kinds: Vec<KindCount>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UserDuplicates {
+    #[serde(rename = "_id")]
+    user: ObjectId,
+    duplicates: u64,
+}
+
+/// What a user did over the last day.
+#[derive(Debug, Default)]
+pub struct Signals {
+    pub follows: u64,
+    pub unfollows: u64,
+    /// Likes in the busiest hour.
+    pub likes_per_hour: u64,
+    /// Tweets repeating one the user already tweeted.
+    pub duplicates: u64,
+}
+
+/// Limits and thresholds, configured from the environment:
+///
+/// - Every user may follow, unfollow, like or tweet
+///   `SPAM_ACTIONS_PER_MINUTE` times a minute (60 by default), throttled
+///   users `SPAM_THROTTLED_ACTIONS_PER_MINUTE` times (5).
+/// - Each signal scores 100 when it reaches its daily allowance:
+///   `SPAM_FOLLOWS_PER_DAY` (100), `SPAM_CHURN_PER_DAY` (20, the lesser of
+///   the day's follows and unfollows), `SPAM_LIKES_PER_HOUR` (60, in the busiest
+///   hour) and `SPAM_DUPLICATES_PER_DAY` (3). Accounts younger than
+///   `SPAM_NEW_ACCOUNT_DAYS` (7) score `SPAM_NEW_ACCOUNT_WEIGHT` (2) times
+///   as much.
+/// - A total of `SPAM_THROTTLE_SCORE` (100) throttles the user,
+///   `SPAM_HIDE_SCORE` (200) hides them from search and
+///   `SPAM_CHALLENGE_SCORE` (300) asks for a challenge, which is then
+///   waived for `SPAM_CHALLENGE_GRACE_HOURS` (24).
+pub struct SpamPolicy {
+    pub actions_per_minute: u64,
+    pub throttled_actions_per_minute: u64,
+    pub follows_per_day: u64,
+    pub churn_per_day: u64,
+    pub likes_per_hour: u64,
+    pub duplicates_per_day: u64,
+    pub new_account_days: i64,
+    pub new_account_weight: u64,
+    pub throttle_score: u64,
+    pub hide_score: u64,
+    pub challenge_score: u64,
+    pub challenge_grace_hours: i64,
+}
+
+impl SpamPolicy {
+    pub fn from_env() -> Self {
+        SpamPolicy {
+            actions_per_minute: env_or("SPAM_ACTIONS_PER_MINUTE", 60),
+            throttled_actions_per_minute: env_or("SPAM_THROTTLED_ACTIONS_PER_MINUTE", 5),
+            follows_per_day: env_or("SPAM_FOLLOWS_PER_DAY", 100),
+            churn_per_day: env_or("SPAM_CHURN_PER_DAY", 20),
+            likes_per_hour: env_or("SPAM_LIKES_PER_HOUR", 60),
+            duplicates_per_day: env_or("SPAM_DUPLICATES_PER_DAY", 3),
+            new_account_days: env_or("SPAM_NEW_ACCOUNT_DAYS", 7),
+            new_account_weight: env_or("SPAM_NEW_ACCOUNT_WEIGHT", 2),
+            throttle_score: env_or("SPAM_THROTTLE_SCORE", 100),
+            hide_score: env_or("SPAM_HIDE_SCORE", 200),
+            challenge_score: env_or("SPAM_CHALLENGE_SCORE", 300),
+            challenge_grace_hours: env_or("SPAM_CHALLENGE_GRACE_HOURS", 24),
+        }
+    }
+
+    /// How suspicious `signals` look for an account created at `created`.
+    pub fn score(&self, signals: &Signals, created: DateTime) -> u64 {
+        let ratio = |count: u64, limit: u64| (count * 100).checked_div(limit).unwrap_or(0);
+
+        let score = ratio(signals.follows, self.follows_per_day)
+            + ratio(signals.follows.min(signals.unfollows), self.churn_per_day)
+            + ratio(signals.likes_per_hour, self.likes_per_hour)
+            + ratio(signals.duplicates, self.duplicates_per_day);
+
+        let age = DateTime::now().timestamp_millis() - created.timestamp_millis();
+        if age < self.new_account_days * DAY_MILLIS {
+            score * self.new_account_weight
+        } else {
+            score
+        }
+    }
+
+    pub fn restriction(&self, score: u64) -> Restriction {
+        if score >= self.challenge_score {
+            Restriction::Challenge
+        } else if score >= self.hide_score {
+            Restriction::Hidden
+        } else if score >= self.throttle_score {
+            Restriction::Throttled
+        } else {
+            Restriction::None
+        }
+    }
+
+    /// Whether a challenge is still owed by a user who last passed one at
+    /// `passed_at`.
+    fn challenge_due(&self, passed_at: Option<DateTime>) -> bool {
+        let grace = self.challenge_grace_hours * HOUR_MILLIS;
+
+        challenge::is_enabled()
+            && passed_at.is_none_or(|passed_at| {
+                DateTime::now().timestamp_millis() - passed_at.timestamp_millis() >= grace
+            })
+    }
+}
+
+/// A log of recent follows, unfollows, likes and tweets per user, kept for
+/// a day, which spam scores are computed from.
+pub struct Activity;
+
+impl Activity {
+    pub fn get_collection<T>(data: web::Data<Tweetbook>) -> Collection<T> {
+        data.db.collection::<T>("accountActivity")
+    }
+
+    pub async fn ensure_indexes(data: web::Data<Tweetbook>) -> Result<(), Error> {
+        let index = Self::get_collection::<Document>(data)
+            .create_indexes(
+                vec![
+                    IndexModel::builder()
+                        .keys(doc! { "user": 1, "createdAt": 1 })
+                        .build(),
+                    IndexModel::builder()
+                        .keys(doc! { "createdAt": 1 })
+                        .options(
+                            IndexOptions::builder()
+                                .expire_after(Duration::from_millis(DAY_MILLIS as u64))
+                                .build(),
+                        )
+                        .build(),
+                ],
+                None,
+            )
+            .await;
+
+        match index {
+            Ok(_) => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+
+    async fn standing(data: web::Data<Tweetbook>, user: ObjectId) -> Result<Standing, Error> {
+        let standing = User::get_collection::<Standing>(data)
+            .find_one(
+                doc! { "_id": user },
+                FindOneOptions::builder()
+                    .projection(doc! { "restriction": 1, "challengePassedAt": 1 })
+                    .build(),
+            )
+            .await;
+
+        match standing {
+            Ok(standing) => Ok(standing.unwrap_or_default()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Lets `user` go ahead with an action of `kind` and logs it, unless
+    /// they owe a challenge or went over their rate limit.
+    pub async fn track(
+        data: web::Data<Tweetbook>,
+        user: ObjectId,
+        kind: ActivityKind,
+        fingerprint: Option<String>,
+    ) -> Result<(), UserError> {
+        let policy = SpamPolicy::from_env();
+
+        let standing = match Self::standing(data.clone(), user).await {
+            Ok(standing) => standing,
+            Err(_) => return Err(UserError::InternalServerError),
+        };
+        if standing.restriction >= Restriction::Challenge
+            && policy.challenge_due(standing.challenge_passed_at)
+        {
+            return Err(UserError::ChallengeRequired);
+        }
+
+        let limit = if standing.restriction >= Restriction::Throttled {
+            policy.throttled_actions_per_minute
+        } else {
+            policy.actions_per_minute
+        };
+        let since = DateTime::from_millis(DateTime::now().timestamp_millis() - MINUTE_MILLIS);
+        let recent = Self::get_collection::<Document>(data.clone())
+            .count_documents(doc! { "user": user, "createdAt": { "$gte": since } }, None)
+            .await;
+
+        match recent {
+            Ok(recent) if recent >= limit => return Err(UserError::RateLimited),
+            Ok(_) => {}
+            Err(_) => return Err(UserError::InternalServerError),
+        }
+
+        let logged = Self::get_collection::<Document>(data)
+            .insert_one(
+                doc! {
+                    "user": user,
+                    "kind": to_bson(&kind).unwrap(),
+                    "fingerprint": fingerprint,
+                    "createdAt": DateTime::now(),
+                },
+                None,
+            )
+            .await;
+
+        match logged {
+            Ok(_) => Ok(()),
+            Err(_) => Err(UserError::InternalServerError),
+        }
+    }
+
+    /// `track` for a new tweet, remembering its text to spot repeats.
+    pub async fn track_tweet(
+        data: web::Data<Tweetbook>,
+        user: ObjectId,
+        text: &str,
+    ) -> Result<(), UserError> {
+        let fingerprint = if normalize(text).is_empty() {
+            None
+        } else {
+            Some(fingerprint(text))
+        };
+
+        Self::track(data, user, ActivityKind::Tweet, fingerprint).await
+    }
+
+    /// Whether `user` has to solve a challenge before acting again.
+    pub async fn challenge_required(
+        data: web::Data<Tweetbook>,
+        user: ObjectId,
+    ) -> Result<bool, Error> {
+        match Self::standing(data, user).await {
+            Ok(standing) => Ok(standing.restriction >= Restriction::Challenge
+                && SpamPolicy::from_env().challenge_due(standing.challenge_passed_at)),
+            Err(error) => Err(error),
+        }
+    }
+
+    pub async fn pass_challenge(data: web::Data<Tweetbook>, user: ObjectId) -> Result<(), Error> {
+        let updated = User::get_collection::<Document>(data)
+            .update_one(
+                doc! { "_id": user },
+                doc! { "$set": { "challengePassedAt": DateTime::now() } },
+                None,
+            )
+            .await;
+
+        match updated {
+            Ok(_) => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Follows, unfollows, likes and tweets per user over the last day.
+    async fn recent_activity(
+        data: web::Data<Tweetbook>,
+        since: DateTime,
+    ) -> Result<Vec<UserActivity>, Error> {
+        let cursor = Self::get_collection::<Document>(data)
+            .aggregate(
+                vec![
+                    doc! { "$match": { "createdAt": { "$gte": since } } },
+                    doc! {
+                        "$group": {
+                            "_id": {
+                                "user": "$user",
+                                "kind": "$kind",
+                                "hour": {
+                                    "$dateToString": { "format": "%Y-%m-%dT%H", "date": "$createdAt" }
+                                },
+                            },
+                            "count": { "$sum": 1 },
+                        }
+                    },
+                    doc! {
+                        "$group": {
+                            "_id": { "user": "$_id.user", "kind": "$_id.kind" },
+                            "total": { "$sum": "$count" },
+                            "peak": { "$max": "$count" },
+                        }
+                    },
+                    doc! {
+                        "$group": {
+                            "_id": "$_id.user",
+                            "kinds": {
+                                "$push": {
+                                    "kind": "$_id.kind",
+                                    "total": { "$toLong": "$total" },
+                                    "peak": { "$toLong": "$peak" },
+                                }
+                            },
+                        }
+                    },
+                ],
+                None,
+            )
+            .await;
+
+        match cursor {
+            Ok(mut rows) => {
+                let mut activity: Vec<UserActivity> = vec![];
+
+                while let Some(res) = rows.next().await {
+                    activity.push(from_document(res.unwrap()).unwrap());
+                }
+
+                Ok(activity)
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Repeated tweets per user over the last day.
+    async fn recent_duplicates(
+        data: web::Data<Tweetbook>,
+        since: DateTime,
+    ) -> Result<HashMap<ObjectId, u64>, Error> {
+        let cursor = Self::get_collection::<Document>(data)
+            .aggregate(
+                vec![
+                    doc! {
+                        "$match": {
+                            "createdAt": { "$gte": since },
+                            "kind": to_bson(&ActivityKind::Tweet).unwrap(),
+                            "fingerprint": { "$ne": null },
+                        }
+                    },
+                    doc! {
+                        "$group": {
+                            "_id": { "user": "$user", "fingerprint": "$fingerprint" },
+                            "count": { "$sum": 1 },
+                        }
+                    },
+                    doc! { "$match": { "count": { "$gt": 1 } } },
+                    doc! {
+                        "$group": {
+                            "_id": "$_id.user",
+                            "duplicates": { "$sum": { "$subtract": ["$count", 1] } },
+                        }
+                    },
+                    doc! { "$set": { "duplicates": { "$toLong": "$duplicates" } } },
+                ],
+                None,
+            )
+            .await;
+
+        match cursor {
+            Ok(mut rows) => {
+                let mut duplicates = HashMap::new();
+
+                while let Some(res) = rows.next().await {
+                    let row: UserDuplicates = from_document(res.unwrap()).unwrap();
+                    duplicates.insert(row.user, row.duplicates);
+                }
+
+                Ok(duplicates)
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Scores everyone active over the last day and stores their
+    /// `spamScore` and `restriction`. Users with no recent activity left
+    /// get theirs cleared.
+    pub async fn score_users(data: web::Data<Tweetbook>) -> Result<(), Error> {
+        let policy = SpamPolicy::from_env();
+        let started = DateTime::now();
+        let since = DateTime::from_millis(started.timestamp_millis() - DAY_MILLIS);
+
+        let activity = match Self::recent_activity(data.clone(), since).await {
+            Ok(activity) => activity,
+            Err(error) => return Err(error),
+        };
+        let duplicates = match Self::recent_duplicates(data.clone(), since).await {
+            Ok(duplicates) => duplicates,
+            Err(error) => return Err(error),
+        };
+
+        let users = User::get_collection::<Document>(data);
+
+        for entry in activity {
+            let mut signals = Signals {
+                duplicates: duplicates.get(&entry.user).copied().unwrap_or(0),
+                ..Signals::default()
+            };
+            for kind in entry.kinds {
+                match kind.kind {
+                    ActivityKind::Follow => signals.follows = kind.total,
+                    ActivityKind::Unfollow => signals.unfollows = kind.total,
+                    ActivityKind::Like => signals.likes_per_hour = kind.peak,
+                    ActivityKind::Tweet => {}
+                }
+            }
+
+            let score = policy.score(&signals, entry.user.timestamp());
+            if score == 0 {
+                continue;
+            }
+
+            let scored = users
+                .update_one(
+                    doc! { "_id": entry.user },
+                    doc! {
+                        "$set": {
+                            "spamScore": score as i64,
+                            "restriction": to_bson(&policy.restriction(score)).unwrap(),
+                            "spamScoredAt": started,
+                        }
+                    },
+                    None,
+                )
+                .await;
+
+            match scored {
+                Ok(_) => {}
+                Err(error) => return Err(error),
+            }
+        }
+
+        let cleared = users
+            .update_many(
+                doc! { "spamScoredAt": { "$lt": started } },
+                doc! { "$unset": { "spamScore": "", "restriction": "", "spamScoredAt": "" } },
+                None,
+            )
+            .await;
+
+        match cleared {
+            Ok(_) => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> SpamPolicy {
+        SpamPolicy {
+            actions_per_minute: 60,
+            throttled_actions_per_minute: 5,
+            follows_per_day: 100,
+            churn_per_day: 20,
+            likes_per_hour: 60,
+            duplicates_per_day: 3,
+            new_account_days: 7,
+            new_account_weight: 2,
+            throttle_score: 100,
+            hide_score: 200,
+            challenge_score: 300,
+            challenge_grace_hours: 24,
+        }
+    }
+
+    fn days_ago(days: i64) -> DateTime {
+        DateTime::from_millis(DateTime::now().timestamp_millis() - days * DAY_MILLIS)
+    }
+
+    #[test]
+    fn scores_each_signal_against_its_allowance() {
+        let policy = policy();
+        let old = days_ago(30);
+
+        assert_eq!(policy.score(&Signals::default(), old), 0);
+        for (signals, score) in [
+            (
+                Signals {
+                    follows: 99,
+                    ..Signals::default()
+                },
+                99,
+            ),
+            (
+                Signals {
+                    follows: 100,
+                    ..Signals::default()
+                },
+                100,
+            ),
+            (
+                Signals {
+                    likes_per_hour: 60,
+                    ..Signals::default()
+                },
+                100,
+            ),
+            (
+                Signals {
+                    duplicates: 3,
+                    ..Signals::default()
+                },
+                100,
+            ),
+            (
+                Signals {
+                    duplicates: 2,
+                    ..Signals::default()
+                },
+                66,
+            ),
+            (
+                Signals {
+                    unfollows: 50,
+                    ..Signals::default()
+                },
+                0,
+            ),
+        ] {
+            assert_eq!(policy.score(&signals, old), score, "{:?}", signals);
+        }
+
+        // Churn counts the lesser of follows and unfollows.
+        let churn = Signals {
+            follows: 20,
+            unfollows: 40,
+            ..Signals::default()
+        };
+        assert_eq!(policy.score(&churn, old), 20 + 100);
+    }
+
+    #[test]
+    fn weighs_new_accounts_more() {
+        let policy = policy();
+        let signals = Signals {
+            follows: 100,
+            ..Signals::default()
+        };
+
+        assert_eq!(policy.score(&signals, days_ago(0)), 200);
+        assert_eq!(policy.score(&signals, days_ago(6)), 200);
+        assert_eq!(policy.score(&signals, days_ago(7)), 100);
+    }
+
+    #[test]
+    fn ignores_signals_without_an_allowance() {
+        let policy = SpamPolicy {
+            follows_per_day: 0,
+            ..policy()
+        };
+        let signals = Signals {
+            follows: 1000,
+            ..Signals::default()
+        };
+
+        assert_eq!(policy.score(&signals, days_ago(30)), 0);
+    }
+
+    #[test]
+    fn restricts_from_each_threshold() {
+        let policy = policy();
+
+        for (score, restriction) in [
+            (0, Restriction::None),
+            (99, Restriction::None),
+            (100, Restriction::Throttled),
+            (199, Restriction::Throttled),
+            (200, Restriction::Hidden),
+            (299, Restriction::Hidden),
+            (300, Restriction::Challenge),
+            (u64::MAX, Restriction::Challenge),
+        ] {
+            assert_eq!(policy.restriction(score), restriction, "{}", score);
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use actix_web::web;
+use mongodb::{
+    bson::{doc, oid::ObjectId, to_bson, DateTime, Document},
+    error::Error,
+};
+use serde::Serialize;
+
+use super::{
+    init::Tweetbook,
+    messages::Message,
+    reports::{Report, ReportStatus},
+    users::User,
+};
+
+const DAY_MILLIS: i64 = 24 * 60 * 60 * 1000;
+
+#[derive(Debug, Serialize)]
+pub struct UserStats {
+    pub total: u64,
+    #[serde(rename = "newToday")]
+    pub new_today: u64,
+    #[serde(rename = "newThisWeek")]
+    pub new_this_week: u64,
+    pub suspended: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TweetStats {
+    pub total: u64,
+    pub today: u64,
+    #[serde(rename = "thisWeek")]
+    pub this_week: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReportStats {
+    pub open: u64,
+}
+
+/// Platform-wide counts for the admin console.
+#[derive(Debug, Serialize)]
+pub struct PlatformStats {
+    pub users: UserStats,
+    pub tweets: TweetStats,
+    pub reports: ReportStats,
+}
+
+/// The smallest id generated at `time`. Users have no creation date, but
+/// their ids start with one.
+fn id_at(time: DateTime) -> ObjectId {
+    let mut bytes = [0; 12];
+    bytes[..4].copy_from_slice(&((time.timestamp_millis() / 1000) as u32).to_be_bytes());
+    ObjectId::from_bytes(bytes)
+}
+
+impl PlatformStats {
+    pub async fn collect(data: web::Data<Tweetbook>) -> Result<PlatformStats, Error> {
+        let now = DateTime::now().timestamp_millis();
+        let day_ago = DateTime::from_millis(now - DAY_MILLIS);
+        let week_ago = DateTime::from_millis(now - 7 * DAY_MILLIS);
+
+        let users = User::get_collection::<Document>(data.clone());
+        let messages = Message::get_collection::<Document>(data.clone());
+        let reports = Report::get_collection::<Document>(data);
+
+        let counts = [
+            (&users, doc! {}),
+            (&users, doc! { "_id": { "$gte": id_at(day_ago) } }),
+            (&users, doc! { "_id": { "$gte": id_at(week_ago) } }),
+            (&users, doc! { "suspendedAt": { "$ne": null } }),
+            (&messages, doc! { "deletedAt": null }),
+            (
+                &messages,
+                doc! { "deletedAt": null, "createdAt": { "$gte": day_ago } },
+            ),
+            (
+                &messages,
+                doc! { "deletedAt": null, "createdAt": { "$gte": week_ago } },
+            ),
+            (
+                &reports,
+                doc! { "status": to_bson(&ReportStatus::Open).unwrap() },
+            ),
+        ];
+
+        let mut totals = vec![];
+        for (collection, filter) in counts {
+            match collection.count_documents(filter, None).await {
+                Ok(total) => totals.push(total),
+                Err(error) => return Err(error),
+            }
+        }
+
+        Ok(PlatformStats {
+            users: UserStats {
+                total: totals[0],
+                new_today: totals[1],
+                new_this_week: totals[2],
+                suspended: totals[3],
+            },
+            tweets: TweetStats {
+                total: totals[4],
+                today: totals[5],
+                this_week: totals[6],
+            },
+            reports: ReportStats { open: totals[7] },
+        })
+    }
+}
@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+
+use actix_web::web;
+use futures::StreamExt;
+use mongodb::{
+    bson::{doc, from_document, DateTime, Document},
+    error::Error,
+    options::ReplaceOptions,
+    Collection,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::utils::config::env_or;
+
+use super::{init::Tweetbook, messages::Message, spam::Restriction};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Trend {
+    pub tag: String,
+    /// Tweets in the recent window, counting at most `TRENDS_MAX_PER_ACCOUNT`
+    /// per account.
+    #[serde(rename = "recentCount")]
+    pub recent_count: i64,
+    #[serde(rename = "baselineCount")]
+    pub baseline_count: i64,
+    pub accounts: i64,
+    pub score: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Trends {
+    #[serde(rename = "computedAt")]
+    pub computed_at: DateTime,
+    pub trends: Vec<Trend>,
+}
+
+/// One account's tweet counts for a hashtag in both windows.
+#[derive(Debug, Deserialize)]
+struct TagActivity {
+    tag: String,
+    recent: i64,
+    baseline: i64,
+}
+
+impl Trends {
+    pub fn get_collection<T>(data: web::Data<Tweetbook>) -> Collection<T> {
+        data.db.collection::<T>("trends")
+    }
+
+    /// Ranks hashtags by velocity: how much faster they are used in the
+    /// recent window than their hourly average over the baseline window.
+    /// Each account contributes a capped number of tweets and tags used by
+    /// too few accounts are dropped, so a handful of spammers can't trend.
+    fn rank(activity: Vec<TagActivity>, baseline_hours: i64) -> Vec<Trend> {
+        let max_per_account = env_or("TRENDS_MAX_PER_ACCOUNT", 2_i64);
+        let min_accounts = env_or("TRENDS_MIN_ACCOUNTS", 3_i64);
+        let limit = env_or("TRENDS_LIMIT", 10_usize);
+
+        let mut by_tag: HashMap<String, Trend> = HashMap::new();
+        for row in activity {
+            let trend = by_tag.entry(row.tag.clone()).or_insert(Trend {
+                tag: row.tag,
+                recent_count: 0,
+                baseline_count: 0,
+                accounts: 0,
+                score: 0.0,
+            });
+
+            trend.baseline_count += row.baseline.min(max_per_account * baseline_hours);
+            if row.recent > 0 {
+                trend.recent_count += row.recent.min(max_per_account);
+                trend.accounts += 1;
+            }
+        }
+
+        let mut trends: Vec<Trend> = by_tag
+            .into_values()
+            .filter(|trend| trend.accounts >= min_accounts)
+            .map(|mut trend| {
+                let hourly = trend.baseline_count as f64 / baseline_hours as f64;
+                trend.score = trend.recent_count as f64 / (hourly + 1.0);
+                trend
+            })
+            .collect();
+
+        trends.sort_by(|a, b| b.score.total_cmp(&a.score));
+        trends.truncate(limit);
+        trends
+    }
+
+    pub async fn compute(data: web::Data<Tweetbook>) -> Result<Trends, Error> {
+        let recent_minutes = env_or("TRENDS_RECENT_WINDOW_MINUTES", 60_i64);
+        let baseline_hours = env_or("TRENDS_BASELINE_WINDOW_HOURS", 24_i64).max(1);
+
+        let now = DateTime::now().timestamp_millis();
+        let recent_since = DateTime::from_millis(now - recent_minutes * 60 * 1000);
+        let baseline_since = DateTime::from_millis(now - baseline_hours * 60 * 60 * 1000);
+
+        let cursor = Message::get_collection::<Document>(data)
+            .aggregate(
+                vec![
+                    doc! {
+                        "$match": {
+                            "createdAt": { "$gte": baseline_since },
+                            "deletedAt": null,
+                            "heldForReview": { "$ne": true },
+                            "entities.hashtags.0": { "$exists": true },
+                        }
+                    },
+                    // Only public accounts the spam scoring hasn't hidden
+                    // count towards the public panel.
+                    doc! {
+                        "$lookup": {
+                            "from": "users",
+                            "localField": "user",
+                            "foreignField": "_id",
+                            "pipeline": [{ "$project": { "private": 1, "restriction": 1 } }],
+                            "as": "author",
+                        }
+                    },
+                    doc! {
+                        "$match": {
+                            "author.0": { "$exists": true },
+                            "author.private": { "$ne": true },
+                            "author.restriction": { "$nin": Restriction::Hidden.at_least() },
+                        }
+                    },
+                    doc! { "$unwind": "$entities.hashtags" },
+                    doc! {
+                        "$group": {
+                            "_id": { "tag": "$entities.hashtags.tag", "user": "$user" },
+                            "baseline": { "$sum": 1 },
+                            "recent": {
+                                "$sum": {
+                                    "$cond": [{ "$gte": ["$createdAt", recent_since] }, 1, 0]
+                                }
+                            },
+                        }
+                    },
+                    doc! {
+                        "$project": {
+                            "_id": 0,
+                            "tag": "$_id.tag",
+                            "baseline": { "$toLong": "$baseline" },
+                            "recent": { "$toLong": "$recent" },
+                        }
+                    },
+                ],
+                None,
+            )
+            .await;
+
+        match cursor {
+            Ok(mut rows) => {
+                let mut activity: Vec<TagActivity> = vec![];
+
+                while let Some(res) = rows.next().await {
+                    activity.push(from_document(res.unwrap()).unwrap());
+                }
+
+                Ok(Trends {
+                    computed_at: DateTime::now(),
+                    trends: Self::rank(activity, baseline_hours),
+                })
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Recomputes trends and stores them as the current snapshot.
+    pub async fn refresh(data: web::Data<Tweetbook>) -> Result<(), Error> {
+        match Self::compute(data.clone()).await {
+            Ok(trends) => {
+                let stored = Self::get_collection::<Trends>(data)
+                    .replace_one(
+                        doc! { "_id": "current" },
+                        trends,
+                        ReplaceOptions::builder().upsert(true).build(),
+                    )
+                    .await;
+
+                match stored {
+                    Ok(_) => Ok(()),
+                    Err(error) => Err(error),
+                }
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    pub async fn get_current(data: web::Data<Tweetbook>) -> Result<Trends, Error> {
+        let current = Self::get_collection::<Trends>(data)
+            .find_one(doc! { "_id": "current" }, None)
+            .await;
+
+        match current {
+            Ok(Some(trends)) => Ok(trends),
+            Ok(None) => Ok(Trends {
+                computed_at: DateTime::now(),
+                trends: vec![],
+            }),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(tag: &str, recent: i64, baseline: i64) -> TagActivity {
+        TagActivity {
+            tag: tag.to_string(),
+            recent,
+            baseline,
+        }
+    }
+
+    fn tags(trends: &[Trend]) -> Vec<&str> {
+        trends.iter().map(|trend| trend.tag.as_str()).collect()
+    }
+
+    #[test]
+    fn ranks_by_velocity_over_baseline() {
+        let mut activity = vec![];
+        for _ in 0..3 {
+            activity.push(row("steady", 1, 20));
+            activity.push(row("rising", 1, 1));
+        }
+
+        let trends = Trends::rank(activity, 24);
+        assert_eq!(tags(&trends), ["rising", "steady"]);
+        assert!(trends[0].score > trends[1].score);
+    }
+
+    #[test]
+    fn caps_tweets_per_account() {
+        let activity = vec![
+            row("flood", 100, 100),
+            row("flood", 1, 1),
+            row("flood", 1, 1),
+        ];
+
+        let trends = Trends::rank(activity, 24);
+        assert_eq!(trends[0].recent_count, 4);
+        assert_eq!(trends[0].baseline_count, 48 + 2);
+        assert_eq!(trends[0].accounts, 3);
+    }
+
+    #[test]
+    fn drops_tags_used_by_too_few_accounts() {
+        let activity = vec![
+            row("few", 2, 2),
+            row("few", 2, 2),
+            row("quiet", 0, 5),
+            row("quiet", 0, 5),
+            row("quiet", 1, 5),
+        ];
+
+        assert!(Trends::rank(activity, 24).is_empty());
+    }
+
+    #[test]
+    fn keeps_the_top_ten() {
+        let activity = (0..12)
+            .flat_map(|tag| (0..3).map(move |_| row(&format!("tag{}", tag), 1, tag)))
+            .collect();
+
+        let trends = Trends::rank(activity, 24);
+        assert_eq!(trends.len(), 10);
+        assert_eq!(trends[0].tag, "tag0");
+    }
+}
@@ -1,44 +1,201 @@
+use std::{env, str::FromStr};
+
 use actix_web::web;
 use bcrypt::hash;
 use futures::StreamExt;
 use mongodb::{
-    bson::{doc, from_document, oid::ObjectId, Document},
+    bson::{doc, from_document, oid::ObjectId, to_bson, DateTime, Document},
     error::Error,
-    options::UpdateModifications,
-    Collection, Cursor,
+    options::{FindOneOptions, UpdateModifications},
+    Collection, Cursor, IndexModel,
 };
 use serde::{Deserialize, Serialize};
 
-use crate::api::auth::AuthCredentials;
+use crate::{
+    api::auth::AuthCredentials,
+    events::{BusEvent, Channel},
+    utils::pagination::{Paginated, Pagination},
+};
 
-use super::{init::Tweetbook, messages::Message};
+use super::{init::Tweetbook, messages::Message, spam::Restriction};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct User {
     #[serde(rename = "_id")]
     pub id: ObjectId,
+    /// The tweet the user pinned, when the viewer can see it.
+    #[serde(rename = "pinnedTweet", skip_serializing_if = "Option::is_none")]
+    pub pinned_tweet: Option<Message>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub messages: Option<Vec<Message>>,
-    pub email: String,
+    /// Only present when the account owner is the one reading it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(skip_serializing)]
     pub password: Option<String>,
     pub username: String,
+    #[serde(rename = "displayName", skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub followers: Option<Vec<MinUser>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub following: Option<Vec<MinUser>>,
     pub bio: Option<String>,
     #[serde(rename = "profileImgUrl")]
     pub profile_img_url: Option<String>,
+    #[serde(default)]
+    pub private: bool,
+    /// Set by a moderator. Suspended users can't sign in or use their
+    /// token.
+    #[serde(rename = "suspendedAt", default, skip_serializing)]
+    pub suspended_at: Option<DateTime>,
+    #[serde(default, skip_serializing)]
+    pub role: Role,
+    /// Tokens issued before this are no longer accepted.
+    #[serde(rename = "passwordChangedAt", default, skip_serializing)]
+    pub password_changed_at: Option<DateTime>,
+    /// Set by an admin; the user has to pick a new password to sign in.
+    #[serde(rename = "passwordResetRequired", default, skip_serializing)]
+    pub password_reset_required: bool,
+    #[serde(rename = "followersCount", skip_serializing_if = "Option::is_none")]
+    pub followers_count: Option<u64>,
+    #[serde(rename = "followingCount", skip_serializing_if = "Option::is_none")]
+    pub following_count: Option<u64>,
+    #[serde(rename = "tweetsCount", skip_serializing_if = "Option::is_none")]
+    pub tweets_count: Option<u64>,
+    #[serde(rename = "followedByMe", skip_serializing_if = "Option::is_none")]
+    pub followed_by_me: Option<bool>,
+    #[serde(rename = "followsMe", skip_serializing_if = "Option::is_none")]
+    pub follows_me: Option<bool>,
+    #[serde(rename = "requestedByMe", skip_serializing_if = "Option::is_none")]
+    pub requested_by_me: Option<bool>,
 }
 
+/// Public view of a user, safe to embed in any response.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct MinUser {
     #[serde(rename = "_id")]
     pub id: ObjectId,
-    pub email: String,
     pub username: String,
+    #[serde(rename = "displayName", skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
     pub bio: Option<String>,
     #[serde(rename = "profileImgUrl")]
     pub profile_img_url: Option<String>,
+    #[serde(rename = "followedByMe", skip_serializing_if = "Option::is_none")]
+    pub followed_by_me: Option<bool>,
+}
+
+/// What a user may do, each role including the ones before it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    #[default]
+    User,
+    Moderator,
+    Admin,
+}
+
+#[derive(Debug, Deserialize)]
+struct StoredRole {
+    #[serde(default)]
+    role: Role,
+}
+
+/// A user as admins see it, email and account state included.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AdminUser {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub username: String,
+    #[serde(rename = "displayName", skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    pub email: String,
+    #[serde(default)]
+    pub role: Role,
+    #[serde(rename = "suspendedAt", skip_serializing_if = "Option::is_none")]
+    pub suspended_at: Option<DateTime>,
+    #[serde(rename = "passwordResetRequired", default)]
+    pub password_reset_required: bool,
+    #[serde(rename = "spamScore", default)]
+    pub spam_score: u64,
+    #[serde(default)]
+    pub restriction: Restriction,
+    #[serde(rename = "followersCount", default)]
+    pub followers_count: u64,
+    #[serde(rename = "tweetsCount", default)]
+    pub tweets_count: u64,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Access {
+    Allowed,
+    Private,
+    Blocked,
+}
+
+/// Compact user entry returned by the mention typeahead.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserSuggestion {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub username: String,
+    #[serde(rename = "displayName", skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    #[serde(rename = "profileImgUrl")]
+    pub profile_img_url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UserConnections {
+    pub total: u64,
+    pub items: Vec<MinUser>,
+}
+
+/// Escapes regex metacharacters so user input only ever matches literally.
+fn escape_regex(input: &str) -> String {
+    let mut escaped = String::with_capacity(input.len());
+    for c in input.chars() {
+        if "\\^$.|?*+()[]{}-/".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
 }
 
+/// Stored user fields that never leave the server.
+const SECRET_FIELDS: [&str; 4] = [
+    "password",
+    "activeIps",
+    "passwordChangedAt",
+    "passwordResetRequired",
+];
+/// Stored user fields only the account owner may read.
+const PRIVATE_FIELDS: [&str; 1] = ["email"];
+/// Relationship lists and lookup keys, exposed only as counts and flags.
+const INTERNAL_FIELDS: [&str; 16] = [
+    "followers",
+    "following",
+    "messages",
+    "followRequests",
+    "blocked",
+    "muted",
+    "usernameLower",
+    "displayNameLower",
+    "notificationPrefs",
+    "pinnedTweetId",
+    "suspendedAt",
+    "role",
+    "spamScore",
+    "restriction",
+    "spamScoredAt",
+    "challengePassedAt",
+];
+
+const MAX_SEARCH_LENGTH: usize = 50;
+const TYPEAHEAD_LIMIT: i64 = 8;
+
 impl User {
     pub fn get_collection<T>(data: web::Data<Tweetbook>) -> Collection<T> {
         data.db.collection::<T>("users")
@@ -62,9 +219,50 @@ impl User {
         }
     }
 
+    /// Exclusion `$project` body hiding everything but the public view of a
+    /// user document found under `prefix` (e.g. `"user."`, or `""` for the
+    /// root). Pass `owner` to keep the fields only the owner may read.
+    pub fn public_projection(prefix: &str, owner: bool) -> Document {
+        let mut hidden = SECRET_FIELDS.to_vec();
+        hidden.extend(INTERNAL_FIELDS);
+        if !owner {
+            hidden.extend(PRIVATE_FIELDS);
+        }
+
+        let mut projection = Document::new();
+        for field in hidden {
+            projection.insert(format!("{}{}", prefix, field), 0);
+        }
+        projection
+    }
+
+    /// `$lookup` stage resolving an array of user ids into public user
+    /// documents, each marked with whether `viewer` follows them.
+    pub fn min_user_lookup(local_field: &str, viewer: ObjectId, as_field: &str) -> Document {
+        doc! {
+            "$lookup": {
+                "from": "users",
+                "localField": local_field,
+                "foreignField": "_id",
+                "pipeline": [
+                    {
+                        "$addFields": {
+                            "followedByMe": {
+                                "$in": [viewer, { "$ifNull": ["$followers", []] }]
+                            }
+                        }
+                    },
+                    { "$project": Self::public_projection("", false) }
+                ],
+                "as": as_field,
+            }
+        }
+    }
+
     pub async fn get_user_details(
         data: web::Data<Tweetbook>,
         id: String,
+        viewer: ObjectId,
     ) -> Result<Vec<Self>, Error> {
         let users = Self::get_collection::<Self>(data)
             .aggregate(
@@ -76,78 +274,87 @@ impl User {
                             }
                         }
                     },
+                    doc! {
+                        "$addFields": {
+                            "followersCount": { "$size": { "$ifNull": ["$followers", []] } },
+                            "followingCount": { "$size": { "$ifNull": ["$following", []] } },
+                            "tweetsCount": { "$size": { "$ifNull": ["$messages", []] } },
+                            "followedByMe": { "$in": [viewer, { "$ifNull": ["$followers", []] }] },
+                            "followsMe": { "$in": [viewer, { "$ifNull": ["$following", []] }] },
+                            "requestedByMe": { "$in": [viewer, { "$ifNull": ["$followRequests", []] }] },
+                            "email": {
+                                "$cond": [{ "$eq": ["$_id", viewer] }, "$email", "$$REMOVE"]
+                            },
+                        }
+                    },
                     doc! {
                         "$lookup": {
                             "from": "messages",
-                            "localField": "messages",
+                            "localField": "pinnedTweetId",
                             "foreignField": "_id",
-                            "pipeline": [
-                                {
-                                    "$lookup": {
-                                        "from": "users",
-                                        "localField": "user",
-                                        "foreignField": "_id",
-                                        "as": "user"
-                                    }
-                                },
-                                {
-                                    "$addFields": {
-                                        "user": {
-                                            "$first": "$user"
-                                        }
-                                    }
-                                },
-                                {
-                                    "$lookup": {
-                                        "from": "users",
-                                        "localField": "likes",
-                                        "foreignField": "_id",
-                                        "as": "likes",
-                                    }
-                                },
-                                {
-                                    "$project": {
-                                        "followers": 0,
-                                        "following": 0,
-                                        "messages": 0,
-                                        "password": 0,
-                                        "activeIps": 0
-                                    }
-                                }
-                            ],
-                            "as": "messages",
+                            "pipeline": Message::enrichment_stages(viewer),
+                            "as": "pinnedTweet",
                         }
                     },
+                    doc! { "$addFields": { "pinnedTweet": { "$first": "$pinnedTweet" } } },
+                    doc! { "$project": Self::public_projection("", true) },
+                ],
+                None,
+            )
+            .await;
+
+        Self::parse_aggrigate::<Self>(users).await
+    }
+
+    /// Pages through the `followers` or `following` ids of a user, marking
+    /// each entry with whether `viewer` follows them.
+    pub async fn get_user_connections(
+        data: web::Data<Tweetbook>,
+        id: String,
+        field: &str,
+        viewer: ObjectId,
+        pagination: &Pagination,
+    ) -> Result<Paginated<MinUser>, Error> {
+        let connections = Self::get_collection::<Self>(data)
+            .aggregate(
+                vec![
                     doc! {
-                        "$lookup": {
-                            "from": "users",
-                            "localField": "followers",
-                            "foreignField": "_id",
-                            "as": "followers",
+                        "$match": {
+                            "$expr": {
+                                "$eq": ["$_id", {"$toObjectId": id}]
+                            }
                         }
                     },
                     doc! {
-                        "$lookup": {
-                            "from": "users",
-                            "localField": "following",
-                            "foreignField": "_id",
-                            "as": "following",
+                        "$project": {
+                            "ids": { "$ifNull": [format!("${}", field), []] }
                         }
                     },
                     doc! {
                         "$project": {
-                            "password": 0,
-                            "activeIps": 0
+                            "total": { "$size": "$ids" },
+                            "ids": {
+                                "$slice": ["$ids", pagination.skip() as i64, pagination.limit() as i64]
+                            }
                         }
                     },
+                    Self::min_user_lookup("ids", viewer, "items"),
                 ],
                 None,
             )
             .await;
 
-        Self::parse_aggrigate::<Self>(users).await
+        match Self::parse_aggrigate::<UserConnections>(connections).await {
+            Ok(mut pages) => match pages.pop() {
+                Some(page) => Ok(Paginated::new(page.items, page.total, pagination)),
+                None => Ok(Paginated::new(vec![], 0, pagination)),
+            },
+            Err(error) => Err(error),
+        }
     }
 
+    /// Full user documents for server-side use, including the password
+    /// hash and email. Never serialize these into a response as-is.
     pub async fn get_user_by_query<T>(
         data: web::Data<Tweetbook>,
         query: Document,
@@ -164,6 +371,9 @@ impl User {
                             "followers": 0,
                             "following": 0,
                             "messages": 0,
+                            "followRequests": 0,
+                            "blocked": 0,
+                            "muted": 0,
                         }
                     },
                 ],
@@ -183,6 +393,7 @@ impl User {
         let user = Self::get_collection::<Document>(data)
             .insert_one(
                 doc! {
+                    "usernameLower": creds.username.clone().unwrap().to_lowercase(),
                     "username": creds.username.unwrap(),
                     "email": creds.email,
                     "password": hash(creds.password, 10).unwrap()
@@ -194,10 +405,11 @@ impl User {
         match user {
             Ok(inserted) => Ok(MinUser {
                 id: inserted.inserted_id.as_object_id().unwrap(),
-                email: cloned_creds.email,
-                username: cloned_creds.password,
+                username: cloned_creds.username.unwrap(),
+                display_name: None,
                 profile_img_url: Some("".to_string()),
                 bio: None,
+                followed_by_me: None,
             }),
             Err(error) => Err(error),
         }
@@ -240,4 +452,687 @@ impl User {
             Err(error) => Err(error),
         }
     }
+
+    /// Pins `tweet` to `user`'s profile, replacing any earlier pin.
+    /// Ownership is checked by the caller.
+    pub async fn pin_tweet(
+        data: web::Data<Tweetbook>,
+        user: ObjectId,
+        tweet: ObjectId,
+    ) -> Result<(), Error> {
+        let pinned = Self::get_collection::<Document>(data)
+            .update_one(
+                doc! { "_id": user },
+                doc! { "$set": { "pinnedTweetId": tweet } },
+                None,
+            )
+            .await;
+
+        match pinned {
+            Ok(_) => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Clears `user`'s pin if it is `tweet`.
+    pub async fn unpin_tweet(
+        data: web::Data<Tweetbook>,
+        user: ObjectId,
+        tweet: ObjectId,
+    ) -> Result<(), Error> {
+        let unpinned = Self::get_collection::<Document>(data)
+            .update_one(
+                doc! { "_id": user, "pinnedTweetId": tweet },
+                doc! { "$unset": { "pinnedTweetId": "" } },
+                None,
+            )
+            .await;
+
+        match unpinned {
+            Ok(_) => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Suspends or reinstates a user. Returns `false` when the user doesn't
+    /// exist or already was in that state.
+    pub async fn set_suspended(
+        data: web::Data<Tweetbook>,
+        user: ObjectId,
+        suspended: bool,
+    ) -> Result<bool, Error> {
+        let (filter, update) = if suspended {
+            (
+                doc! { "_id": user, "suspendedAt": null },
+                doc! { "$set": { "suspendedAt": DateTime::now() } },
+            )
+        } else {
+            (
+                doc! { "_id": user, "suspendedAt": { "$ne": null } },
+                doc! { "$unset": { "suspendedAt": "" } },
+            )
+        };
+
+        let updated = Self::get_collection::<Document>(data)
+            .update_one(filter, update, None)
+            .await;
+
+        match updated {
+            Ok(result) => Ok(result.modified_count > 0),
+            Err(error) => Err(error),
+        }
+    }
+
+    pub async fn set_role(
+        data: web::Data<Tweetbook>,
+        user: ObjectId,
+        role: Role,
+    ) -> Result<bool, Error> {
+        let updated = Self::get_collection::<Document>(data)
+            .update_one(
+                doc! { "_id": user },
+                doc! { "$set": { "role": to_bson(&role).unwrap() } },
+                None,
+            )
+            .await;
+
+        match updated {
+            Ok(result) => Ok(result.matched_count > 0),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Makes admins of the users listed in `ADMIN_IDS` (comma separated
+    /// ids), so a fresh install has someone who can hand out roles.
+    pub async fn bootstrap_admins(data: web::Data<Tweetbook>) -> Result<(), Error> {
+        let ids: Vec<ObjectId> = env::var("ADMIN_IDS")
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|id| ObjectId::from_str(id.trim()).ok())
+            .collect();
+        if ids.is_empty() {
+            return Ok(());
+        }
+
+        let promoted = Self::get_collection::<Document>(data)
+            .update_many(
+                doc! { "_id": { "$in": ids } },
+                doc! { "$set": { "role": to_bson(&Role::Admin).unwrap() } },
+                None,
+            )
+            .await;
+
+        match promoted {
+            Ok(_) => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Revokes the user's tokens and makes them choose a new password the
+    /// next time they sign in.
+    pub async fn require_password_reset(
+        data: web::Data<Tweetbook>,
+        user: ObjectId,
+    ) -> Result<bool, Error> {
+        let updated = Self::get_collection::<Document>(data)
+            .update_one(
+                doc! { "_id": user },
+                doc! {
+                    "$set": {
+                        "passwordResetRequired": true,
+                        "passwordChangedAt": DateTime::now(),
+                    }
+                },
+                None,
+            )
+            .await;
+
+        match updated {
+            Ok(result) => Ok(result.matched_count > 0),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Replaces the user's password, revoking tokens issued before.
+    pub async fn set_password(
+        data: web::Data<Tweetbook>,
+        user: ObjectId,
+        password: &str,
+    ) -> Result<(), Error> {
+        let updated = Self::get_collection::<Document>(data)
+            .update_one(
+                doc! { "_id": user },
+                doc! {
+                    "$set": {
+                        "password": hash(password, 10).unwrap(),
+                        "passwordChangedAt": DateTime::now(),
+                    },
+                    "$unset": { "passwordResetRequired": "" },
+                },
+                None,
+            )
+            .await;
+
+        match updated {
+            Ok(_) => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Users for the admin console, newest first, optionally only those
+    /// whose username or email starts with `search`.
+    pub async fn admin_search(
+        data: web::Data<Tweetbook>,
+        search: Option<&str>,
+        pagination: &Pagination,
+    ) -> Result<Paginated<AdminUser>, Error> {
+        let filter = match search.map(str::trim).filter(|search| !search.is_empty()) {
+            Some(search) => {
+                let search: String = search.chars().take(MAX_SEARCH_LENGTH).collect();
+                let prefix = format!("^{}", escape_regex(&search.to_lowercase()));
+
+                doc! {
+                    "$or": [
+                        { "usernameLower": { "$regex": &prefix } },
+                        { "email": { "$regex": &prefix, "$options": "i" } },
+                    ]
+                }
+            }
+            None => doc! {},
+        };
+
+        let total = Self::get_collection::<Document>(data.clone())
+            .count_documents(filter.clone(), None)
+            .await;
+
+        match total {
+            Ok(total) => {
+                let users = Self::get_collection::<Document>(data)
+                    .aggregate(
+                        vec![
+                            doc! { "$match": filter },
+                            doc! { "$sort": { "_id": -1 } },
+                            doc! { "$skip": pagination.skip() as i64 },
+                            doc! { "$limit": pagination.limit() as i64 },
+                            doc! {
+                                "$project": {
+                                    "username": 1,
+                                    "displayName": 1,
+                                    "email": 1,
+                                    "role": 1,
+                                    "suspendedAt": 1,
+                                    "passwordResetRequired": 1,
+                                    "spamScore": { "$toLong": { "$ifNull": ["$spamScore", 0] } },
+                                    "restriction": 1,
+                                    "followersCount": { "$size": { "$ifNull": ["$followers", []] } },
+                                    "tweetsCount": { "$size": { "$ifNull": ["$messages", []] } },
+                                }
+                            },
+                        ],
+                        None,
+                    )
+                    .await;
+
+                match Self::parse_aggrigate::<AdminUser>(users).await {
+                    Ok(users) => Ok(Paginated::new(users, total, pagination)),
+                    Err(error) => Err(error),
+                }
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    pub async fn exists(data: web::Data<Tweetbook>, id: ObjectId) -> Result<bool, Error> {
+        let users = Self::get_collection::<Document>(data)
+            .count_documents(doc! { "_id": id }, None)
+            .await;
+
+        match users {
+            Ok(count) => Ok(count > 0),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Whether `actor`'s stored role is strictly higher than `target`'s, as
+    /// needed to take action against another account. Missing users
+    /// outrank no one.
+    pub async fn outranks(
+        data: web::Data<Tweetbook>,
+        actor: ObjectId,
+        target: ObjectId,
+    ) -> Result<bool, Error> {
+        let collection = Self::get_collection::<StoredRole>(data);
+        let options = FindOneOptions::builder()
+            .projection(doc! { "role": 1 })
+            .build();
+
+        let actor = match collection
+            .find_one(doc! { "_id": actor }, options.clone())
+            .await
+        {
+            Ok(Some(actor)) => actor.role,
+            Ok(None) => return Ok(false),
+            Err(error) => return Err(error),
+        };
+
+        match collection.find_one(doc! { "_id": target }, options).await {
+            Ok(Some(target)) => Ok(actor > target.role),
+            Ok(None) => Ok(false),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Whether either user has blocked the other.
+    pub async fn is_blocked_between(
+        data: web::Data<Tweetbook>,
+        first: ObjectId,
+        second: ObjectId,
+    ) -> Result<bool, Error> {
+        let blocks = Self::get_collection::<Document>(data)
+            .count_documents(
+                doc! {
+                    "$or": [
+                        { "_id": first, "blocked": second },
+                        { "_id": second, "blocked": first },
+                    ]
+                },
+                None,
+            )
+            .await;
+
+        match blocks {
+            Ok(count) => Ok(count > 0),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Whether `viewer` may see `owner`'s tweets and connections: blocks hide
+    /// both sides from each other, private accounts are visible only to the
+    /// owner and approved followers.
+    pub async fn access(
+        data: web::Data<Tweetbook>,
+        owner: ObjectId,
+        viewer: ObjectId,
+    ) -> Result<Access, Error> {
+        if owner == viewer {
+            return Ok(Access::Allowed);
+        }
+
+        match Self::is_blocked_between(data.clone(), owner, viewer).await {
+            Ok(true) => Ok(Access::Blocked),
+            Ok(false) => {
+                let visible = Self::get_collection::<Document>(data)
+                    .count_documents(
+                        doc! {
+                            "_id": owner,
+                            "$or": [
+                                { "private": { "$ne": true } },
+                                { "followers": viewer },
+                            ]
+                        },
+                        None,
+                    )
+                    .await;
+
+                match visible {
+                    Ok(0) => Ok(Access::Private),
+                    Ok(_) => Ok(Access::Allowed),
+                    Err(error) => Err(error),
+                }
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Blocks `blocked` for `blocker`, dropping follow edges in both
+    /// directions.
+    pub async fn block(
+        data: web::Data<Tweetbook>,
+        blocker: ObjectId,
+        blocked: ObjectId,
+    ) -> Result<(), Error> {
+        let blocking = Self::update_user(
+            data.clone(),
+            blocker.to_string(),
+            doc! { "$addToSet": { "blocked": blocked }},
+        )
+        .await;
+
+        match blocking {
+            Ok(_) => match Self::unfollow(data.clone(), blocker, blocked).await {
+                Ok(_) => Self::unfollow(data, blocked, blocker).await,
+                Err(error) => Err(error),
+            },
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Adds the `follower -> followee` edge on both users.
+    pub async fn follow(
+        data: web::Data<Tweetbook>,
+        follower: ObjectId,
+        followee: ObjectId,
+    ) -> Result<(), Error> {
+        let following = Self::update_user(
+            data.clone(),
+            follower.to_string(),
+            doc! { "$addToSet": { "following": { "$each": vec![followee]} }},
+        )
+        .await;
+
+        match following {
+            Ok(_) => {
+                let followers = Self::update_user(
+                    data.clone(),
+                    followee.to_string(),
+                    doc! {
+                        "$addToSet": { "followers": { "$each": vec![follower]}},
+                        "$pull": { "followRequests": follower },
+                    },
+                )
+                .await;
+
+                match followers {
+                    Ok(_) => {
+                        data.events
+                            .publish(Channel::Inbox(follower), BusEvent::Followed { followee });
+                        Ok(())
+                    }
+                    Err(error) => Err(error),
+                }
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Removes the `follower -> followee` edge, and any pending request,
+    /// from both users.
+    pub async fn unfollow(
+        data: web::Data<Tweetbook>,
+        follower: ObjectId,
+        followee: ObjectId,
+    ) -> Result<(), Error> {
+        let following = Self::update_user(
+            data.clone(),
+            follower.to_string(),
+            doc! { "$pull": { "following": { "$in": vec![followee]} }},
+        )
+        .await;
+
+        match following {
+            Ok(_) => {
+                let followers = Self::update_user(
+                    data.clone(),
+                    followee.to_string(),
+                    doc! { "$pull": {
+                        "followers": { "$in": vec![follower]},
+                        "followRequests": follower,
+                    }},
+                )
+                .await;
+
+                match followers {
+                    Ok(_) => {
+                        data.events
+                            .publish(Channel::Inbox(follower), BusEvent::Unfollowed { followee });
+                        Ok(())
+                    }
+                    Err(error) => Err(error),
+                }
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Records a pending follow request from `follower` on a private account.
+    pub async fn request_follow(
+        data: web::Data<Tweetbook>,
+        follower: ObjectId,
+        followee: ObjectId,
+    ) -> Result<(), Error> {
+        let requested = Self::update_user(
+            data,
+            followee.to_string(),
+            doc! { "$addToSet": { "followRequests": follower }},
+        )
+        .await;
+
+        match requested {
+            Ok(_) => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Whether `follower` has a pending request on `followee`.
+    pub async fn has_follow_request(
+        data: web::Data<Tweetbook>,
+        follower: ObjectId,
+        followee: ObjectId,
+    ) -> Result<bool, Error> {
+        let requests = Self::get_collection::<Document>(data)
+            .count_documents(doc! { "_id": followee, "followRequests": follower }, None)
+            .await;
+
+        match requests {
+            Ok(count) => Ok(count > 0),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// The user ids stored in one of `user`'s relationship lists, such as
+    /// `following` or `blocked`.
+    pub async fn get_relation_ids(
+        data: web::Data<Tweetbook>,
+        user: ObjectId,
+        field: &str,
+    ) -> Result<Vec<ObjectId>, Error> {
+        let relations = Self::get_collection::<Document>(data)
+            .find_one(
+                doc! { "_id": user },
+                FindOneOptions::builder()
+                    .projection(doc! { field: 1 })
+                    .build(),
+            )
+            .await;
+
+        match relations {
+            Ok(Some(user)) => Ok(user
+                .get_array(field)
+                .map(|ids| ids.iter().filter_map(|id| id.as_object_id()).collect())
+                .unwrap_or_default()),
+            Ok(None) => Ok(vec![]),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Creates the indexes user search relies on and backfills the lowercase
+    /// copies of `username`/`displayName` for users created before them.
+    pub async fn ensure_indexes(data: web::Data<Tweetbook>) -> Result<(), Error> {
+        let collection = Self::get_collection::<Document>(data);
+
+        let backfilled = collection
+            .update_many(
+                doc! { "usernameLower": { "$exists": false } },
+                vec![doc! {
+                    "$set": {
+                        "usernameLower": { "$toLower": "$username" },
+                        "displayNameLower": { "$toLower": "$displayName" },
+                    }
+                }],
+                None,
+            )
+            .await;
+
+        match backfilled {
+            Ok(_) => {
+                let indexes = collection
+                    .create_indexes(
+                        vec![
+                            IndexModel::builder()
+                                .keys(doc! { "usernameLower": 1 })
+                                .build(),
+                            IndexModel::builder()
+                                .keys(doc! { "displayNameLower": 1 })
+                                .build(),
+                        ],
+                        None,
+                    )
+                    .await;
+
+                match indexes {
+                    Ok(_) => Ok(()),
+                    Err(error) => Err(error),
+                }
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Filter for users whose username or display name starts with `search`,
+    /// case-insensitively, excluding users who blocked `viewer` and users
+    /// hidden for looking like spam. Anchored on the lowercase copies so the
+    /// prefix match can use their indexes.
+    fn search_filter(search: &str, viewer: ObjectId) -> Document {
+        let prefix = format!("^{}", escape_regex(&search.to_lowercase()));
+
+        doc! {
+            "$or": [
+                { "usernameLower": { "$regex": &prefix } },
+                { "displayNameLower": { "$regex": &prefix } },
+            ],
+            "blocked": { "$ne": viewer },
+            "restriction": { "$nin": Restriction::Hidden.at_least() },
+        }
+    }
+
+    /// Users matching `search` by prefix, exact matches first.
+    pub async fn search_users(
+        data: web::Data<Tweetbook>,
+        search: &str,
+        viewer: ObjectId,
+        pagination: &Pagination,
+    ) -> Result<Paginated<MinUser>, Error> {
+        let search: String = search.trim().chars().take(MAX_SEARCH_LENGTH).collect();
+        if search.is_empty() {
+            return Ok(Paginated::new(vec![], 0, pagination));
+        }
+
+        let filter = Self::search_filter(&search, viewer);
+        let total = Self::get_collection::<Document>(data.clone())
+            .count_documents(filter.clone(), None)
+            .await;
+
+        match total {
+            Ok(total) => {
+                let lower = search.to_lowercase();
+                let users = Self::get_collection::<Document>(data)
+                    .aggregate(
+                        vec![
+                            doc! { "$match": filter },
+                            doc! {
+                                "$addFields": {
+                                    "exact": {
+                                        "$or": [
+                                            { "$eq": ["$usernameLower", &lower] },
+                                            { "$eq": ["$displayNameLower", &lower] },
+                                        ]
+                                    },
+                                    "followedByMe": {
+                                        "$in": [viewer, { "$ifNull": ["$followers", []] }]
+                                    },
+                                }
+                            },
+                            doc! { "$sort": { "exact": -1, "usernameLower": 1 } },
+                            doc! { "$skip": pagination.skip() as i64 },
+                            doc! { "$limit": pagination.limit() as i64 },
+                            doc! {
+                                "$project": {
+                                    "_id": 1,
+                                    "username": 1,
+                                    "displayName": 1,
+                                    "bio": 1,
+                                    "profileImgUrl": 1,
+                                    "followedByMe": 1,
+                                }
+                            },
+                        ],
+                        None,
+                    )
+                    .await;
+
+                match Self::parse_aggrigate::<MinUser>(users).await {
+                    Ok(items) => Ok(Paginated::new(items, total, pagination)),
+                    Err(error) => Err(error),
+                }
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Top username/display name prefix matches for mention autocomplete,
+    /// exact matches then accounts `viewer` follows first.
+    pub async fn typeahead(
+        data: web::Data<Tweetbook>,
+        search: &str,
+        viewer: ObjectId,
+    ) -> Result<Vec<UserSuggestion>, Error> {
+        let search: String = search
+            .trim()
+            .trim_start_matches('@')
+            .chars()
+            .take(MAX_SEARCH_LENGTH)
+            .collect();
+        if search.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let lower = search.to_lowercase();
+        let users = Self::get_collection::<Document>(data)
+            .aggregate(
+                vec![
+                    doc! { "$match": Self::search_filter(&search, viewer) },
+                    doc! {
+                        "$addFields": {
+                            "exact": { "$eq": ["$usernameLower", &lower] },
+                            "followed": { "$in": [viewer, { "$ifNull": ["$followers", []] }] },
+                        }
+                    },
+                    doc! { "$sort": { "exact": -1, "followed": -1, "usernameLower": 1 } },
+                    doc! { "$limit": TYPEAHEAD_LIMIT },
+                    doc! {
+                        "$project": {
+                            "_id": 1,
+                            "username": 1,
+                            "displayName": 1,
+                            "profileImgUrl": 1,
+                        }
+                    },
+                ],
+                None,
+            )
+            .await;
+
+        Self::parse_aggrigate::<UserSuggestion>(users).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_regex_metacharacters() {
+        assert_eq!(
+            escape_regex(r".*+?()[]{}|^$\"),
+            r"\.\*\+\?\(\)\[\]\{\}\|\^\$\\"
+        );
+        assert_eq!(escape_regex("a-b/c"), r"a\-b\/c");
+    }
+
+    #[test]
+    fn leaves_plain_text_alone() {
+        assert_eq!(escape_regex("ferris_42"), "ferris_42");
+        assert_eq!(escape_regex("Zoë"), "Zoë");
+        assert_eq!(escape_regex(""), "");
+    }
 }
@@ -0,0 +1,368 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::RwLock,
+};
+
+use actix_web::web;
+use futures::StreamExt;
+use mongodb::{
+    bson::{doc, from_document, oid::ObjectId, DateTime, Document},
+    error::Error,
+};
+use serde::Deserialize;
+
+use crate::models::{
+    init::Tweetbook,
+    messages::{Entities, Message},
+};
+
+use super::{recency_weight, SearchQuery, TweetSearch};
+
+/// In-process inverted index over tweet text, rebuilt from the database at
+/// startup and kept current from the tweet write paths. Meant for local runs
+/// where a MongoDB text index isn't available.
+#[derive(Default)]
+pub struct EmbeddedSearch {
+    state: RwLock<IndexState>,
+}
+
+#[derive(Default)]
+struct IndexState {
+    tweets: HashMap<ObjectId, IndexedTweet>,
+    postings: HashMap<String, HashSet<ObjectId>>,
+}
+
+struct IndexedTweet {
+    user: Option<ObjectId>,
+    created_at: DateTime,
+    text: String,
+    terms: HashMap<String, usize>,
+    hashtags: Vec<String>,
+    like_count: u64,
+}
+
+/// A live tweet as stored, read when rebuilding the index.
+#[derive(Debug, Deserialize)]
+struct StoredTweet {
+    #[serde(rename = "_id")]
+    id: ObjectId,
+    user: ObjectId,
+    text: String,
+    #[serde(rename = "createdAt")]
+    created_at: DateTime,
+    likes: Option<Vec<ObjectId>>,
+    #[serde(default)]
+    entities: Entities,
+}
+
+fn tokenize(text: &str) -> Vec<String> {
+    text.to_lowercase()
+        .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|term| !term.is_empty())
+        .map(|term| term.to_string())
+        .collect()
+}
+
+impl IndexState {
+    fn insert(&mut self, id: ObjectId, tweet: IndexedTweet) {
+        self.delete(id);
+
+        for term in tweet.terms.keys() {
+            self.postings.entry(term.clone()).or_default().insert(id);
+        }
+        self.tweets.insert(id, tweet);
+    }
+
+    fn delete(&mut self, id: ObjectId) {
+        if let Some(old) = self.tweets.remove(&id) {
+            for term in old.terms.keys() {
+                if let Some(ids) = self.postings.get_mut(term) {
+                    ids.remove(&id);
+                    if ids.is_empty() {
+                        self.postings.remove(term);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Ids of up to `max_hits` tweets matching `query`, ranked by term
+    /// relevance weighted by age at `now`, then newest first.
+    fn search(&self, query: &SearchQuery, max_hits: usize, now: DateTime) -> Vec<ObjectId> {
+        let candidates: HashSet<ObjectId> = if query.terms.is_empty() {
+            self.tweets.keys().copied().collect()
+        } else {
+            query
+                .terms
+                .iter()
+                .filter_map(|term| self.postings.get(term))
+                .flatten()
+                .copied()
+                .collect()
+        };
+
+        let total_tweets = self.tweets.len().max(1) as f64;
+        let mut hits: Vec<(ObjectId, f64, DateTime)> = candidates
+            .into_iter()
+            .filter_map(|id| self.tweets.get(&id).map(|tweet| (id, tweet)))
+            .filter(|(_, tweet)| tweet.matches(query))
+            .map(|(id, tweet)| {
+                let relevance = if query.has_text() {
+                    let terms: f64 = query
+                        .terms
+                        .iter()
+                        .map(|term| {
+                            let frequency = *tweet.terms.get(term).unwrap_or(&0) as f64;
+                            let documents = self.postings.get(term).map_or(0, |ids| ids.len());
+                            frequency * ((total_tweets / (documents.max(1) as f64)).ln() + 1.0)
+                        })
+                        .sum();
+                    terms + query.phrases.len() as f64
+                } else {
+                    1.0
+                };
+
+                (
+                    id,
+                    relevance * recency_weight(tweet.created_at, now),
+                    tweet.created_at,
+                )
+            })
+            .collect();
+
+        hits.sort_by(|a, b| b.1.total_cmp(&a.1).then(b.2.cmp(&a.2)));
+
+        hits.into_iter()
+            .take(max_hits)
+            .map(|(id, _, _)| id)
+            .collect()
+    }
+}
+
+impl IndexedTweet {
+    fn new(
+        user: Option<ObjectId>,
+        created_at: DateTime,
+        text: &str,
+        entities: &Entities,
+        like_count: u64,
+    ) -> Self {
+        let mut terms = HashMap::new();
+        for term in tokenize(text) {
+            *terms.entry(term).or_insert(0) += 1;
+        }
+
+        IndexedTweet {
+            user,
+            created_at,
+            text: text.to_lowercase(),
+            terms,
+            hashtags: entities
+                .hashtags
+                .iter()
+                .map(|tag| tag.tag.clone())
+                .collect(),
+            like_count,
+        }
+    }
+
+    fn matches(&self, query: &SearchQuery) -> bool {
+        query
+            .phrases
+            .iter()
+            .all(|phrase| self.text.contains(phrase.as_str()))
+            && query.from_user.is_none_or(|user| self.user == Some(user))
+            && query.since.is_none_or(|since| self.created_at >= since)
+            && query.until.is_none_or(|until| self.created_at < until)
+            && query.min_likes.is_none_or(|likes| self.like_count >= likes)
+            && query.hashtags.iter().all(|tag| self.hashtags.contains(tag))
+    }
+}
+
+impl TweetSearch for EmbeddedSearch {
+    async fn rebuild(&self, data: web::Data<Tweetbook>) -> Result<(), Error> {
+        let cursor = Message::get_collection::<Document>(data)
+            .find(
+                doc! { "deletedAt": null, "heldForReview": { "$ne": true } },
+                None,
+            )
+            .await;
+
+        match cursor {
+            Ok(mut tweets) => {
+                let mut state = IndexState::default();
+
+                while let Some(res) = tweets.next().await {
+                    let tweet: StoredTweet = from_document(res.unwrap()).unwrap();
+                    let like_count = tweet.likes.map_or(0, |likes| likes.len() as u64);
+
+                    state.insert(
+                        tweet.id,
+                        IndexedTweet::new(
+                            Some(tweet.user),
+                            tweet.created_at,
+                            &tweet.text,
+                            &tweet.entities,
+                            like_count,
+                        ),
+                    );
+                }
+
+                *self.state.write().unwrap() = state;
+                Ok(())
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    async fn search(
+        &self,
+        _data: web::Data<Tweetbook>,
+        query: &SearchQuery,
+        max_hits: usize,
+    ) -> Result<Vec<ObjectId>, Error> {
+        let state = self.state.read().unwrap();
+
+        Ok(state.search(query, max_hits, DateTime::now()))
+    }
+
+    fn index(&self, message: &Message) {
+        let tweet = IndexedTweet::new(
+            message.user.as_ref().map(|user| user.id),
+            message.created_at,
+            &message.text,
+            &message.entities,
+            message.like_count,
+        );
+
+        self.state.write().unwrap().insert(message.id, tweet);
+    }
+
+    fn remove(&self, message_id: ObjectId) {
+        self.state.write().unwrap().delete(message_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::messages::Hashtag;
+
+    use super::*;
+
+    const DAY_MILLIS: i64 = 24 * 60 * 60 * 1000;
+
+    fn at(days: i64) -> DateTime {
+        DateTime::from_millis(100 * DAY_MILLIS + days * DAY_MILLIS)
+    }
+
+    fn tweet(user: ObjectId, days: i64, text: &str, tags: &[&str], likes: u64) -> IndexedTweet {
+        let entities = Entities {
+            hashtags: tags
+                .iter()
+                .map(|tag| Hashtag {
+                    tag: tag.to_string(),
+                    start: 0,
+                    end: 0,
+                })
+                .collect(),
+            ..Entities::default()
+        };
+
+        IndexedTweet::new(Some(user), at(days), text, &entities, likes)
+    }
+
+    fn query(q: &str) -> SearchQuery {
+        SearchQuery::parse(q).unwrap()
+    }
+
+    #[test]
+    fn matches_phrases_authors_dates_likes_and_tags() {
+        let author = ObjectId::new();
+        let tweet = tweet(author, 0, "Async traits are Here", &["rust"], 2);
+
+        assert!(tweet.matches(&query(r#""traits are here""#)));
+        assert!(!tweet.matches(&query(r#""here are traits""#)));
+
+        let mut from = query("from:ferris");
+        from.from_user = Some(author);
+        assert!(tweet.matches(&from));
+        from.from_user = Some(ObjectId::new());
+        assert!(!tweet.matches(&from));
+
+        let mut dated = query("async");
+        dated.since = Some(at(0));
+        dated.until = Some(at(1));
+        assert!(tweet.matches(&dated));
+        dated.since = Some(at(1));
+        assert!(!tweet.matches(&dated));
+        dated.since = None;
+        dated.until = Some(at(0));
+        assert!(!tweet.matches(&dated));
+
+        assert!(tweet.matches(&query("async has:likes")));
+        assert!(tweet.matches(&query("async min_likes:2")));
+        assert!(!tweet.matches(&query("async min_likes:3")));
+
+        assert!(tweet.matches(&query("#Rust")));
+        assert!(!tweet.matches(&query("#rust #go")));
+    }
+
+    #[test]
+    fn cleans_up_postings() {
+        let mut state = IndexState::default();
+        let id = ObjectId::new();
+        let author = ObjectId::new();
+
+        state.insert(id, tweet(author, 0, "hello world", &[], 0));
+        assert_eq!(state.postings["hello"].len(), 1);
+
+        state.insert(id, tweet(author, 0, "goodbye world", &[], 0));
+        assert!(!state.postings.contains_key("hello"));
+        assert_eq!(state.postings["world"].len(), 1);
+
+        state.delete(id);
+        assert!(state.tweets.is_empty());
+        assert!(state.postings.is_empty());
+
+        state.delete(id);
+        assert!(state.tweets.is_empty());
+    }
+
+    #[test]
+    fn ranks_by_relevance_and_recency() {
+        let mut state = IndexState::default();
+        let author = ObjectId::new();
+        let (once, twice, old_twice, other) = (
+            ObjectId::new(),
+            ObjectId::new(),
+            ObjectId::new(),
+            ObjectId::new(),
+        );
+
+        state.insert(once, tweet(author, 0, "rust is fun", &[], 0));
+        state.insert(twice, tweet(author, 0, "rust rust everywhere", &[], 0));
+        state.insert(old_twice, tweet(author, -3, "rust and more rust", &[], 0));
+        state.insert(other, tweet(author, 0, "go is fun", &[], 0));
+
+        let now = at(0);
+        assert_eq!(
+            state.search(&query("rust"), 10, now),
+            [twice, once, old_twice]
+        );
+        assert_eq!(state.search(&query("rust"), 2, now), [twice, once]);
+        assert!(state.search(&query("python"), 10, now).is_empty());
+    }
+
+    #[test]
+    fn ranks_newest_first_without_text() {
+        let mut state = IndexState::default();
+        let author = ObjectId::new();
+        let (older, newer) = (ObjectId::new(), ObjectId::new());
+
+        state.insert(older, tweet(author, -1, "first", &["til"], 0));
+        state.insert(newer, tweet(author, 0, "second", &["til"], 0));
+        state.insert(ObjectId::new(), tweet(author, 0, "untagged", &[], 0));
+
+        assert_eq!(state.search(&query("#til"), 10, at(0)), [newer, older]);
+    }
+}
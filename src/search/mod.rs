@@ -0,0 +1,268 @@
+pub mod embedded;
+pub mod mongo;
+
+use actix_web::web;
+use chrono::NaiveDate;
+use mongodb::{
+    bson::{oid::ObjectId, DateTime},
+    error::Error,
+};
+
+use crate::{
+    models::{init::Tweetbook, messages::Message},
+    utils::error::UserError,
+};
+
+use self::{embedded::EmbeddedSearch, mongo::MongoSearch};
+
+/// A parsed `/api/search/tweets` query, e.g.
+/// `rust "async traits" from:ferris since:2024-01-01 has:likes #til`.
+#[derive(Debug, Default)]
+pub struct SearchQuery {
+    pub terms: Vec<String>,
+    pub phrases: Vec<String>,
+    pub from: Option<String>,
+    /// `from` resolved to a user id by the caller before searching.
+    pub from_user: Option<ObjectId>,
+    pub since: Option<DateTime>,
+    pub until: Option<DateTime>,
+    pub min_likes: Option<u64>,
+    pub hashtags: Vec<String>,
+}
+
+fn parse_date(value: &str) -> Result<DateTime, UserError> {
+    match NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        Ok(date) => Ok(DateTime::from_millis(
+            date.and_hms_opt(0, 0, 0).unwrap().timestamp_millis(),
+        )),
+        Err(_) => Err(UserError::InvalidSearchQuery),
+    }
+}
+
+impl SearchQuery {
+    pub fn parse(q: &str) -> Result<SearchQuery, UserError> {
+        let mut query = SearchQuery::default();
+        let mut rest = q.trim();
+
+        while !rest.is_empty() {
+            if let Some(quoted) = rest.strip_prefix('"') {
+                let end = quoted.find('"').unwrap_or(quoted.len());
+                let phrase = quoted[..end].trim().to_lowercase();
+                if !phrase.is_empty() {
+                    query.phrases.push(phrase);
+                }
+                rest = quoted.get(end + 1..).unwrap_or("").trim_start();
+                continue;
+            }
+
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            let token = &rest[..end];
+            rest = rest[end..].trim_start();
+
+            if let Some(name) = token.strip_prefix("from:") {
+                query.from = Some(name.trim_start_matches('@').to_string());
+            } else if let Some(date) = token.strip_prefix("since:") {
+                query.since = Some(parse_date(date)?);
+            } else if let Some(date) = token.strip_prefix("until:") {
+                query.until = Some(parse_date(date)?);
+            } else if token == "has:likes" {
+                query.min_likes = Some(query.min_likes.unwrap_or(1).max(1));
+            } else if let Some(count) = token.strip_prefix("min_likes:") {
+                match count.parse::<u64>() {
+                    Ok(count) => query.min_likes = Some(count),
+                    Err(_) => return Err(UserError::InvalidSearchQuery),
+                }
+            } else if let Some(tag) = token.strip_prefix('#') {
+                if !tag.is_empty() {
+                    query.hashtags.push(tag.to_lowercase());
+                }
+            } else {
+                query.terms.push(token.to_lowercase());
+            }
+        }
+
+        if query.terms.is_empty()
+            && query.phrases.is_empty()
+            && query.hashtags.is_empty()
+            && query.from.is_none()
+        {
+            return Err(UserError::InvalidSearchQuery);
+        }
+
+        Ok(query)
+    }
+
+    pub fn has_text(&self) -> bool {
+        !self.terms.is_empty() || !self.phrases.is_empty()
+    }
+}
+
+/// Weight applied to a text relevance score so that, at equal relevance,
+/// newer tweets rank first: a day-old tweet weighs half as much as a new
+/// one, a two-day-old one a third.
+pub fn recency_weight(created_at: DateTime, now: DateTime) -> f64 {
+    let age_days = (now.timestamp_millis() - created_at.timestamp_millis()).max(0) as f64
+        / (24.0 * 60.0 * 60.0 * 1000.0);
+    1.0 / (1.0 + age_days)
+}
+
+/// A backend able to answer tweet searches. Backends that keep their own
+/// index are told about every tweet write; the MongoDB one ignores those.
+pub trait TweetSearch {
+    /// Prepares the backend at startup.
+    async fn rebuild(&self, data: web::Data<Tweetbook>) -> Result<(), Error>;
+
+    /// Ids of up to `max_hits` matching tweets, best match first. Callers
+    /// page through them after dropping the ones the viewer may not see.
+    async fn search(
+        &self,
+        data: web::Data<Tweetbook>,
+        query: &SearchQuery,
+        max_hits: usize,
+    ) -> Result<Vec<ObjectId>, Error>;
+
+    fn index(&self, message: &Message);
+
+    fn remove(&self, message_id: ObjectId);
+}
+
+/// The backend selected by `SEARCH_BACKEND`: `mongo` (default) uses a text
+/// index, `embedded` keeps an in-process index for local runs.
+pub enum SearchIndex {
+    Mongo(MongoSearch),
+    Embedded(EmbeddedSearch),
+}
+
+impl SearchIndex {
+    pub fn from_env() -> Self {
+        match std::env::var("SEARCH_BACKEND").as_deref() {
+            Ok("embedded") => SearchIndex::Embedded(EmbeddedSearch::default()),
+            _ => SearchIndex::Mongo(MongoSearch),
+        }
+    }
+}
+
+impl TweetSearch for SearchIndex {
+    async fn rebuild(&self, data: web::Data<Tweetbook>) -> Result<(), Error> {
+        match self {
+            SearchIndex::Mongo(search) => search.rebuild(data).await,
+            SearchIndex::Embedded(search) => search.rebuild(data).await,
+        }
+    }
+
+    async fn search(
+        &self,
+        data: web::Data<Tweetbook>,
+        query: &SearchQuery,
+        max_hits: usize,
+    ) -> Result<Vec<ObjectId>, Error> {
+        match self {
+            SearchIndex::Mongo(search) => search.search(data, query, max_hits).await,
+            SearchIndex::Embedded(search) => search.search(data, query, max_hits).await,
+        }
+    }
+
+    fn index(&self, message: &Message) {
+        if message.held_for_review {
+            return self.remove(message.id);
+        }
+
+        match self {
+            SearchIndex::Mongo(search) => search.index(message),
+            SearchIndex::Embedded(search) => search.index(message),
+        }
+    }
+
+    fn remove(&self, message_id: ObjectId) {
+        match self {
+            SearchIndex::Mongo(search) => search.remove(message_id),
+            SearchIndex::Embedded(search) => search.remove(message_id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_terms_and_phrases() {
+        let query = SearchQuery::parse(r#"  Rust "Async  Traits" tips "unclosed"#).unwrap();
+        assert_eq!(query.terms, ["rust", "tips"]);
+        assert_eq!(query.phrases, ["async  traits", "unclosed"]);
+        assert!(query.has_text());
+
+        let query = SearchQuery::parse(r#"rust """#).unwrap();
+        assert!(query.phrases.is_empty());
+    }
+
+    #[test]
+    fn parses_operators() {
+        let query =
+            SearchQuery::parse("from:@ferris since:2024-01-01 until:2024-02-01 #TIL").unwrap();
+        assert_eq!(query.from.as_deref(), Some("ferris"));
+        assert_eq!(query.since, Some(parse_date("2024-01-01").unwrap()));
+        assert_eq!(
+            query.until.unwrap().timestamp_millis(),
+            query.since.unwrap().timestamp_millis() + 31 * 24 * 60 * 60 * 1000
+        );
+        assert_eq!(query.hashtags, ["til"]);
+        assert!(!query.has_text());
+    }
+
+    #[test]
+    fn parses_like_filters() {
+        assert_eq!(
+            SearchQuery::parse("a has:likes").unwrap().min_likes,
+            Some(1)
+        );
+        assert_eq!(
+            SearchQuery::parse("a min_likes:10").unwrap().min_likes,
+            Some(10)
+        );
+        assert_eq!(
+            SearchQuery::parse("a min_likes:0 has:likes")
+                .unwrap()
+                .min_likes,
+            Some(1)
+        );
+        assert_eq!(
+            SearchQuery::parse("a min_likes:5 has:likes")
+                .unwrap()
+                .min_likes,
+            Some(5)
+        );
+        assert!(SearchQuery::parse("a min_likes:many").is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_dates() {
+        for q in [
+            "a since:yesterday",
+            "a since:2024-13-01",
+            "a until:2024-02-30",
+            "a until:",
+        ] {
+            assert!(SearchQuery::parse(q).is_err(), "{}", q);
+        }
+    }
+
+    #[test]
+    fn needs_something_to_search_for() {
+        for q in ["", "   ", "since:2024-01-01", "has:likes", "#", r#""""#] {
+            assert!(SearchQuery::parse(q).is_err(), "{:?}", q);
+        }
+        assert!(SearchQuery::parse("from:ferris").is_ok());
+    }
+
+    #[test]
+    fn weighs_newer_tweets_higher() {
+        let day = 24 * 60 * 60 * 1000;
+        let now = DateTime::from_millis(10 * day);
+
+        assert_eq!(recency_weight(now, now), 1.0);
+        assert_eq!(recency_weight(DateTime::from_millis(9 * day), now), 0.5);
+        assert!((recency_weight(DateTime::from_millis(8 * day), now) - 1.0 / 3.0).abs() < 1e-9);
+        assert_eq!(recency_weight(DateTime::from_millis(11 * day), now), 1.0);
+    }
+}
@@ -0,0 +1,128 @@
+use actix_web::web;
+use futures::StreamExt;
+use mongodb::{
+    bson::{doc, from_document, oid::ObjectId, Document},
+    error::Error,
+    IndexModel,
+};
+use serde::Deserialize;
+
+use crate::models::{init::Tweetbook, messages::Message};
+
+use super::{SearchQuery, TweetSearch};
+
+/// Searches through a MongoDB text index on `messages.text`.
+pub struct MongoSearch;
+
+#[derive(Debug, Deserialize)]
+struct HitId {
+    #[serde(rename = "_id")]
+    id: ObjectId,
+}
+
+impl MongoSearch {
+    fn filter(query: &SearchQuery) -> Document {
+        let mut filter = doc! { "deletedAt": null, "heldForReview": { "$ne": true } };
+
+        if query.has_text() {
+            let mut search = query.terms.join(" ");
+            for phrase in &query.phrases {
+                search.push_str(&format!(" \"{}\"", phrase));
+            }
+            filter.insert("$text", doc! { "$search": search.trim() });
+        }
+        if let Some(user) = query.from_user {
+            filter.insert("user", user);
+        }
+        if query.since.is_some() || query.until.is_some() {
+            let mut created_at = Document::new();
+            if let Some(since) = query.since {
+                created_at.insert("$gte", since);
+            }
+            if let Some(until) = query.until {
+                created_at.insert("$lt", until);
+            }
+            filter.insert("createdAt", created_at);
+        }
+        if let Some(min_likes) = query.min_likes.filter(|likes| *likes > 0) {
+            filter.insert(format!("likes.{}", min_likes - 1), doc! { "$exists": true });
+        }
+        if !query.hashtags.is_empty() {
+            filter.insert("entities.hashtags.tag", doc! { "$all": &query.hashtags });
+        }
+
+        filter
+    }
+}
+
+impl TweetSearch for MongoSearch {
+    async fn rebuild(&self, data: web::Data<Tweetbook>) -> Result<(), Error> {
+        let index = Message::get_collection::<Document>(data)
+            .create_index(
+                IndexModel::builder().keys(doc! { "text": "text" }).build(),
+                None,
+            )
+            .await;
+
+        match index {
+            Ok(_) => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+
+    async fn search(
+        &self,
+        data: web::Data<Tweetbook>,
+        query: &SearchQuery,
+        max_hits: usize,
+    ) -> Result<Vec<ObjectId>, Error> {
+        let mut pipeline = vec![doc! { "$match": Self::filter(query) }];
+
+        if query.has_text() {
+            pipeline.push(doc! {
+                "$addFields": {
+                    "rank": {
+                        "$divide": [
+                            { "$meta": "textScore" },
+                            {
+                                "$add": [1, {
+                                    "$divide": [{ "$subtract": ["$$NOW", "$createdAt"] }, 86_400_000]
+                                }]
+                            }
+                        ]
+                    }
+                }
+            });
+            pipeline.push(doc! { "$sort": { "rank": -1, "createdAt": -1 } });
+        } else {
+            pipeline.push(doc! { "$sort": { "createdAt": -1 } });
+        }
+
+        pipeline.push(doc! { "$limit": max_hits as i64 });
+        pipeline.push(doc! { "$project": { "_id": 1 } });
+
+        let cursor = Message::get_collection::<Document>(data)
+            .aggregate(pipeline, None)
+            .await;
+
+        match cursor {
+            Ok(mut hits) => {
+                let mut ids = vec![];
+
+                while let Some(res) = hits.next().await {
+                    match res {
+                        Ok(hit) => ids.push(from_document::<HitId>(hit).unwrap().id),
+                        Err(error) => return Err(error),
+                    }
+                }
+
+                Ok(ids)
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    fn index(&self, _message: &Message) {}
+
+    fn remove(&self, _message_id: ObjectId) {}
+}
@@ -0,0 +1,63 @@
+use std::{fs, io, path::PathBuf};
+
+use actix_web::web;
+
+use super::MediaStorage;
+
+/// Route local media is served from.
+pub const MEDIA_ROUTE: &str = "/media";
+
+/// Stores media as files under `MEDIA_DIR`, served by the app itself.
+pub struct LocalStorage {
+    dir: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn from_env() -> Self {
+        Self::new(Self::dir())
+    }
+
+    pub fn new(dir: PathBuf) -> Self {
+        LocalStorage { dir }
+    }
+
+    pub fn dir() -> PathBuf {
+        PathBuf::from(std::env::var("MEDIA_DIR").unwrap_or_else(|_| "./media".to_string()))
+    }
+}
+
+impl MediaStorage for LocalStorage {
+    async fn put(&self, key: &str, bytes: Vec<u8>, _content_type: &str) -> io::Result<()> {
+        let path = self.dir.join(key);
+
+        match web::block(move || {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(path, bytes)
+        })
+        .await
+        {
+            Ok(written) => written,
+            Err(error) => Err(io::Error::other(error)),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        let path = self.dir.join(key);
+
+        match web::block(move || match fs::remove_file(path) {
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+            removed => removed,
+        })
+        .await
+        {
+            Ok(removed) => removed,
+            Err(error) => Err(io::Error::other(error)),
+        }
+    }
+
+    fn url(&self, key: &str) -> String {
+        format!("{}/{}", MEDIA_ROUTE, key)
+    }
+}
@@ -0,0 +1,62 @@
+pub mod local;
+pub mod s3;
+
+use std::io;
+
+use self::{local::LocalStorage, s3::S3Storage};
+
+/// Somewhere uploaded media can be written to and served from.
+pub trait MediaStorage {
+    async fn put(&self, key: &str, bytes: Vec<u8>, content_type: &str) -> io::Result<()>;
+
+    async fn delete(&self, key: &str) -> io::Result<()>;
+
+    /// Public URL the stored object is served at.
+    fn url(&self, key: &str) -> String;
+}
+
+/// The backend selected by `MEDIA_STORAGE`: `local` (default) writes under
+/// `MEDIA_DIR` and serves it from `/media`, `s3` talks to any S3-compatible
+/// object store.
+pub enum Storage {
+    Local(LocalStorage),
+    S3(S3Storage),
+}
+
+impl Storage {
+    pub fn from_env() -> Self {
+        if Self::is_local() {
+            Storage::Local(LocalStorage::from_env())
+        } else {
+            Storage::S3(S3Storage::from_env())
+        }
+    }
+
+    /// Whether the app serves media itself rather than an object store.
+    pub fn is_local() -> bool {
+        std::env::var("MEDIA_STORAGE").as_deref() != Ok("s3")
+    }
+}
+
+impl MediaStorage for Storage {
+    async fn put(&self, key: &str, bytes: Vec<u8>, content_type: &str) -> io::Result<()> {
+        match self {
+            Storage::Local(storage) => storage.put(key, bytes, content_type).await,
+            Storage::S3(storage) => storage.put(key, bytes, content_type).await,
+        }
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        match self {
+            Storage::Local(storage) => storage.delete(key).await,
+            Storage::S3(storage) => storage.delete(key).await,
+        }
+    }
+
+    fn url(&self, key: &str) -> String {
+        match self {
+            Storage::Local(storage) => storage.url(key),
+            Storage::S3(storage) => storage.url(key),
+        }
+    }
+}
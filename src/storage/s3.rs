@@ -0,0 +1,336 @@
+use std::io;
+
+use awc::{http::Method, Client};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+use super::MediaStorage;
+
+type HmacSha256 = Hmac<Sha256>;
+
+const SIGNED_HEADERS: &str = "host;x-amz-content-sha256;x-amz-date";
+
+/// Stores media in a bucket of an S3-compatible object store (AWS, MinIO,
+/// ...), addressed path-style so a local stand-in works without DNS setup.
+pub struct S3Storage {
+    /// e.g. `https://s3.eu-west-1.amazonaws.com` or `http://localhost:9000`.
+    endpoint: String,
+    bucket: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+    /// Where objects are publicly served from, if not the bucket URL itself
+    /// (e.g. a CDN in front of it).
+    public_url: Option<String>,
+}
+
+fn hmac(key: &[u8], message: &str) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).unwrap();
+    mac.update(message.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+/// Percent-encodes a key for the canonical URI, keeping `/` separators.
+fn encode_key(key: &str) -> String {
+    let mut encoded = String::with_capacity(key.len());
+    for byte in key.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+impl S3Storage {
+    pub fn from_env() -> Self {
+        let var = |key: &str| std::env::var(key).unwrap_or_default();
+
+        S3Storage {
+            endpoint: var("MEDIA_S3_ENDPOINT").trim_end_matches('/').to_string(),
+            bucket: var("MEDIA_S3_BUCKET"),
+            region: std::env::var("MEDIA_S3_REGION").unwrap_or_else(|_| "us-east-1".to_string()),
+            access_key: var("MEDIA_S3_ACCESS_KEY"),
+            secret_key: var("MEDIA_S3_SECRET_KEY"),
+            public_url: std::env::var("MEDIA_PUBLIC_URL")
+                .ok()
+                .map(|url| url.trim_end_matches('/').to_string()),
+        }
+    }
+
+    fn path(&self, key: &str) -> String {
+        format!("/{}/{}", self.bucket, encode_key(key))
+    }
+
+    fn host(&self) -> &str {
+        self.endpoint
+            .split_once("://")
+            .map_or(self.endpoint.as_str(), |(_, host)| host)
+    }
+
+    /// The canonical request AWS Signature Version 4 signs, covering the
+    /// host, payload hash and date headers.
+    fn canonical_request(
+        &self,
+        method: &Method,
+        path: &str,
+        payload_hash: &str,
+        amz_date: &str,
+    ) -> String {
+        format!(
+            "{}\n{}\n\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+            method,
+            path,
+            self.host(),
+            payload_hash,
+            amz_date,
+            SIGNED_HEADERS,
+            payload_hash
+        )
+    }
+
+    /// Headers for an AWS Signature Version 4 signed request.
+    fn signed_headers(&self, method: &Method, path: &str, payload: &[u8]) -> Vec<(&str, String)> {
+        self.signed_headers_at(method, path, payload, Utc::now())
+    }
+
+    fn signed_headers_at(
+        &self,
+        method: &Method,
+        path: &str,
+        payload: &[u8],
+        now: DateTime<Utc>,
+    ) -> Vec<(&str, String)> {
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let payload_hash = sha256_hex(payload);
+        let canonical_request = self.canonical_request(method, path, &payload_hash, &amz_date);
+
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            sha256_hex(canonical_request.as_bytes())
+        );
+
+        let key = hmac(format!("AWS4{}", self.secret_key).as_bytes(), &date);
+        let key = hmac(&key, &self.region);
+        let key = hmac(&key, "s3");
+        let key = hmac(&key, "aws4_request");
+        let signature = hex::encode(hmac(&key, &string_to_sign));
+
+        vec![
+            (
+                "authorization",
+                format!(
+                    "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                    self.access_key, scope, SIGNED_HEADERS, signature
+                ),
+            ),
+            ("x-amz-content-sha256", payload_hash),
+            ("x-amz-date", amz_date),
+        ]
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        key: &str,
+        body: Vec<u8>,
+        content_type: Option<&str>,
+    ) -> io::Result<()> {
+        let path = self.path(key);
+        let mut request =
+            Client::default().request(method.clone(), format!("{}{}", self.endpoint, path));
+        for (name, value) in self.signed_headers(&method, &path, &body) {
+            request = request.insert_header((name, value));
+        }
+        if let Some(content_type) = content_type {
+            request = request.insert_header(("content-type", content_type));
+        }
+
+        match request.send_body(body).await {
+            Ok(response) if response.status().is_success() => Ok(()),
+            Ok(response) => Err(io::Error::other(format!(
+                "object store answered {} for {}",
+                response.status(),
+                key
+            ))),
+            Err(error) => Err(io::Error::other(error.to_string())),
+        }
+    }
+}
+
+impl MediaStorage for S3Storage {
+    async fn put(&self, key: &str, bytes: Vec<u8>, content_type: &str) -> io::Result<()> {
+        self.send(Method::PUT, key, bytes, Some(content_type)).await
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        self.send(Method::DELETE, key, vec![], None).await
+    }
+
+    fn url(&self, key: &str) -> String {
+        match &self.public_url {
+            Some(url) => format!("{}/{}", url, encode_key(key)),
+            None => format!("{}{}", self.endpoint, self.path(key)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+
+    use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+    use chrono::TimeZone;
+
+    use super::*;
+
+    const HELLO_HASH: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+    fn storage(endpoint: &str) -> S3Storage {
+        S3Storage {
+            endpoint: endpoint.to_string(),
+            bucket: "media".to_string(),
+            region: "us-east-1".to_string(),
+            access_key: "AKIDEXAMPLE".to_string(),
+            secret_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_string(),
+            public_url: None,
+        }
+    }
+
+    #[test]
+    fn encodes_keys_for_the_canonical_uri() {
+        assert_eq!(encode_key("avatars/a b+c.png"), "avatars/a%20b%2Bc.png");
+        assert_eq!(encode_key("a-b_c.~d"), "a-b_c.~d");
+    }
+
+    #[test]
+    fn builds_the_canonical_request() {
+        let storage = storage("http://localhost:9000");
+        let path = storage.path("avatars/a b.png");
+
+        assert_eq!(
+            storage.canonical_request(&Method::PUT, &path, HELLO_HASH, "20240102T030405Z"),
+            format!(
+                "PUT\n/media/avatars/a%20b.png\n\nhost:localhost:9000\n\
+                 x-amz-content-sha256:{0}\nx-amz-date:20240102T030405Z\n\n\
+                 host;x-amz-content-sha256;x-amz-date\n{0}",
+                HELLO_HASH
+            )
+        );
+    }
+
+    #[test]
+    fn signs_with_signature_version_4() {
+        let storage = storage("http://localhost:9000");
+        let now = Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap();
+        let headers: HashMap<_, _> = storage
+            .signed_headers_at(&Method::PUT, "/media/avatars/a%20b.png", b"hello", now)
+            .into_iter()
+            .collect();
+
+        assert_eq!(headers["x-amz-date"], "20240102T030405Z");
+        assert_eq!(headers["x-amz-content-sha256"], HELLO_HASH);
+        assert_eq!(
+            headers["authorization"],
+            "AWS4-HMAC-SHA256 \
+             Credential=AKIDEXAMPLE/20240102/us-east-1/s3/aws4_request, \
+             SignedHeaders=host;x-amz-content-sha256;x-amz-date, \
+             Signature=9286ae12f3e7b2107362ca64ca7f966c8e9f8fdf56bfe036d5cde9dece11aabb"
+        );
+    }
+
+    #[test]
+    fn builds_public_urls() {
+        let mut storage = storage("http://localhost:9000");
+        assert_eq!(
+            storage.url("avatars/a.png"),
+            "http://localhost:9000/media/avatars/a.png"
+        );
+
+        storage.public_url = Some("https://cdn.example.com".to_string());
+        assert_eq!(
+            storage.url("avatars/a.png"),
+            "https://cdn.example.com/avatars/a.png"
+        );
+    }
+
+    type Objects = Arc<Mutex<HashMap<String, Vec<u8>>>>;
+
+    /// A bucket stand-in that only accepts signed requests.
+    async fn object(
+        req: HttpRequest,
+        body: web::Bytes,
+        objects: web::Data<Objects>,
+    ) -> HttpResponse {
+        let signed = req
+            .headers()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/"));
+        if !signed {
+            return HttpResponse::Forbidden().finish();
+        }
+
+        let mut objects = objects.lock().unwrap();
+        let path = req.path().to_string();
+        match *req.method() {
+            Method::PUT => {
+                objects.insert(path, body.to_vec());
+                HttpResponse::Ok().finish()
+            }
+            Method::DELETE => match objects.remove(&path) {
+                Some(_) => HttpResponse::NoContent().finish(),
+                None => HttpResponse::NotFound().finish(),
+            },
+            _ => HttpResponse::MethodNotAllowed().finish(),
+        }
+    }
+
+    #[actix_web::test]
+    async fn puts_and_deletes_objects() {
+        let objects = Objects::default();
+        let data = web::Data::new(objects.clone());
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(data.clone())
+                .default_service(web::to(object))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let address = server.addrs()[0];
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        let storage = storage(&format!("http://{}", address));
+        storage
+            .put("avatars/a b.png", b"hello".to_vec(), "image/png")
+            .await
+            .unwrap();
+        assert_eq!(
+            objects.lock().unwrap().get("/media/avatars/a%20b.png"),
+            Some(&b"hello".to_vec())
+        );
+
+        storage.delete("avatars/a b.png").await.unwrap();
+        assert!(objects.lock().unwrap().is_empty());
+        assert!(storage.delete("avatars/a b.png").await.is_err());
+
+        handle.stop(false).await;
+    }
+}
@@ -1,4 +1,8 @@
-use actix_web::{http::header, web::Data, HttpRequest};
+use actix_web::{
+    http::header,
+    web::{Data, Query},
+    HttpRequest,
+};
 use chrono::{Months, Utc};
 use dotenv::dotenv;
 use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
@@ -8,32 +12,42 @@ use std::env;
 
 use crate::models::{
     init::Tweetbook,
-    users::{MinUser, User},
+    users::{MinUser, Role, User},
 };
 
 use super::error::UserError;
 
+#[derive(Deserialize)]
+struct StreamToken {
+    token: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Authorization {
     sub: String,
     company: String,
     exp: usize,
+    #[serde(default)]
+    iat: usize,
+    /// For clients to adapt their UI; routes check the stored role.
+    #[serde(default)]
+    role: Role,
 }
 
 impl Authorization {
-    pub fn get_token(user: MinUser) -> String {
+    pub fn get_token(user: MinUser, role: Role) -> String {
         dotenv().ok();
         let _secret = env::var("TOKEN_SECRET").unwrap();
 
-        let expiration = Utc::now()
-            .checked_add_months(Months::new(12))
-            .unwrap()
-            .timestamp();
+        let now = Utc::now();
+        let expiration = now.checked_add_months(Months::new(12)).unwrap().timestamp();
 
         let _claims = Self {
             sub: user.id.to_string(),
             company: "TweetBook".to_string(),
             exp: expiration as usize,
+            iat: now.timestamp() as usize,
+            role,
         };
 
         encode(
@@ -51,45 +65,113 @@ impl Authorization {
         let auth_token = headers.get(header::AUTHORIZATION);
 
         match auth_token {
-            Some(token) => {
-                let secret = env::var("TOKEN_SECRET").unwrap();
-
-                let decoded = decode::<Self>(
-                    token.to_str().unwrap(),
-                    &DecodingKey::from_secret(secret.as_ref()),
-                    &Validation::new(Algorithm::HS256),
-                );
-
-                match decoded {
-                    Ok(token_data) => {
-                        let data = req.app_data::<Data<Tweetbook>>().unwrap().to_owned();
-                        let user_res = User::get_user_by_query::<User>(
-                            data,
-                            doc! {
-                                "$match": {
-                                    "$expr": {
-                                        "$eq": ["$_id", {"$toObjectId": token_data.claims.sub}]
-                                    }
-                                }
-                            },
-                        )
-                        .await;
-
-                        match user_res {
-                            Ok(user) => {
-                                if user.len() > 0 {
-                                    Ok(user[0].id)
-                                } else {
-                                    Err(UserError::Unauthorised)
-                                }
+            Some(token) => match token.to_str() {
+                Ok(token) => Self::verify_token(&req, token).await,
+                Err(_) => Err(UserError::Unauthorised),
+            },
+            None => Err(UserError::Unauthorised),
+        }
+    }
+
+    /// Guard for privileged routes: like `verify_request`, but only lets
+    /// through users whose stored role is at least `role`, so a demotion
+    /// takes effect without waiting for their token to expire.
+    pub async fn verify_role(
+        req: HttpRequest,
+        role: Role,
+    ) -> Result<mongodb::bson::oid::ObjectId, UserError> {
+        let headers = req.headers();
+        let auth_token = headers.get(header::AUTHORIZATION);
+
+        let user = match auth_token {
+            Some(token) => match token.to_str() {
+                Ok(token) => Self::verify_user(&req, token).await,
+                Err(_) => Err(UserError::Unauthorised),
+            },
+            None => Err(UserError::Unauthorised),
+        };
+
+        match user {
+            Ok(user) if user.role >= role => Ok(user.id),
+            Ok(_) => Err(UserError::Forbidden),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Like `verify_request`, but also accepts the token as a `token` query
+    /// parameter, since browsers can't set headers on `EventSource` or
+    /// WebSocket connections.
+    pub async fn verify_stream_request(
+        req: HttpRequest,
+    ) -> Result<mongodb::bson::oid::ObjectId, UserError> {
+        if req.headers().contains_key(header::AUTHORIZATION) {
+            return Self::verify_request(req).await;
+        }
+
+        match Query::<StreamToken>::from_query(req.query_string()) {
+            Ok(query) => Self::verify_token(&req, &query.token).await,
+            Err(_) => Err(UserError::Unauthorised),
+        }
+    }
+
+    async fn verify_token(
+        req: &HttpRequest,
+        token: &str,
+    ) -> Result<mongodb::bson::oid::ObjectId, UserError> {
+        match Self::verify_user(req, token).await {
+            Ok(user) => Ok(user.id),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Decodes `token` and loads its user, rejecting suspended users and
+    /// tokens issued before the last password change.
+    async fn verify_user(req: &HttpRequest, token: &str) -> Result<User, UserError> {
+        let secret = env::var("TOKEN_SECRET").unwrap();
+
+        let decoded = decode::<Self>(
+            token,
+            &DecodingKey::from_secret(secret.as_ref()),
+            &Validation::new(Algorithm::HS256),
+        );
+
+        match decoded {
+            Ok(token_data) => {
+                let data = req.app_data::<Data<Tweetbook>>().unwrap().to_owned();
+                let user_res = User::get_user_by_query::<User>(
+                    data,
+                    doc! {
+                        "$match": {
+                            "$expr": {
+                                "$eq": ["$_id", {"$toObjectId": token_data.claims.sub}]
                             }
-                            Err(_) => Err(UserError::Unauthorised),
+                        }
+                    },
+                )
+                .await;
+
+                match user_res {
+                    Ok(mut user) => {
+                        if user.is_empty() {
+                            return Err(UserError::Unauthorised);
+                        }
+                        let user = user.remove(0);
+                        let revoked = user.password_changed_at.is_some_and(|changed| {
+                            changed.timestamp_millis() / 1000 > token_data.claims.iat as i64
+                        });
+
+                        if revoked {
+                            Err(UserError::Unauthorised)
+                        } else if user.suspended_at.is_some() {
+                            Err(UserError::AccountSuspended)
+                        } else {
+                            Ok(user)
                         }
                     }
                     Err(_) => Err(UserError::Unauthorised),
                 }
             }
-            None => Err(UserError::Unauthorised),
+            Err(_) => Err(UserError::Unauthorised),
         }
     }
 }
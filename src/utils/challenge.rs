@@ -0,0 +1,65 @@
+use std::{env, time::Duration};
+
+use awc::Client;
+use serde::Deserialize;
+
+use super::{config::env_or, error::UserError};
+
+#[derive(Deserialize)]
+struct SiteVerify {
+    success: bool,
+}
+
+fn var(key: &str) -> Option<String> {
+    env::var(key).ok().filter(|value| !value.trim().is_empty())
+}
+
+/// Whether `CHALLENGE_VERIFY_URL` points at a verifier. Without one, users
+/// are never asked to solve a challenge.
+pub fn is_enabled() -> bool {
+    var("CHALLENGE_VERIFY_URL").is_some()
+}
+
+/// The public key clients render the challenge widget with.
+pub fn site_key() -> Option<String> {
+    var("CHALLENGE_SITE_KEY")
+}
+
+/// Checks a solved challenge `token` with the provider. reCAPTCHA, hCaptcha
+/// and Turnstile all take the `secret` and `response` form fields and answer
+/// with `{"success": bool}`.
+pub async fn verify(token: &str) -> Result<bool, UserError> {
+    let url = match var("CHALLENGE_VERIFY_URL") {
+        Some(url) => url,
+        None => return Ok(true),
+    };
+    let secret = var("CHALLENGE_SECRET").unwrap_or_default();
+    let timeout = Duration::from_secs(env_or("CHALLENGE_TIMEOUT_SECONDS", 5));
+
+    let response = Client::builder()
+        .timeout(timeout)
+        .finish()
+        .post(url)
+        .send_form(&[("secret", secret.as_str()), ("response", token)])
+        .await;
+
+    match response {
+        Ok(mut response) if response.status().is_success() => {
+            match response.json::<SiteVerify>().await {
+                Ok(result) => Ok(result.success),
+                Err(error) => {
+                    println!("Failed to read challenge verification: {:?}", error);
+                    Err(UserError::InternalServerError)
+                }
+            }
+        }
+        Ok(response) => {
+            println!("Challenge verification failed: {}", response.status());
+            Err(UserError::InternalServerError)
+        }
+        Err(error) => {
+            println!("Failed to verify challenge: {:?}", error);
+            Err(UserError::InternalServerError)
+        }
+    }
+}
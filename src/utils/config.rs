@@ -0,0 +1,16 @@
+use std::{env, str::FromStr, time::Duration};
+
+/// Reads `key` from the environment, falling back to `default` when it is
+/// unset or can't be parsed.
+pub fn env_or<T: FromStr>(key: &str, default: T) -> T {
+    env::var(key)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+/// A job period of `key` seconds, at least one since a zero period would
+/// stop the job.
+pub fn env_interval(key: &str, default_seconds: u64) -> Duration {
+    Duration::from_secs(env_or(key, default_seconds).max(1))
+}
@@ -0,0 +1,172 @@
+/// A `#hashtag`, `@mention` or link found in tweet text. `start` and `end` are
+/// character (not byte) offsets of the whole token including its sigil,
+/// `end` being exclusive.
+#[derive(Debug, PartialEq)]
+pub struct Token {
+    pub value: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn extract(text: &str, sigil: char) -> Vec<Token> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+
+    while i < chars.len() {
+        if chars[i] == sigil && (i == 0 || !is_word(chars[i - 1])) {
+            let mut j = i + 1;
+            while j < chars.len() && is_word(chars[j]) {
+                j += 1;
+            }
+
+            if j > i + 1 {
+                tokens.push(Token {
+                    value: chars[i + 1..j].iter().collect(),
+                    start: i,
+                    end: j,
+                });
+            }
+            i = j;
+        } else {
+            i += 1;
+        }
+    }
+
+    tokens
+}
+
+/// Hashtags in `text`, lowercased. Purely numeric tags like `#1` are skipped.
+pub fn extract_hashtags(text: &str) -> Vec<Token> {
+    extract(text, '#')
+        .into_iter()
+        .filter(|token| !token.value.chars().all(|c| c.is_ascii_digit()))
+        .map(|token| Token {
+            value: token.value.to_lowercase(),
+            ..token
+        })
+        .collect()
+}
+
+/// `@username` mentions in `text`, as written.
+pub fn extract_mentions(text: &str) -> Vec<Token> {
+    extract(text, '@')
+}
+
+/// Characters that end a sentence rather than a link, e.g. the `.` in
+/// "see https://example.com."
+fn is_trailing_punctuation(c: char) -> bool {
+    matches!(
+        c,
+        '.' | ',' | '!' | '?' | ':' | ';' | ')' | ']' | '\'' | '"'
+    )
+}
+
+/// `http://` and `https://` links in `text`, up to the next whitespace and
+/// without trailing punctuation.
+pub fn extract_urls(text: &str) -> Vec<Token> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+
+    while i < chars.len() {
+        let rest: String = chars[i..chars.len().min(i + 8)].iter().collect();
+        let rest = rest.to_lowercase();
+        let scheme = if rest.starts_with("https://") {
+            8
+        } else if rest.starts_with("http://") {
+            7
+        } else {
+            0
+        };
+
+        if scheme > 0 && (i == 0 || !is_word(chars[i - 1])) {
+            let mut j = i + scheme;
+            while j < chars.len() && !chars[j].is_whitespace() {
+                j += 1;
+            }
+            while j > i + scheme && is_trailing_punctuation(chars[j - 1]) {
+                j -= 1;
+            }
+
+            if j > i + scheme {
+                tokens.push(Token {
+                    value: chars[i..j].iter().collect(),
+                    start: i,
+                    end: j,
+                });
+            }
+            i = j.max(i + 1);
+        } else {
+            i += 1;
+        }
+    }
+
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(tokens: &[Token]) -> Vec<&str> {
+        tokens.iter().map(|token| token.value.as_str()).collect()
+    }
+
+    #[test]
+    fn hashtags_are_lowercased_and_skip_numbers() {
+        let tags = extract_hashtags("#Rust and #til, not #1 or # alone");
+        assert_eq!(values(&tags), ["rust", "til"]);
+    }
+
+    #[test]
+    fn sigils_inside_words_are_ignored() {
+        assert!(extract_hashtags("issue#12 c#").is_empty());
+        assert!(extract_mentions("me@example.com").is_empty());
+        assert_eq!(
+            values(&extract_mentions("(@ferris) @a_b!")),
+            ["ferris", "a_b"]
+        );
+    }
+
+    #[test]
+    fn offsets_count_characters_not_bytes() {
+        let tags = extract_hashtags("héllo 🦀 #rust");
+        assert_eq!(
+            tags,
+            [Token {
+                value: "rust".to_string(),
+                start: 8,
+                end: 13,
+            }]
+        );
+
+        let mentions = extract_mentions("ça @crab");
+        assert_eq!((mentions[0].start, mentions[0].end), (3, 8));
+    }
+
+    #[test]
+    fn urls_drop_trailing_punctuation() {
+        let urls = extract_urls("see https://example.com/a?b=1. and (http://x.org/y), ok");
+        assert_eq!(
+            values(&urls),
+            ["https://example.com/a?b=1", "http://x.org/y"]
+        );
+        assert_eq!((urls[0].start, urls[0].end), (4, 29));
+    }
+
+    #[test]
+    fn urls_need_a_scheme_at_a_word_boundary() {
+        assert!(extract_urls("xhttps://example.com").is_empty());
+        assert!(extract_urls("https:// nothing").is_empty());
+        assert!(extract_urls("example.com").is_empty());
+        assert_eq!(
+            values(&extract_urls("HTTPS://Example.com")),
+            ["HTTPS://Example.com"]
+        );
+    }
+}
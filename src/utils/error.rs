@@ -17,6 +17,96 @@ pub enum UserError {
     InternalServerError,
     #[display(fmt = "Wrong Email or Password. PLease Try with the valid credentials.")]
     WrongEmailOrPassword,
+    #[display(fmt = "Tweet doesn't exist!")]
+    TweetNotExists,
+    #[display(fmt = "This account is private. Follow it to see its tweets.")]
+    PrivateAccount,
+    #[display(fmt = "Follow request doesn't exist!")]
+    FollowRequestNotExists,
+    #[display(fmt = "You can't interact with this user.")]
+    Blocked,
+    #[display(fmt = "You can't perform this action on yourself!")]
+    CannotTargetSelf,
+    #[display(fmt = "You can only modify your own tweets.")]
+    NotTweetOwner,
+    #[display(fmt = "This tweet can no longer be edited.")]
+    EditWindowExpired,
+    #[display(fmt = "This tweet can no longer be restored.")]
+    UndoWindowExpired,
+    #[display(fmt = "Invalid search query.")]
+    InvalidSearchQuery,
+    #[display(fmt = "Notification doesn't exist!")]
+    NotificationNotExists,
+    #[display(fmt = "Conversation doesn't exist!")]
+    ConversationNotExists,
+    #[display(fmt = "Message doesn't exist!")]
+    DirectMessageNotExists,
+    #[display(fmt = "Invalid conversation participants.")]
+    InvalidParticipants,
+    #[display(fmt = "Direct messages must be 1 to 1000 characters long.")]
+    InvalidDirectMessage,
+    #[display(fmt = "Unsupported or corrupt image.")]
+    InvalidMedia,
+    #[display(fmt = "This image is too large.")]
+    MediaTooLarge,
+    #[display(fmt = "Media doesn't exist!")]
+    MediaNotExists,
+    #[display(fmt = "Too many attachments.")]
+    TooManyAttachments,
+    #[display(fmt = "Polls need 2 to 4 options and must run between 5 minutes and 7 days.")]
+    InvalidPoll,
+    #[display(fmt = "This tweet has no poll.")]
+    PollNotExists,
+    #[display(fmt = "That option isn't in this poll.")]
+    InvalidPollOption,
+    #[display(fmt = "This poll has closed.")]
+    PollClosed,
+    #[display(fmt = "You already voted in this poll.")]
+    AlreadyVoted,
+    #[display(fmt = "Bookmark folder doesn't exist!")]
+    BookmarkFolderNotExists,
+    #[display(fmt = "Folder names must be 1 to 50 characters long.")]
+    InvalidFolderName,
+    #[display(fmt = "List doesn't exist!")]
+    ListNotExists,
+    #[display(fmt = "Only the list's owner can change it.")]
+    NotListOwner,
+    #[display(
+        fmt = "Lists need a 1 to 25 character name and at most 100 characters of description."
+    )]
+    InvalidList,
+    #[display(fmt = "This list is full.")]
+    ListFull,
+    #[display(fmt = "Draft doesn't exist!")]
+    DraftNotExists,
+    #[display(fmt = "Tweets can only be scheduled for a time in the future, up to a year ahead.")]
+    InvalidSchedule,
+    #[display(fmt = "This account has been suspended.")]
+    AccountSuspended,
+    #[display(fmt = "You don't have permission to do that.")]
+    Forbidden,
+    #[display(fmt = "Invalid report. Pick a reason and keep details under 500 characters.")]
+    InvalidReport,
+    #[display(fmt = "Report doesn't exist!")]
+    ReportNotExists,
+    #[display(fmt = "That action doesn't apply to this report.")]
+    InvalidModerationAction,
+    #[display(fmt = "Your password has to be reset. Sign in with a newPassword.")]
+    PasswordResetRequired,
+    #[display(fmt = "Your tweet contains language that isn't allowed.")]
+    BannedContent,
+    #[display(fmt = "Your tweet links to a site that isn't allowed.")]
+    BlockedLink,
+    #[display(fmt = "You already posted this recently.")]
+    DuplicateTweet,
+    #[display(fmt = "The new password must be at least 8 characters and differ from the old one.")]
+    InvalidPassword,
+    #[display(fmt = "You're doing that too often. Please wait a minute and try again.")]
+    RateLimited,
+    #[display(fmt = "Please complete the challenge to continue.")]
+    ChallengeRequired,
+    #[display(fmt = "The challenge wasn't solved. Please try again.")]
+    InvalidChallenge,
 }
 
 impl error::ResponseError for UserError {
@@ -33,6 +123,50 @@ impl error::ResponseError for UserError {
             UserError::InternalServerError => StatusCode::INTERNAL_SERVER_ERROR,
             UserError::WrongEmailOrPassword => StatusCode::BAD_REQUEST,
             UserError::Unauthorised => StatusCode::UNAUTHORIZED,
+            UserError::TweetNotExists => StatusCode::BAD_REQUEST,
+            UserError::PrivateAccount => StatusCode::FORBIDDEN,
+            UserError::FollowRequestNotExists => StatusCode::BAD_REQUEST,
+            UserError::Blocked => StatusCode::FORBIDDEN,
+            UserError::CannotTargetSelf => StatusCode::BAD_REQUEST,
+            UserError::NotTweetOwner => StatusCode::FORBIDDEN,
+            UserError::EditWindowExpired => StatusCode::BAD_REQUEST,
+            UserError::UndoWindowExpired => StatusCode::BAD_REQUEST,
+            UserError::InvalidSearchQuery => StatusCode::BAD_REQUEST,
+            UserError::NotificationNotExists => StatusCode::BAD_REQUEST,
+            UserError::ConversationNotExists => StatusCode::BAD_REQUEST,
+            UserError::DirectMessageNotExists => StatusCode::BAD_REQUEST,
+            UserError::InvalidParticipants => StatusCode::BAD_REQUEST,
+            UserError::InvalidDirectMessage => StatusCode::BAD_REQUEST,
+            UserError::InvalidMedia => StatusCode::BAD_REQUEST,
+            UserError::MediaTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            UserError::MediaNotExists => StatusCode::BAD_REQUEST,
+            UserError::TooManyAttachments => StatusCode::BAD_REQUEST,
+            UserError::InvalidPoll => StatusCode::BAD_REQUEST,
+            UserError::PollNotExists => StatusCode::BAD_REQUEST,
+            UserError::InvalidPollOption => StatusCode::BAD_REQUEST,
+            UserError::PollClosed => StatusCode::BAD_REQUEST,
+            UserError::AlreadyVoted => StatusCode::NOT_ACCEPTABLE,
+            UserError::BookmarkFolderNotExists => StatusCode::BAD_REQUEST,
+            UserError::InvalidFolderName => StatusCode::BAD_REQUEST,
+            UserError::ListNotExists => StatusCode::BAD_REQUEST,
+            UserError::NotListOwner => StatusCode::FORBIDDEN,
+            UserError::InvalidList => StatusCode::BAD_REQUEST,
+            UserError::ListFull => StatusCode::BAD_REQUEST,
+            UserError::DraftNotExists => StatusCode::BAD_REQUEST,
+            UserError::InvalidSchedule => StatusCode::BAD_REQUEST,
+            UserError::AccountSuspended => StatusCode::FORBIDDEN,
+            UserError::Forbidden => StatusCode::FORBIDDEN,
+            UserError::InvalidReport => StatusCode::BAD_REQUEST,
+            UserError::ReportNotExists => StatusCode::BAD_REQUEST,
+            UserError::InvalidModerationAction => StatusCode::BAD_REQUEST,
+            UserError::PasswordResetRequired => StatusCode::FORBIDDEN,
+            UserError::BannedContent => StatusCode::BAD_REQUEST,
+            UserError::BlockedLink => StatusCode::BAD_REQUEST,
+            UserError::DuplicateTweet => StatusCode::BAD_REQUEST,
+            UserError::InvalidPassword => StatusCode::BAD_REQUEST,
+            UserError::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            UserError::ChallengeRequired => StatusCode::PRECONDITION_REQUIRED,
+            UserError::InvalidChallenge => StatusCode::BAD_REQUEST,
         }
     }
 }
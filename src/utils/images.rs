@@ -0,0 +1,199 @@
+use std::io::Cursor;
+
+use image::{
+    codecs::{jpeg::JpegEncoder, png::PngEncoder},
+    metadata::Orientation,
+    DynamicImage, ImageDecoder, ImageFormat, ImageReader, Limits,
+};
+
+use super::{config::env_or, error::UserError};
+
+/// An upload decoded and re-encoded from its pixels only, so EXIF and any
+/// other embedded metadata is left behind.
+pub struct ProcessedImage {
+    pub bytes: Vec<u8>,
+    pub thumbnail: Vec<u8>,
+    pub content_type: &'static str,
+    pub extension: &'static str,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Largest accepted upload, `MEDIA_MAX_BYTES` (5 MiB by default).
+pub fn max_upload_bytes() -> usize {
+    env_or("MEDIA_MAX_BYTES", 5 * 1024 * 1024)
+}
+
+fn encode(image: &DynamicImage, transparent: bool) -> Result<Vec<u8>, UserError> {
+    let mut bytes = vec![];
+
+    let encoded = if transparent {
+        image.write_with_encoder(PngEncoder::new(&mut bytes))
+    } else {
+        DynamicImage::ImageRgb8(image.to_rgb8())
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut bytes, 85))
+    };
+
+    match encoded {
+        Ok(_) => Ok(bytes),
+        Err(_) => Err(UserError::InvalidMedia),
+    }
+}
+
+/// Sniffs the format from the bytes themselves (the client's content type
+/// is ignored), accepts JPEG, PNG, GIF and WebP within `MEDIA_MAX_DIMENSION`
+/// pixels a side, turns it upright per its EXIF orientation and re-encodes
+/// to JPEG, or PNG when there is transparency.
+/// A thumbnail at most `MEDIA_THUMBNAIL_SIZE` pixels a side is generated
+/// alongside.
+pub fn process(bytes: &[u8]) -> Result<ProcessedImage, UserError> {
+    let max_dimension = env_or("MEDIA_MAX_DIMENSION", 8192_u32);
+    let thumbnail_size = env_or("MEDIA_THUMBNAIL_SIZE", 400_u32);
+
+    let mut reader = match ImageReader::new(Cursor::new(bytes)).with_guessed_format() {
+        Ok(reader) => reader,
+        Err(_) => return Err(UserError::InvalidMedia),
+    };
+    match reader.format() {
+        Some(ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::Gif | ImageFormat::WebP) => {}
+        _ => return Err(UserError::InvalidMedia),
+    }
+
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(max_dimension);
+    limits.max_image_height = Some(max_dimension);
+    reader.limits(limits);
+
+    let mut decoder = match reader.into_decoder() {
+        Ok(decoder) => decoder,
+        Err(_) => return Err(UserError::InvalidMedia),
+    };
+    let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+    let mut image = match DynamicImage::from_decoder(decoder) {
+        Ok(image) => image,
+        Err(_) => return Err(UserError::InvalidMedia),
+    };
+    // The EXIF orientation is dropped along with the rest of the metadata,
+    // so it has to be baked into the pixels.
+    image.apply_orientation(orientation);
+
+    let transparent = image.color().has_alpha();
+    let (content_type, extension) = if transparent {
+        ("image/png", "png")
+    } else {
+        ("image/jpeg", "jpg")
+    };
+
+    Ok(ProcessedImage {
+        bytes: encode(&image, transparent)?,
+        thumbnail: encode(
+            &image.thumbnail(thumbnail_size, thumbnail_size),
+            transparent,
+        )?,
+        content_type,
+        extension,
+        width: image.width(),
+        height: image.height(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgb, RgbImage, Rgba, RgbaImage};
+
+    use super::*;
+
+    fn png(image: DynamicImage) -> Vec<u8> {
+        let mut bytes = vec![];
+        image
+            .write_with_encoder(PngEncoder::new(&mut bytes))
+            .unwrap();
+        bytes
+    }
+
+    /// A JPEG carrying an EXIF segment with the given orientation tag, the
+    /// way phone cameras write rotated shots.
+    fn jpeg_with_orientation(width: u32, height: u32, orientation: u16) -> Vec<u8> {
+        let mut encoded = vec![];
+        DynamicImage::ImageRgb8(RgbImage::from_pixel(width, height, Rgb([200, 10, 10])))
+            .write_with_encoder(JpegEncoder::new(&mut encoded))
+            .unwrap();
+
+        let mut exif = b"Exif\0\0MM\0\x2a\0\0\0\x08\0\x01\x01\x12\0\x03\0\0\0\x01".to_vec();
+        exif.extend_from_slice(&orientation.to_be_bytes());
+        exif.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+
+        let mut bytes = encoded[..2].to_vec();
+        bytes.extend_from_slice(&[0xFF, 0xE1]);
+        bytes.extend_from_slice(&(exif.len() as u16 + 2).to_be_bytes());
+        bytes.extend_from_slice(&exif);
+        bytes.extend_from_slice(&encoded[2..]);
+        bytes
+    }
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack
+            .windows(needle.len())
+            .any(|window| window == needle)
+    }
+
+    #[test]
+    fn sniffs_the_format_from_the_bytes() {
+        let processed = process(&png(DynamicImage::ImageRgb8(RgbImage::new(4, 4)))).unwrap();
+        assert_eq!(processed.content_type, "image/jpeg");
+        assert_eq!(processed.extension, "jpg");
+
+        assert!(process(b"definitely not an image").is_err());
+        assert!(process(b"BM\x3a\0\0\0\0\0\0\0\x36\0\0\0\x28\0\0\0").is_err());
+        assert!(process(&[]).is_err());
+    }
+
+    #[test]
+    fn rejects_oversized_dimensions() {
+        let image = DynamicImage::ImageRgb8(RgbImage::new(8193, 1));
+        assert!(process(&png(image)).is_err());
+
+        let image = DynamicImage::ImageRgb8(RgbImage::new(8192, 1));
+        assert!(process(&png(image)).is_ok());
+    }
+
+    #[test]
+    fn strips_metadata() {
+        let bytes = jpeg_with_orientation(8, 8, 1);
+        assert!(contains(&bytes, b"Exif"));
+
+        let processed = process(&bytes).unwrap();
+        assert!(!contains(&processed.bytes, b"Exif"));
+        assert!(!contains(&processed.thumbnail, b"Exif"));
+    }
+
+    #[test]
+    fn applies_the_exif_orientation() {
+        let processed = process(&jpeg_with_orientation(40, 20, 6)).unwrap();
+        assert_eq!((processed.width, processed.height), (20, 40));
+
+        let decoded = image::load_from_memory(&processed.bytes).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (20, 40));
+    }
+
+    #[test]
+    fn generates_a_bounded_thumbnail() {
+        let processed = process(&png(DynamicImage::ImageRgb8(RgbImage::new(1000, 500)))).unwrap();
+        assert_eq!((processed.width, processed.height), (1000, 500));
+
+        let thumbnail = image::load_from_memory(&processed.thumbnail).unwrap();
+        assert_eq!((thumbnail.width(), thumbnail.height()), (400, 200));
+    }
+
+    #[test]
+    fn keeps_transparency_as_png() {
+        let image = RgbaImage::from_pixel(4, 4, Rgba([0, 0, 0, 0]));
+        let processed = process(&png(DynamicImage::ImageRgba8(image))).unwrap();
+        assert_eq!(processed.content_type, "image/png");
+        assert_eq!(processed.extension, "png");
+        assert_eq!(
+            image::guess_format(&processed.bytes).unwrap(),
+            ImageFormat::Png
+        );
+    }
+}
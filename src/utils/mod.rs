@@ -1,2 +1,8 @@
 pub mod auth;
+pub mod challenge;
+pub mod config;
+pub mod entities;
 pub mod error;
+pub mod images;
+pub mod pagination;
+pub mod unfurl;
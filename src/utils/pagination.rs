@@ -0,0 +1,47 @@
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_LIMIT: u64 = 20;
+const MAX_LIMIT: u64 = 100;
+
+#[derive(Debug, Deserialize)]
+pub struct Pagination {
+    pub page: Option<u64>,
+    pub limit: Option<u64>,
+}
+
+impl Pagination {
+    pub fn page(&self) -> u64 {
+        self.page.unwrap_or(1).max(1)
+    }
+
+    pub fn limit(&self) -> u64 {
+        self.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)
+    }
+
+    /// Items before the requested page, capped so it still fits the `i64`
+    /// MongoDB takes for `$skip` however large `page` is.
+    pub fn skip(&self) -> u64 {
+        (self.page() - 1)
+            .saturating_mul(self.limit())
+            .min(i64::MAX as u64)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Paginated<T> {
+    pub items: Vec<T>,
+    pub page: u64,
+    pub limit: u64,
+    pub total: u64,
+}
+
+impl<T> Paginated<T> {
+    pub fn new(items: Vec<T>, total: u64, pagination: &Pagination) -> Self {
+        Paginated {
+            items,
+            page: pagination.page(),
+            limit: pagination.limit(),
+            total,
+        }
+    }
+}
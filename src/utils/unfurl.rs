@@ -0,0 +1,680 @@
+use std::{
+    error::Error as StdError,
+    net::{IpAddr, SocketAddr, ToSocketAddrs},
+    time::Duration,
+};
+
+use actix_tls::connect::{Connector as TcpConnector, Resolve, Resolver};
+use actix_web::{rt, web};
+use awc::{
+    http::{header, Uri},
+    Client, ClientResponse, Connector,
+};
+use futures::{future::LocalBoxFuture, StreamExt};
+
+use crate::models::cards::Card;
+
+use super::config::env_or;
+
+/// Redirect hops followed before giving up on a link.
+const MAX_REDIRECTS: usize = 3;
+
+/// Whether `ip` is somewhere on the public internet, as opposed to
+/// loopback, private, link-local, shared or otherwise reserved space.
+/// `UNFURL_ALLOW_PRIVATE=true` lifts this for local development.
+fn is_public(ip: IpAddr) -> bool {
+    env_or("UNFURL_ALLOW_PRIVATE", false) || is_public_address(ip)
+}
+
+fn is_public_address(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || a == 0
+                || a >= 240
+                || (a == 100 && (64..128).contains(&b))
+                || (a == 192 && b == 0 && c == 0)
+                || (a == 198 && (18..20).contains(&b)))
+        }
+        IpAddr::V6(ip) => {
+            if let Some(mapped) = ip.to_ipv4_mapped() {
+                return is_public_address(IpAddr::V4(mapped));
+            }
+
+            let [first, second, ..] = ip.segments();
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || (first & 0xfe00) == 0xfc00
+                || (first & 0xffc0) == 0xfe80
+                || (first == 0x64 && second == 0xff9b)
+                || (first == 0x2001 && second == 0xdb8))
+        }
+    }
+}
+
+/// Resolves hostnames to their public addresses only. Doing this inside the
+/// connector means the addresses checked are the ones actually dialled, so
+/// a name can't pass the check and then re-resolve somewhere private.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn lookup<'a>(
+        &'a self,
+        host: &'a str,
+        port: u16,
+    ) -> LocalBoxFuture<'a, Result<Vec<SocketAddr>, Box<dyn StdError>>> {
+        let host = format!("{}:{}", host, port);
+
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = web::block(move || host.to_socket_addrs())
+                .await??
+                .filter(|addr| is_public(addr.ip()))
+                .collect();
+
+            if addrs.is_empty() {
+                return Err("host has no public address".into());
+            }
+            Ok(addrs)
+        })
+    }
+}
+
+fn client(timeout: Duration) -> Client {
+    let connector = Connector::new()
+        .connector(TcpConnector::new(Resolver::custom(PublicResolver)).service())
+        .timeout(timeout);
+
+    Client::builder()
+        .connector(connector)
+        .timeout(timeout)
+        .disable_redirects()
+        .add_default_header((header::USER_AGENT, "TweetbookBot/1.0 (link previews)"))
+        .finish()
+}
+
+/// Parses `url` if it is an http(s) link whose host, when written as an IP
+/// address, is public. Hostnames are checked by [`PublicResolver`].
+fn checked(url: &str) -> Option<Uri> {
+    let uri: Uri = url.parse().ok()?;
+    match uri.scheme_str() {
+        Some("http") | Some("https") => {}
+        _ => return None,
+    }
+
+    let host = uri.host()?.trim_start_matches('[').trim_end_matches(']');
+    match host.parse::<IpAddr>() {
+        Ok(ip) if !is_public(ip) => None,
+        _ => Some(uri),
+    }
+}
+
+/// Resolves `link` as found on the page at `base` to an absolute URL.
+fn absolute(base: &Uri, link: &str) -> Option<String> {
+    let lower = link.to_ascii_lowercase();
+    if lower.starts_with("http://") || lower.starts_with("https://") {
+        return Some(link.to_string());
+    }
+
+    let scheme = base.scheme_str()?;
+    let authority = base.authority()?;
+    if link.starts_with("//") {
+        Some(format!("{}:{}", scheme, link))
+    } else if link.starts_with('/') {
+        Some(format!("{}://{}{}", scheme, authority, link))
+    } else {
+        let path = base.path();
+        let dir = &path[..=path.rfind('/').unwrap_or(0)];
+        Some(format!("{}://{}{}{}", scheme, authority, dir, link))
+    }
+}
+
+/// Reads at most `limit` bytes of the body. Metadata lives in the `<head>`,
+/// so a page cut short is still usable.
+async fn read_capped<S>(response: &mut ClientResponse<S>, limit: usize) -> Vec<u8>
+where
+    S: futures::Stream<Item = Result<web::Bytes, awc::error::PayloadError>> + Unpin,
+{
+    let mut body = vec![];
+
+    while let Some(Ok(chunk)) = response.next().await {
+        let room = limit - body.len();
+        body.extend_from_slice(&chunk[..chunk.len().min(room)]);
+        if body.len() >= limit {
+            break;
+        }
+    }
+
+    body
+}
+
+/// Fetches `url` and builds a preview card from its Open Graph or Twitter
+/// card metadata. Only public addresses are contacted, every hop gets
+/// `UNFURL_TIMEOUT_SECONDS` and at most `UNFURL_MAX_BYTES` of HTML is read.
+/// Anything unexpected just means no card.
+pub async fn fetch(url: &str) -> Option<Card> {
+    let timeout = Duration::from_secs(env_or("UNFURL_TIMEOUT_SECONDS", 5));
+    let max_bytes = env_or("UNFURL_MAX_BYTES", 512 * 1024_usize);
+    let client = client(timeout);
+    let mut current = url.to_string();
+
+    for _ in 0..=MAX_REDIRECTS {
+        let uri = checked(&current)?;
+        let mut response = client
+            .get(uri.clone())
+            .insert_header((header::ACCEPT, "text/html"))
+            .send()
+            .await
+            .ok()?;
+
+        if response.status().is_redirection() {
+            let location = response.headers().get(header::LOCATION)?.to_str().ok()?;
+            current = absolute(&uri, location)?;
+            continue;
+        }
+
+        let is_html = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.to_ascii_lowercase().contains("text/html"));
+        if !response.status().is_success() || !is_html {
+            return None;
+        }
+
+        let body = rt::time::timeout(timeout, read_capped(&mut response, max_bytes))
+            .await
+            .ok()?;
+
+        return parse(&String::from_utf8_lossy(&body), &uri, url);
+    }
+
+    None
+}
+
+/// Decodes the handful of character references that show up in titles and
+/// descriptions.
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let entity = rest
+            .find(';')
+            .filter(|end| *end <= 10)
+            .map(|end| &rest[1..end]);
+        let character = entity.and_then(|entity| match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ => entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                .and_then(char::from_u32),
+        });
+
+        match (entity, character) {
+            (Some(entity), Some(character)) => {
+                decoded.push(character);
+                rest = &rest[entity.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+
+    decoded
+}
+
+/// Decoded, whitespace-collapsed and cut to `max` characters.
+fn clean(text: &str, max: usize) -> Option<String> {
+    let text = decode_entities(text)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    if text.is_empty() {
+        None
+    } else if text.chars().count() > max {
+        Some(text.chars().take(max - 1).collect::<String>() + "…")
+    } else {
+        Some(text)
+    }
+}
+
+/// Attributes of a single tag, names lowercased.
+fn attributes(tag: &str) -> Vec<(String, String)> {
+    let chars: Vec<char> = tag.chars().collect();
+    let mut attributes = vec![];
+    let mut i = 0;
+
+    while i < chars.len() {
+        while i < chars.len() && (chars[i].is_whitespace() || chars[i] == '/') {
+            i += 1;
+        }
+        let name_start = i;
+        while i < chars.len() && !chars[i].is_whitespace() && !matches!(chars[i], '=' | '/') {
+            i += 1;
+        }
+        let name: String = chars[name_start..i].iter().collect();
+
+        while i < chars.len() && chars[i].is_whitespace() {
+            i += 1;
+        }
+        let mut value = String::new();
+        if i < chars.len() && chars[i] == '=' {
+            i += 1;
+            while i < chars.len() && chars[i].is_whitespace() {
+                i += 1;
+            }
+            if i < chars.len() && matches!(chars[i], '"' | '\'') {
+                let quote = chars[i];
+                i += 1;
+                while i < chars.len() && chars[i] != quote {
+                    value.push(chars[i]);
+                    i += 1;
+                }
+                i += 1;
+            } else {
+                while i < chars.len() && !chars[i].is_whitespace() {
+                    value.push(chars[i]);
+                    i += 1;
+                }
+            }
+        }
+
+        if !name.is_empty() {
+            attributes.push((name.to_ascii_lowercase(), value));
+        } else {
+            i += 1;
+        }
+    }
+
+    attributes
+}
+
+/// Builds a card from the `<meta>` tags and `<title>` in the page's head,
+/// preferring Open Graph over Twitter card tags over plain HTML. Pages
+/// without any title get no card.
+fn parse(html: &str, page: &Uri, url: &str) -> Option<Card> {
+    // ASCII lowercasing keeps byte offsets valid for slicing `html`.
+    let lower = html.to_ascii_lowercase();
+    let head_end = lower.find("</head").unwrap_or(lower.len());
+    let mut meta: Vec<(String, String)> = vec![];
+
+    let mut offset = 0;
+    while let Some(start) = lower[offset..head_end].find("<meta") {
+        let start = offset + start + "<meta".len();
+        let end = match lower[start..head_end].find('>') {
+            Some(end) => start + end,
+            None => break,
+        };
+
+        let attributes = attributes(&html[start..end]);
+        let key = attributes
+            .iter()
+            .find(|(name, _)| name == "property" || name == "name")
+            .map(|(_, value)| value.to_ascii_lowercase());
+        let content = attributes
+            .iter()
+            .find(|(name, _)| name == "content")
+            .map(|(_, value)| value.clone());
+        if let (Some(key), Some(content)) = (key, content) {
+            meta.push((key, content));
+        }
+
+        offset = end;
+    }
+
+    let first = |keys: &[&str]| {
+        keys.iter().find_map(|key| {
+            meta.iter()
+                .find(|(name, content)| name == key && !content.trim().is_empty())
+                .map(|(_, content)| content.as_str())
+        })
+    };
+
+    let title_tag = lower[..head_end].find("<title").and_then(|start| {
+        let start = start + lower[start..].find('>')? + 1;
+        let end = start + lower[start..].find("</title")?;
+        Some(&html[start..end])
+    });
+
+    Some(Card {
+        url: url.to_string(),
+        title: clean(first(&["og:title", "twitter:title"]).or(title_tag)?, 200)?,
+        description: first(&["og:description", "twitter:description", "description"])
+            .and_then(|description| clean(description, 300)),
+        image: first(&[
+            "og:image",
+            "og:image:url",
+            "twitter:image",
+            "twitter:image:src",
+        ])
+        .and_then(|image| absolute(page, &decode_entities(image.trim()))),
+        site_name: first(&["og:site_name"]).and_then(|name| clean(name, 100)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{Ipv4Addr, Ipv6Addr},
+        sync::Once,
+        time::Instant,
+    };
+
+    use actix_web::{dev::ServerHandle, App, HttpResponse, HttpServer};
+
+    use super::*;
+
+    fn page() -> Uri {
+        "https://example.com/articles/post.html".parse().unwrap()
+    }
+
+    #[test]
+    fn rejects_reserved_addresses() {
+        for ip in [
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "127.0.0.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "255.255.255.255",
+            "198.18.0.1",
+            "::1",
+            "::",
+            "fc00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "::ffff:10.0.0.1",
+            "64:ff9b::a00:1",
+        ] {
+            assert!(!is_public_address(ip.parse().unwrap()), "{}", ip);
+        }
+
+        assert!(is_public_address(IpAddr::V4(Ipv4Addr::new(
+            93, 184, 216, 34
+        ))));
+        assert!(is_public_address(IpAddr::V6(Ipv6Addr::new(
+            0x2606, 0x2800, 0x220, 0x1, 0, 0, 0, 0x1
+        ))));
+        assert!(is_public_address("::ffff:93.184.216.34".parse().unwrap()));
+    }
+
+    #[test]
+    fn checks_schemes_and_literal_hosts() {
+        assert!(checked("ftp://example.com/file").is_none());
+        assert!(checked("not a url").is_none());
+        assert!(checked("https://example.com/").is_some());
+        assert!(checked("http://93.184.216.34/").is_some());
+    }
+
+    #[test]
+    fn resolves_links_against_the_page() {
+        let page = page();
+        assert_eq!(
+            absolute(&page, "HTTP://other.com/a.png").as_deref(),
+            Some("HTTP://other.com/a.png")
+        );
+        assert_eq!(
+            absolute(&page, "//cdn.example.com/a.png").as_deref(),
+            Some("https://cdn.example.com/a.png")
+        );
+        assert_eq!(
+            absolute(&page, "/images/a.png").as_deref(),
+            Some("https://example.com/images/a.png")
+        );
+        assert_eq!(
+            absolute(&page, "a.png").as_deref(),
+            Some("https://example.com/articles/a.png")
+        );
+    }
+
+    #[test]
+    fn decodes_entities() {
+        assert_eq!(
+            decode_entities("Tom &amp; Jerry &lt;3 &quot;hi&quot; &apos;x&apos;"),
+            "Tom & Jerry <3 \"hi\" 'x'"
+        );
+        assert_eq!(decode_entities("&#65;&#x42;&#X43;"), "ABC");
+        assert_eq!(decode_entities("fish & chips"), "fish & chips");
+        assert_eq!(decode_entities("&unknown; &#xZZ; &"), "&unknown; &#xZZ; &");
+        assert_eq!(
+            decode_entities("&averyveryverylongname;"),
+            "&averyveryverylongname;"
+        );
+    }
+
+    #[test]
+    fn reads_tag_attributes() {
+        assert_eq!(
+            attributes(r#" PROPERTY="og:title" content='Say "hi"' data-x=bare /"#),
+            [
+                ("property".to_string(), "og:title".to_string()),
+                ("content".to_string(), r#"Say "hi""#.to_string()),
+                ("data-x".to_string(), "bare".to_string()),
+            ]
+        );
+        assert_eq!(
+            attributes(r#"name = "description" content="a > b""#),
+            [
+                ("name".to_string(), "description".to_string()),
+                ("content".to_string(), "a > b".to_string()),
+            ]
+        );
+        assert_eq!(attributes("async"), [("async".to_string(), String::new())]);
+    }
+
+    #[test]
+    fn prefers_open_graph_metadata() {
+        let html = r#"<html><head>
+            <title>Plain title</title>
+            <meta name="twitter:title" content="Twitter title">
+            <meta property="og:title" content="OG &amp; title">
+            <meta name="description" content="Plain description">
+            <meta property="og:description" content="  OG
+                description  ">
+            <meta property="og:image" content="/cover.png?a=1&amp;b=2">
+            <meta property="og:site_name" content="Example">
+        </head><body><meta property="og:title" content="Body"></body></html>"#;
+
+        let card = parse(html, &page(), "https://example.com/x").unwrap();
+        assert_eq!(card.url, "https://example.com/x");
+        assert_eq!(card.title, "OG & title");
+        assert_eq!(card.description.as_deref(), Some("OG description"));
+        assert_eq!(
+            card.image.as_deref(),
+            Some("https://example.com/cover.png?a=1&b=2")
+        );
+        assert_eq!(card.site_name.as_deref(), Some("Example"));
+    }
+
+    #[test]
+    fn falls_back_to_twitter_cards_then_the_title() {
+        let html = r#"<head><TITLE>Plain</TITLE>
+            <meta name="twitter:title" content="Twitter title">
+            <meta property="og:title" content="   ">
+            <meta name="twitter:image:src" content="https://img.example.com/t.png">
+            <meta name="description" content="Plain description"></head>"#;
+
+        let card = parse(html, &page(), "https://example.com/").unwrap();
+        assert_eq!(card.title, "Twitter title");
+        assert_eq!(card.description.as_deref(), Some("Plain description"));
+        assert_eq!(card.image.as_deref(), Some("https://img.example.com/t.png"));
+        assert_eq!(card.site_name, None);
+
+        let card = parse("<title> Just a\n title </title>", &page(), "u").unwrap();
+        assert_eq!(card.title, "Just a title");
+        assert_eq!(card.description, None);
+
+        assert!(parse(
+            "<head><meta name=description content=x></head>",
+            &page(),
+            "u"
+        )
+        .is_none());
+        assert!(parse("<head></head><title>Too late</title>", &page(), "u").is_none());
+    }
+
+    #[test]
+    fn truncates_long_titles() {
+        let html = format!("<title>{}</title>", "a".repeat(250));
+        let card = parse(&html, &page(), "u").unwrap();
+        assert_eq!(card.title.chars().count(), 200);
+        assert!(card.title.ends_with('…'));
+    }
+
+    const ARTICLE: &str = r#"<html><head>
+        <meta property="og:title" content="Local article">
+        <meta property="og:image" content="/cover.png">
+    </head><body>Hello</body></html>"#;
+
+    fn html(body: impl Into<String>) -> HttpResponse {
+        HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .body(body.into())
+    }
+
+    async fn redirect(hops: web::Path<usize>) -> HttpResponse {
+        match hops.into_inner() {
+            0 => html(ARTICLE),
+            hops => HttpResponse::Found()
+                .insert_header((header::LOCATION, format!("/redirect/{}", hops - 1)))
+                .finish(),
+        }
+    }
+
+    /// Serves stand-in pages on a loopback port, which the fetcher is
+    /// allowed to reach through `UNFURL_ALLOW_PRIVATE`.
+    fn serve() -> (String, ServerHandle) {
+        static ENV: Once = Once::new();
+        ENV.call_once(|| {
+            std::env::set_var("UNFURL_ALLOW_PRIVATE", "true");
+            std::env::set_var("UNFURL_TIMEOUT_SECONDS", "1");
+            std::env::set_var("UNFURL_MAX_BYTES", "4096");
+        });
+
+        let server = HttpServer::new(|| {
+            App::new()
+                .route("/article", web::get().to(|| async { html(ARTICLE) }))
+                .route("/redirect/{hops}", web::get().to(redirect))
+                .route(
+                    "/json",
+                    web::get().to(|| async {
+                        HttpResponse::Ok()
+                            .content_type("application/json")
+                            .body(r#"{"html": "<title>Nope</title>"}"#)
+                    }),
+                )
+                .route(
+                    "/missing",
+                    web::get().to(|| async {
+                        HttpResponse::NotFound()
+                            .content_type("text/html")
+                            .body("<title>Not found</title>")
+                    }),
+                )
+                .route(
+                    "/large",
+                    web::get()
+                        .to(|| async { html(format!("{}{}", ARTICLE, "x".repeat(1024 * 1024))) }),
+                )
+                .route(
+                    "/buried",
+                    web::get().to(|| async {
+                        html(format!("<head><!--{}-->{}", "x".repeat(8192), ARTICLE))
+                    }),
+                )
+                .route(
+                    "/slow",
+                    web::get().to(|| async {
+                        rt::time::sleep(Duration::from_secs(3)).await;
+                        html(ARTICLE)
+                    }),
+                )
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+
+        let base = format!("http://{}", server.addrs()[0]);
+        let server = server.run();
+        let handle = server.handle();
+        rt::spawn(server);
+        (base, handle)
+    }
+
+    #[actix_web::test]
+    async fn fetches_cards_from_html_pages() {
+        let (base, server) = serve();
+
+        let url = format!("{}/article", base);
+        let card = fetch(&url).await.unwrap();
+        assert_eq!(card.url, url);
+        assert_eq!(card.title, "Local article");
+        assert_eq!(card.image, Some(format!("{}/cover.png", base)));
+
+        assert!(fetch(&format!("{}/json", base)).await.is_none());
+        assert!(fetch(&format!("{}/missing", base)).await.is_none());
+
+        server.stop(false).await;
+    }
+
+    #[actix_web::test]
+    async fn follows_a_limited_number_of_redirects() {
+        let (base, server) = serve();
+
+        let url = format!("{}/redirect/{}", base, MAX_REDIRECTS);
+        let card = fetch(&url).await.unwrap();
+        assert_eq!(card.url, url);
+        assert_eq!(card.image, Some(format!("{}/cover.png", base)));
+
+        let url = format!("{}/redirect/{}", base, MAX_REDIRECTS + 1);
+        assert!(fetch(&url).await.is_none());
+
+        server.stop(false).await;
+    }
+
+    #[actix_web::test]
+    async fn reads_only_the_first_bytes_of_a_page() {
+        let (base, server) = serve();
+
+        let card = fetch(&format!("{}/large", base)).await.unwrap();
+        assert_eq!(card.title, "Local article");
+        assert!(fetch(&format!("{}/buried", base)).await.is_none());
+
+        server.stop(false).await;
+    }
+
+    #[actix_web::test]
+    async fn gives_up_on_slow_pages() {
+        let (base, server) = serve();
+
+        let started = Instant::now();
+        assert!(fetch(&format!("{}/slow", base)).await.is_none());
+        assert!(started.elapsed() < Duration::from_secs(3));
+
+        server.stop(false).await;
+    }
+}